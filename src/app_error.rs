@@ -0,0 +1,153 @@
+use std::io;
+use std::process::ExitCode;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Errors ending a run, each one mapped to its own exit code
+#[derive(Debug, Error)]
+pub(crate) enum AppError {
+    #[error("Invalid arguments: {0}")]
+    InvalidArguments(String),
+    #[error("Input not found: {0}")]
+    InputNotFound(String),
+    #[error("Column not found: {0}")]
+    ColumnNotFound(String),
+    #[error("Column {pattern:?} matches several headers: {matches}")]
+    AmbiguousColumn { pattern: String, matches: String },
+    #[error("Record at line {line} has {bytes} bytes, more than --max-record-bytes")]
+    OversizedRecord { line: u64, bytes: usize },
+    #[error("Categories {first} and {second} both map to the file name {file_name}")]
+    CategoryNameCollision {
+        first: String,
+        second: String,
+        file_name: String,
+    },
+    #[error("Categories {first} and {second} both map to the sheet name {sheet}")]
+    SheetNameCollision {
+        first: String,
+        second: String,
+        sheet: String,
+    },
+    #[error("{sheets} categories are more than the {max_sheets} sheets of a --xlsx workbook")]
+    TooManySheets { sheets: usize, max_sheets: usize },
+    #[error("The headers of {file} differ from {first_file}: {difference}")]
+    SchemaDrift {
+        file: String,
+        first_file: String,
+        difference: String,
+    },
+    #[error("The headers of {file} don't match --expect-headers: {difference}")]
+    UnexpectedHeaders { file: String, difference: String },
+    #[error("Headers {first:?} and {second:?} are both written as {header:?}")]
+    HeaderCollision {
+        first: String,
+        second: String,
+        header: String,
+    },
+    #[error("Category {0:?} isn't listed in --categories-file")]
+    UnexpectedCategory(String),
+    #[error("Category {0:?} is new after --freeze-categories-after")]
+    FrozenCategory(String),
+    #[error("Invalid category name {0:?}, it would escape the output directory")]
+    InvalidCategory(String),
+    #[error("Category {category:?} doesn't match --category-validate {pattern}")]
+    CategoryMismatch { category: String, pattern: String },
+    #[error(
+        "Stopped after {errors} skipped records, more than --max-errors {max_errors}, {rows} records were split before"
+    )]
+    TooManyErrors {
+        errors: usize,
+        max_errors: usize,
+        rows: usize,
+    },
+    #[error("Validation failed, {invalid} of {records} records are invalid")]
+    ValidationFailed { invalid: usize, records: usize },
+    #[error("Stopped after reaching --max-runtime of {0:?}")]
+    MaxRuntimeExceeded(Duration),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl From<csv::Error> for AppError {
+    fn from(error: csv::Error) -> Self {
+        AppError::Io(error.into())
+    }
+}
+
+impl AppError {
+    /// Exit code of the process, 2 is also what clap uses for bad command line arguments
+    pub(crate) fn exit_code(&self) -> u8 {
+        match self {
+            AppError::InvalidArguments(_) => 2,
+            AppError::InputNotFound(_) => 3,
+            AppError::ColumnNotFound(_)
+            | AppError::AmbiguousColumn { .. }
+            | AppError::SchemaDrift { .. }
+            | AppError::UnexpectedHeaders { .. }
+            | AppError::HeaderCollision { .. }
+            | AppError::UnexpectedCategory(_)
+            | AppError::FrozenCategory(_) => 4,
+            AppError::OversizedRecord { .. }
+            | AppError::CategoryNameCollision { .. }
+            | AppError::SheetNameCollision { .. }
+            | AppError::TooManySheets { .. }
+            | AppError::InvalidCategory(_)
+            | AppError::CategoryMismatch { .. }
+            | AppError::TooManyErrors { .. }
+            | AppError::Io(_) => 5,
+            AppError::ValidationFailed { .. } => 6,
+            AppError::MaxRuntimeExceeded(_) => 7,
+        }
+    }
+}
+
+impl From<AppError> for ExitCode {
+    fn from(error: AppError) -> Self {
+        ExitCode::from(error.exit_code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let io_error = io::Error::other("disk full");
+
+        assert_eq!(AppError::InvalidArguments(String::new()).exit_code(), 2);
+        assert_eq!(AppError::InputNotFound(String::new()).exit_code(), 3);
+        assert_eq!(AppError::ColumnNotFound(String::new()).exit_code(), 4);
+        assert_eq!(
+            AppError::SchemaDrift {
+                file: String::new(),
+                first_file: String::new(),
+                difference: String::new(),
+            }
+            .exit_code(),
+            4
+        );
+        assert_eq!(
+            AppError::UnexpectedHeaders {
+                file: String::new(),
+                difference: String::new(),
+            }
+            .exit_code(),
+            4
+        );
+        assert_eq!(AppError::FrozenCategory(String::new()).exit_code(), 4);
+        assert_eq!(AppError::from(io_error).exit_code(), 5);
+        assert_eq!(
+            AppError::ValidationFailed {
+                invalid: 1,
+                records: 2,
+            }
+            .exit_code(),
+            6
+        );
+        assert_eq!(
+            AppError::MaxRuntimeExceeded(Duration::from_secs(1)).exit_code(),
+            7
+        );
+    }
+}
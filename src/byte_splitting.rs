@@ -0,0 +1,256 @@
+use crate::app_error::AppError;
+use crate::data_filtering::{
+    assign_category, check_expected_headers, check_schema_drift, create_category_writer,
+    create_output_dir, is_skipped_category, is_valid_category, prepare_context, read_chunks,
+    record_manifest, write_success_marker,
+};
+use crate::data_loading::read_file;
+use crate::delimiter::Delimiter;
+use crate::output_backend::OutputWriter;
+use crate::record_context::{InvalidCategoryPolicy, RecordProcessingContext};
+use crate::split_summary::SplitSummary;
+use csv::{ByteRecord, Reader, StringRecord, Writer};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::time::Instant;
+
+use rayon::prelude::*;
+use tracing::{event, Level};
+
+/// Records of each category keyed by the raw bytes of the split column
+type ByteCategories = HashMap<Vec<u8>, Vec<ByteRecord>>;
+
+/// Open category writers by raw category, with the file name of the category
+#[derive(Default)]
+struct ByteWriters {
+    writers: HashMap<Vec<u8>, (String, Writer<OutputWriter>)>,
+    /// Raw category owning each file name, two of them may decode to the same name
+    file_categories: HashMap<String, Vec<u8>>,
+}
+
+/// Split a CSV file like `split_file_by_category` without validating the records as
+/// UTF-8, for `--bytes`. Only the headers are decoded, the categories are keyed by their
+/// raw bytes and named after their lossy UTF-8 decoding
+pub(crate) fn split_file_by_bytes(
+    path: &Path,
+    input_column: &str,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let mut reader: Reader<Box<dyn Read>> =
+        read_file(path, &base_context.reader_config(delimiter))?;
+    let headers: StringRecord = reader.headers()?.clone();
+    check_expected_headers(path, &headers, base_context)?;
+    check_schema_drift(path, &headers, base_context)?;
+    let context: RecordProcessingContext =
+        prepare_context(path, &headers, input_column, delimiter, base_context)?;
+    create_output_dir(&context)?;
+
+    let mut writers: ByteWriters = ByteWriters::default();
+    let summary: SplitSummary = read_chunks(&mut reader, &context, |chunk: &Vec<ByteRecord>| {
+        process_chunk(chunk, &mut writers, &context)
+    })?;
+
+    for (_, writer) in writers.writers.values_mut() {
+        writer.flush()?;
+    }
+    record_manifest(path, &summary, base_context)?;
+    if !summary.timed_out {
+        write_success_marker(&context)?;
+    }
+    Ok(summary)
+}
+
+/// Categorize a chunk of records and write them to the files of their categories
+fn process_chunk(
+    chunk: &[ByteRecord],
+    writers: &mut ByteWriters,
+    context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let mut summary: SplitSummary = SplitSummary::default();
+    let filter_started_at: Instant = Instant::now();
+    let categories: ByteCategories = categorize_chunk(chunk, context);
+    summary.filter_time += filter_started_at.elapsed();
+    write_categories(categories, writers, context, &mut summary)?;
+    Ok(summary)
+}
+
+/// Group the written fields of the records by the raw bytes of their split column,
+/// keeping the input order within each category
+fn categorize_chunk(chunk: &[ByteRecord], context: &RecordProcessingContext) -> ByteCategories {
+    chunk
+        .par_iter()
+        .fold_with(HashMap::new(), |mut acc: ByteCategories, record| {
+            let fields: ByteRecord = context
+                .header_indexes
+                .iter()
+                .filter_map(|&idx| record.get(idx))
+                .collect();
+            acc.entry(byte_category(record, context))
+                .or_default()
+                .push(fields);
+            acc
+        })
+        .reduce(HashMap::new, |mut acc, map| {
+            for (key, mut value) in map {
+                acc.entry(key).or_default().append(&mut value);
+            }
+            acc
+        })
+}
+
+/// Raw bytes of the split column, the null category when empty, missing or one of the
+/// `--null-values`
+fn byte_category(record: &ByteRecord, context: &RecordProcessingContext) -> Vec<u8> {
+    match context
+        .split_column_idx
+        .and_then(|split_column_idx| record.get(split_column_idx))
+    {
+        Some(category)
+            if !category.is_empty()
+                && !context
+                    .null_values
+                    .iter()
+                    .any(|null| null.as_bytes() == category) =>
+        {
+            category.to_vec()
+        }
+        _ => assign_category(&context.null_category, context).into_bytes(),
+    }
+}
+
+/// Write the records of each category, opening its file on the first record
+fn write_categories(
+    categories: ByteCategories,
+    writers: &mut ByteWriters,
+    context: &RecordProcessingContext,
+    summary: &mut SplitSummary,
+) -> Result<(), AppError> {
+    let started_at: Instant = Instant::now();
+    for (category, records) in categories {
+        if is_skipped_category(&category, context) {
+            summary.null_category_records_skipped += records.len();
+            continue;
+        }
+        let (file_category, writer): &mut (String, Writer<OutputWriter>) =
+            match writers.writers.entry(category) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let file_category: String = String::from_utf8_lossy(entry.key()).into_owned();
+                    if context.invalid_category_policy == InvalidCategoryPolicy::Skip
+                        && !is_valid_category(&file_category, context)
+                    {
+                        event!(
+                            Level::WARN,
+                            "Skipping {} records of the invalid category {:?}",
+                            records.len(),
+                            file_category
+                        );
+                        summary.invalid_category_records_skipped += records.len();
+                        continue;
+                    }
+                    match writers.file_categories.entry(file_category.clone()) {
+                        Entry::Occupied(first) => {
+                            return Err(AppError::CategoryNameCollision {
+                                first: format!("{:?}", first.get()),
+                                second: format!("{:?}", entry.key()),
+                                file_name: file_category,
+                            });
+                        }
+                        Entry::Vacant(first) => {
+                            first.insert(entry.key().clone());
+                        }
+                    }
+                    let writer: Writer<OutputWriter> =
+                        create_category_writer(&file_category, context, summary)?;
+                    entry.insert((file_category, writer))
+                }
+            };
+        for record in &records {
+            writer.write_byte_record(record)?;
+        }
+        writer.flush()?;
+        if let Some((_, rows)) = context.category_rows.lock().unwrap().get_mut(file_category) {
+            *rows += records.len();
+        }
+        *summary
+            .category_rows
+            .entry(file_category.clone())
+            .or_default() += records.len();
+    }
+    summary.write_time += started_at.elapsed();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_filtering::split_file_by_category;
+    use crate::test_context::TestContext;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_split_file_by_bytes() {
+        let mut test_context = TestContext::new();
+        let string_dir = test_context.add_dir(PathBuf::from("assets/tmp/bytes_string"));
+        let bytes_dir = test_context.add_dir(PathBuf::from("assets/tmp/bytes_raw"));
+        let split = |output_dir: &PathBuf, bytes: bool| {
+            let context = RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                chunk_size: 2,
+                null_values: vec![
+                    String::from("NA"),
+                    String::from("N/A"),
+                    String::from("NULL"),
+                ],
+                ..Default::default()
+            };
+            let path = Path::new("assets/null_city.csv");
+            let mut summary = if bytes {
+                split_file_by_bytes(path, "State", &Delimiter::Comma, &context)
+            } else {
+                split_file_by_category(path, "State", &Delimiter::Comma, &context)
+            }
+            .unwrap();
+            summary.created.sort();
+            let files: Vec<(String, Vec<u8>)> = summary
+                .created
+                .iter()
+                .map(|file| {
+                    let file_path = Path::new(file);
+                    let name = file_path.file_name().unwrap().to_string_lossy();
+                    (name.into_owned(), fs::read(file_path).unwrap())
+                })
+                .collect();
+            let context_rows: HashMap<String, usize> = context
+                .category_rows
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(category, (_, rows))| (category.clone(), *rows))
+                .collect();
+            (
+                files,
+                summary.rows_processed,
+                summary.category_rows,
+                context_rows,
+            )
+        };
+
+        let (string_files, string_rows, string_category_rows, string_context_rows) =
+            split(&string_dir, false);
+        let (byte_files, byte_rows, byte_category_rows, byte_context_rows) =
+            split(&bytes_dir, true);
+
+        assert!(string_files.iter().any(|(name, _)| name == "unknown.csv"));
+        assert_eq!(string_files, byte_files);
+        assert!(byte_rows > 0);
+        assert_eq!(string_rows, byte_rows);
+        assert_eq!(string_category_rows, byte_category_rows);
+        assert_eq!(string_context_rows, byte_context_rows);
+        assert_eq!(byte_category_rows.values().sum::<usize>(), byte_rows);
+    }
+}
@@ -0,0 +1,326 @@
+use crate::app_error::AppError;
+use crate::data_filtering::{
+    check_field_count, get_category, input_records, is_skipped_category, matches_filters,
+    prepare_context,
+};
+use crate::data_loading::read_file;
+use crate::delimiter::Delimiter;
+use crate::record_context::RecordProcessingContext;
+use csv::{Reader, StringRecord};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Print every record of the input whose category is `category` with its line number,
+/// flagging those dropped by `--filter`, then the number of matches. Only the `--rows`
+/// range is read when given, and nothing is written to the output directory
+pub(crate) fn inspect_category<W: Write>(
+    path: &Path,
+    input_column: &str,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+    category: &str,
+    out: &mut W,
+) -> Result<usize, AppError> {
+    let mut reader: Reader<Box<dyn Read>> =
+        read_file(path, &base_context.reader_config(delimiter))?;
+    let headers: StringRecord = reader.headers()?.clone();
+    let context: RecordProcessingContext =
+        prepare_context(path, &headers, input_column, delimiter, base_context)?;
+
+    let mut matches: usize = 0;
+    for result in input_records(&mut reader, &context) {
+        let Some((record, _)): Option<(StringRecord, u64)> = result? else {
+            continue;
+        };
+        check_field_count(&record, headers.len(), &context)?;
+        if get_category(&record, &context) != category {
+            continue;
+        }
+        matches += 1;
+        let line: u64 = record.position().map_or(0, |position| position.line());
+        let fields: Vec<&str> = record.iter().collect();
+        if matches_filters(&record, &context) {
+            writeln!(out, "line {}: {:?}", line, fields)?;
+        } else {
+            writeln!(out, "line {}: {:?} (filtered out)", line, fields)?;
+        }
+    }
+    writeln!(out, "{} records categorize to {:?}", matches, category)?;
+    Ok(matches)
+}
+
+/// Records read by `--measure-cardinality-sampled`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum CardinalitySample {
+    /// The first records, the rest of the input isn't read
+    First(usize),
+    /// Every `n`th record, the whole input being read
+    Every(usize),
+}
+
+/// Distinct categories seen in a sample of the input, a lower bound of the categories
+/// the split would write
+#[derive(Debug, PartialEq)]
+pub(crate) struct CardinalityEstimate {
+    pub(crate) categories: usize,
+    pub(crate) sampled: usize,
+}
+
+impl FromStr for CardinalitySample {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid sample {}, expected first:<n> or every:<n>", s);
+        let (method, count) = s.split_once(':').ok_or_else(invalid)?;
+        let count: usize = count.parse().ok().filter(|&n| n > 0).ok_or_else(invalid)?;
+        match method {
+            "first" => Ok(CardinalitySample::First(count)),
+            "every" => Ok(CardinalitySample::Every(count)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for CardinalitySample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardinalitySample::First(count) => write!(f, "the first {} records", count),
+            CardinalitySample::Every(count) => write!(f, "one record in every {}", count),
+        }
+    }
+}
+
+/// Count the distinct categories the split would write, reading the whole input without
+/// writing anything
+pub(crate) fn count_categories(
+    path: &Path,
+    input_column: &str,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<usize, AppError> {
+    let (categories, _, _) =
+        distinct_categories(path, input_column, delimiter, base_context, None)?;
+    Ok(categories.len())
+}
+
+/// Count the distinct categories of a sample of the input, without writing anything
+pub(crate) fn estimate_cardinality(
+    path: &Path,
+    input_column: &str,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+    sample: CardinalitySample,
+) -> Result<CardinalityEstimate, AppError> {
+    let (categories, sampled, _) =
+        distinct_categories(path, input_column, delimiter, base_context, Some(sample))?;
+    Ok(CardinalityEstimate {
+        categories: categories.len(),
+        sampled,
+    })
+}
+
+/// Categories of the records of the `--rows` range, or of the sample of it, with the
+/// number of records sampled and the context prepared for the input
+pub(crate) fn distinct_categories(
+    path: &Path,
+    input_column: &str,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+    sample: Option<CardinalitySample>,
+) -> Result<(HashSet<String>, usize, RecordProcessingContext), AppError> {
+    let mut reader: Reader<Box<dyn Read>> =
+        read_file(path, &base_context.reader_config(delimiter))?;
+    let headers: StringRecord = reader.headers()?.clone();
+    let context: RecordProcessingContext =
+        prepare_context(path, &headers, input_column, delimiter, base_context)?;
+
+    let mut categories: HashSet<String> = HashSet::new();
+    let mut considered: usize = 0;
+    let mut sampled: usize = 0;
+    for result in input_records(&mut reader, &context) {
+        considered += 1;
+        match sample {
+            Some(CardinalitySample::First(count)) if considered > count => break,
+            Some(CardinalitySample::Every(count)) if !(considered - 1).is_multiple_of(count) => {
+                continue
+            }
+            _ => {}
+        }
+        sampled += 1;
+        let Some((record, _)): Option<(StringRecord, u64)> = result? else {
+            continue;
+        };
+        check_field_count(&record, headers.len(), &context)?;
+        if matches_filters(&record, &context) {
+            let category: String = get_category(&record, &context);
+            if !is_skipped_category(category.as_bytes(), &context) {
+                categories.insert(category);
+            }
+        }
+    }
+    Ok((categories, sampled, context))
+}
+
+/// Decide whether to write `count` categories against `--confirm-categories-above`. Above
+/// the threshold `--yes` confirms, otherwise the user is asked on a terminal and the
+/// run stops when there is none
+pub(crate) fn confirm_category_count<R: BufRead, W: Write>(
+    count: usize,
+    threshold: usize,
+    yes: bool,
+    interactive: bool,
+    input: &mut R,
+    prompt: &mut W,
+) -> Result<(), AppError> {
+    if count <= threshold || yes {
+        return Ok(());
+    }
+    if !interactive {
+        return Err(AppError::InvalidArguments(format!(
+            "The split would write {} categories, more than --confirm-categories-above {}, pass --yes to confirm",
+            count, threshold
+        )));
+    }
+    write!(
+        prompt,
+        "The split would write {} categories, continue? [y/N] ",
+        count
+    )?;
+    prompt.flush()?;
+    let mut answer: String = String::new();
+    input.read_line(&mut answer)?;
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        Ok(())
+    } else {
+        Err(AppError::InvalidArguments(format!(
+            "Aborted writing {} categories",
+            count
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_cardinality() {
+        let estimate = |sample: &str| {
+            estimate_cardinality(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &RecordProcessingContext::default(),
+                sample.parse().unwrap(),
+            )
+            .unwrap()
+        };
+
+        // city.csv has 4 states in 12 records, sorted by state
+        let first = estimate("first:5");
+        assert_eq!(first.sampled, 5);
+        assert!((1..=4).contains(&first.categories));
+        assert_eq!(first.categories, 2);
+        let every = estimate("every:2");
+        assert_eq!(every.sampled, 6);
+        assert!((1..=4).contains(&every.categories));
+        assert_eq!(estimate("first:100").categories, 4);
+        assert!("every:0".parse::<CardinalitySample>().is_err());
+        assert!("last:5".parse::<CardinalitySample>().is_err());
+    }
+
+    #[test]
+    fn test_inspect_category() {
+        let context = RecordProcessingContext {
+            filters: vec!["Population > 100000".parse().unwrap()],
+            ..Default::default()
+        };
+        let mut out: Vec<u8> = Vec::new();
+
+        let matches = inspect_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &context,
+            "NY",
+            &mut out,
+        )
+        .unwrap();
+
+        let printed = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = printed.lines().collect();
+        assert_eq!(matches, 3);
+        assert_eq!(
+            lines[0],
+            r#"line 11: ["Albany", "NY", "93523", "42.6525000", "-73.7566667"] (filtered out)"#
+        );
+        assert_eq!(
+            lines[1],
+            r#"line 12: ["Brooklyn", "NY", "2465326", "40.6500000", "-73.9500000"]"#
+        );
+        assert_eq!(lines[3], r#"3 records categorize to "NY""#);
+    }
+
+    #[test]
+    fn test_count_categories() {
+        let context = RecordProcessingContext::default();
+
+        let count = count_categories(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &context,
+        )
+        .unwrap();
+
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_confirm_category_count() {
+        let confirm = |count: usize, yes: bool, interactive: bool, answer: &str| {
+            let mut prompt: Vec<u8> = Vec::new();
+            confirm_category_count(
+                count,
+                3,
+                yes,
+                interactive,
+                &mut answer.as_bytes(),
+                &mut prompt,
+            )
+        };
+
+        assert!(confirm(3, false, false, "").is_ok());
+        assert!(confirm(4, true, false, "").is_ok());
+        assert!(confirm(4, false, false, "y\n").is_err());
+        assert!(confirm(4, false, true, "y\n").is_ok());
+        assert!(confirm(4, false, true, "\n").is_err());
+    }
+
+    #[test]
+    fn test_inspect_category_row_range() {
+        let context = RecordProcessingContext {
+            row_range: Some("11:11".parse().unwrap()),
+            ..Default::default()
+        };
+        let mut out: Vec<u8> = Vec::new();
+
+        let matches = inspect_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &context,
+            "NY",
+            &mut out,
+        )
+        .unwrap();
+
+        assert_eq!(matches, 1);
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("line 12: [\"Brooklyn\""));
+    }
+}
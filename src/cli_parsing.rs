@@ -1,17 +1,291 @@
-use crate::delimiter::Delimiter;
-use clap::{Arg, ArgMatches};
+use crate::app_error::AppError;
+use crate::category_inspecting::CardinalitySample;
+use crate::delimiter::{DelimiterChoice, OutputDelimiter};
+use crate::fixed_width::FixedWidth;
+use crate::key_expr::KeyExpr;
+use crate::output_encoding::{OutputEncoding, UnmappablePolicy};
+use crate::predicate::{FilterLogic, Predicate};
+use crate::record_context::{
+    CategoryTransform, ColumnMatch, ConcurrencyStrategy, CountTrailer, HeaderCase,
+    InvalidCategoryPolicy, OversizedRecordPolicy, RowRange, SortKey, UnexpectedCategoryPolicy,
+    UnmappedCategoryPolicy, WriteHeader,
+};
+use crate::row_hashing::RowHashAlgorithm;
+use crate::run_date::RunDate;
+use crate::schema_inferring::SqlDialect;
+use crate::split_summary::SummaryFormat;
+use clap::builder::Resettable;
+use clap::{Arg, ArgGroup, ArgMatches, Command, Id};
+use regex::Regex;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::{Table, Value};
 
-pub(crate) fn parse_cli() -> ArgMatches {
-    clap::Command::new("Csv Splitter")
+/// Prefix of the environment variables overriding the config file
+const ENV_PREFIX: &str = "CSV_SPLITTER_";
+/// Shortest `--max-name-length`, the hash of a truncated name taking 9 characters
+const MIN_NAME_LENGTH: usize = 16;
+
+/// Parse the command line arguments, layered over the environment variables and the
+/// config file: CLI flags win over `CSV_SPLITTER_*` variables, which win over the
+/// `--config` file, which wins over the built-in defaults
+pub(crate) fn parse_cli() -> Result<ArgMatches, AppError> {
+    let args: Vec<OsString> = env::args_os().collect();
+    let config: Table = match find_config_path(&args) {
+        Some(path) => load_config(&path)?,
+        None => Table::new(),
+    };
+    Ok(build_cli(&config)?.get_matches_from(args))
+}
+
+/// Get the output directory, with its `%Y`, `%m` and `%d` placeholders resolved against
+/// `--run-date` or the current date. It is empty for the commands writing no files
+pub(crate) fn resolve_output_dir(matches: &ArgMatches) -> PathBuf {
+    let template: &str = matches
+        .get_one::<String>("output-dir")
+        .map_or("", String::as_str);
+    let run_date: RunDate = matches
+        .get_one::<RunDate>("run-date")
+        .copied()
+        .unwrap_or_else(RunDate::today);
+    PathBuf::from(run_date.expand(template))
+}
+
+/// Parse a duration made of a number and a unit among `s`, `m` and `h`, e.g. `10m`
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration {}, expected e.g. 30s, 10m or 2h", value);
+    let unit_idx: usize = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let amount: u64 = value[..unit_idx].parse().map_err(|_| invalid())?;
+    let unit_seconds: u64 = match &value[unit_idx..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return Err(invalid()),
+    };
+    let seconds: u64 = amount
+        .checked_mul(unit_seconds)
+        .ok_or_else(|| format!("Duration {} is too long", value))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parse an `--alias RAW=CATEGORY` pair, the raw value being any text before the first `=`
+pub(crate) fn parse_alias(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((raw, category)) if !raw.is_empty() && !category.is_empty() => {
+            Ok((raw.to_string(), category.to_string()))
+        }
+        _ => Err(format!("Invalid alias {}, expected RAW=CATEGORY", value)),
+    }
+}
+
+/// Parse a `--max-name-length`, long enough to keep some of the value before the hash
+fn parse_max_name_length(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(length) if length >= MIN_NAME_LENGTH => Ok(length),
+        _ => Err(format!(
+            "Invalid name length {}, expected a number of at least {}",
+            value, MIN_NAME_LENGTH
+        )),
+    }
+}
+
+/// Parse a `--comment-char`, a single ASCII character as the reader compares bytes
+fn parse_comment_char(value: &str) -> Result<u8, String> {
+    match value.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(format!(
+            "Invalid comment character {}, expected a single ASCII character",
+            value
+        )),
+    }
+}
+
+/// Parse a `--key-separator`, which can't be empty or hold the `%` its escaping adds, for
+/// the key to still tell its values apart
+fn parse_key_separator(value: &str) -> Result<String, String> {
+    if value.is_empty() || value.contains('%') {
+        return Err(format!(
+            "Invalid key separator {:?}, expected a non-empty separator without %",
+            value
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// Format the resolved options as a TOML config file, for `--dump-config`. Each option
+/// holds the value that won across the layers, the options without any being left out
+pub(crate) fn dump_config(matches: &ArgMatches) -> String {
+    let mut config: Table = Table::new();
+    for arg in cli_command().get_arguments() {
+        let Some(long) = arg
+            .get_long()
+            .filter(|long| !matches!(*long, "config" | "dump-config"))
+        else {
+            continue;
+        };
+        let Some(raw_values) = matches.get_raw(arg.get_id().as_str()) else {
+            continue;
+        };
+        let values: Vec<String> = raw_values
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect();
+        let value: Value = match arg.get_action() {
+            clap::ArgAction::SetTrue => Value::Boolean(values.iter().any(|value| value == "true")),
+            _ if values.len() > 1 => Value::Array(values.into_iter().map(Value::String).collect()),
+            _ => Value::String(values.concat()),
+        };
+        config.insert(long.to_string(), value);
+    }
+    config.to_string()
+}
+
+/// Build the command with the environment variables and the config file values as defaults
+fn build_cli(config: &Table) -> Result<Command, AppError> {
+    let mut command: Command = cli_command();
+    let ids: Vec<(Id, String)> = command
+        .get_arguments()
+        .filter_map(|arg| Some((arg.get_id().clone(), arg.get_long()?.to_string())))
+        .collect();
+    for (id, long) in &ids {
+        let env_name: String = format!("{}{}", ENV_PREFIX, long.to_uppercase().replace('-', "_"));
+        command = command.mut_arg(id, |arg| arg.env(env_name));
+    }
+
+    for (key, value) in config {
+        let (id, _) = ids
+            .iter()
+            .find(|(_, long)| long == key && long != "config")
+            .ok_or_else(|| AppError::InvalidArguments(format!("Unknown config key: {}", key)))?;
+        let values: Vec<String> = config_values(key, value)?;
+        // A value from the file satisfies the required arguments
+        command = command.mut_arg(id, |arg| {
+            arg.default_values(values)
+                .required(false)
+                .required_unless_present(Resettable::<Id>::Reset)
+        });
+    }
+    Ok(command)
+}
+
+/// Find the config file before parsing, as its values become the argument defaults
+fn find_config_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args_iter = args.iter().skip(1).map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args_iter.next() {
+        if arg == "--config" {
+            return args_iter.next().map(|path| PathBuf::from(path.as_ref()));
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    env::var_os(format!("{}CONFIG", ENV_PREFIX)).map(PathBuf::from)
+}
+
+/// Read a TOML config file whose keys are the long names of the CLI options
+fn load_config(path: &Path) -> Result<Table, AppError> {
+    let content: String = fs::read_to_string(path)
+        .map_err(|_| AppError::InputNotFound(path.display().to_string()))?;
+    content
+        .parse::<Table>()
+        .map_err(|error| AppError::InvalidArguments(format!("Invalid config file: {}", error)))
+}
+
+/// Render a config value the way it would be typed on the command line, an array giving
+/// one value per element as if the option was repeated
+fn config_values(key: &str, value: &Value) -> Result<Vec<String>, AppError> {
+    match value {
+        Value::String(value) => Ok(vec![value.clone()]),
+        Value::Integer(value) => Ok(vec![value.to_string()]),
+        Value::Float(value) => Ok(vec![value.to_string()]),
+        Value::Boolean(value) => Ok(vec![value.to_string()]),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Array(_) | Value::Table(_) | Value::Datetime(_) => {
+                    Err(unsupported_config_value(key))
+                }
+                value => config_values(key, value).map(|values| values.concat()),
+            })
+            .collect(),
+        _ => Err(unsupported_config_value(key)),
+    }
+}
+
+fn unsupported_config_value(key: &str) -> AppError {
+    AppError::InvalidArguments(format!("Unsupported value for config key: {}", key))
+}
+
+fn cli_command() -> Command {
+    Command::new("Csv Splitter")
         .version("0.1.0")
         .author("TheLionCoder")
         .about("Split a CSV file into multiple files based on a column values")
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .help("TOML file whose keys are the long option names, used as defaults"),
+        )
+        .arg(
+            Arg::new("dump-config")
+                .long("dump-config")
+                .action(clap::ArgAction::SetTrue)
+                .help("Print the options resolved from the flags, environment variables, config file and defaults as a TOML config file and exit without splitting"),
+        )
         .arg(
             Arg::new("path")
                 .short('p')
                 .long("path")
-                .required(true)
-                .help("Path to the CSV file to split"),
+                .required_unless_present_any(["watch", "input-glob"])
+                .help("Path or http(s) URL of the CSV file to split, gzip and zstd inputs are detected by their first bytes"),
+        )
+        .arg(
+            Arg::new("input-glob")
+                .long("input-glob")
+                .conflicts_with_all([
+                    "path", "watch", "peek", "print-schema-sql", "inspect-category", "validate-only",
+                    "measure-cardinality-sampled", "headers-only", "transpose-columns", "bytes",
+                    "confirm-categories-above", "writer-thread", "sorted-input", "count-trailer",
+                    "sort-within-category", "emit-schema",
+                ])
+                .help("Split every file matching this pattern, e.g. 'exports/*.csv', in parallel into the same category files"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .value_parser(clap::value_parser!(usize))
+                .help("Number of threads splitting the records and the --input-glob files, one per core by default"),
+        )
+        .arg(
+            Arg::new("watch")
+                .short('w')
+                .long("watch")
+                .conflicts_with("path")
+                .help("Watch a directory and split every new CSV file that lands in it"),
+        )
+        .arg(
+            Arg::new("follow")
+                .long("follow")
+                .action(clap::ArgAction::SetTrue)
+                .requires("path")
+                .conflicts_with_all([
+                    "input-glob", "watch", "bytes", "transpose-columns", "headers-only",
+                    "validate-only", "peek", "inspect-category", "field-count-report",
+                    "print-schema-sql", "measure-cardinality-sampled", "confirm-categories-above",
+                    "expand-json", "fixed-width", "trailer-rows", "sort-within-category",
+                    "count-trailer", "tar", "xlsx",
+                ])
+                .help("Keep reading the rows appended to the input once its end is reached, like tail -f, until --max-runtime. A truncated or rotated input is read again from the start"),
+        )
+        .arg(
+            Arg::new("file-name")
+                .short('f')
+                .long("file-name")
+                .help("File name used for the split files inside category directories, defaults to the input file stem"),
         )
         .arg(
             Arg::new("delimiter")
@@ -19,23 +293,106 @@ pub(crate) fn parse_cli() -> ArgMatches {
                 .long("delimiter")
                 .default_value(",")
                 .value_parser(clap::builder::ValueParser::new(|value: &str| {
-                    value.parse::<Delimiter>()
+                    value.parse::<DelimiterChoice>()
                 }))
-                .help("Delimiter used in the CSV file"),
+                .help("Delimiter used in the CSV file, either the character, \\t for a tab, or one of comma, pipe, tab, semicolon, or auto to sniff it"),
+        )
+        .arg(
+            Arg::new("delimiter-out")
+                .long("delimiter-out")
+                .default_value("|")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<OutputDelimiter>()
+                }))
+                .help("Delimiter of the split files, or none to write a single output column raw, one value per line"),
+        )
+        .arg(
+            Arg::new("drop")
+                .long("drop")
+                .value_delimiter(',')
+                .help("Comma separated columns left out of the split files, besides the split column"),
+        )
+        .arg(
+            Arg::new("always-quote-for")
+                .long("always-quote-for")
+                .value_delimiter(',')
+                .help("Comma separated categories whose files quote every field, the others only quoting when needed"),
+        )
+        .arg(
+            Arg::new("quote-empty")
+                .long("quote-empty")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["bytes", "transpose-columns"])
+                .help("Write the empty fields as \"\", for readers taking an unquoted empty field as null"),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .action(clap::ArgAction::SetTrue)
+                .help("Use the sniffed delimiter of --delimiter auto even when it is ambiguous"),
         )
         .arg(
             Arg::new("input-column")
                 .short('c')
                 .long("column")
-                .required(true)
-                .help("Column to split the CSV file by"),
+                .action(clap::ArgAction::Append)
+                .required_unless_present_any(["transpose-columns", "print-schema-sql", "peek", "key-expr", "key-columns", "join-file", "field-count-report"])
+                .help("Column to split the CSV file by. Repeat it to split by each column independently into by_<column> directories"),
+        )
+        .arg(
+            Arg::new("key-expr")
+                .long("key-expr")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<KeyExpr>()
+                }))
+                .conflicts_with_all(["input-column", "transpose-columns"])
+                .help("Expression computing the category instead of --column, e.g. State + \"-\" + substr(Zip, 0, 3)"),
+        )
+        .arg(
+            Arg::new("key-columns")
+                .long("key-columns")
+                .action(clap::ArgAction::Append)
+                .value_delimiter(',')
+                .conflicts_with_all(["input-column", "key-expr", "transpose-columns"])
+                .help("Split by the composite key of these columns, joined by --key-separator in the order listed. Repeatable"),
+        )
+        .arg(
+            Arg::new("key-separator")
+                .long("key-separator")
+                .value_parser(clap::builder::ValueParser::new(parse_key_separator))
+                .default_value("_")
+                .requires("key-columns")
+                .help("Separator of the --key-columns values, percent-encoded within them, neither empty nor holding %"),
+        )
+        .arg(
+            Arg::new("key-regex")
+                .long("key-regex")
+                .value_name("REGEX")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    let key_regex: Regex = value.parse().map_err(|error: regex::Error| error.to_string())?;
+                    if key_regex.capture_names().flatten().next().is_none() {
+                        return Err(format!("No named group in {}, expected e.g. (?P<year>\\d{{4}})", value));
+                    }
+                    Ok(key_regex)
+                }))
+                .requires("input-column")
+                .conflicts_with_all(["key-expr", "key-columns", "transpose-columns", "watch", "input-glob", "bytes", "headers-only"])
+                .help("Pattern matched on the --column value, each named group, e.g. (?P<year>\\d{4})/(?P<month>\\d{2}), splitting by its capture into a by_<group> directory"),
         )
         .arg(
             Arg::new("output-dir")
                 .short('o')
                 .long("dir")
-                .required(true)
-                .help("Output directory to save the split files"),
+                .required_unless_present_any(["tar", "xlsx", "print-schema-sql", "peek", "measure-cardinality-sampled", "validate-only", "field-count-report", "inspect-category"])
+                .help("Output directory to save the split files, a local path, a file:// URI or - to discard them, %Y, %m and %d are replaced by the run date"),
+        )
+        .arg(
+            Arg::new("run-date")
+                .long("run-date")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<RunDate>()
+                }))
+                .help("Date as YYYY-MM-DD replacing the placeholders of --dir, defaults to the current UTC date"),
         )
         .arg(
             Arg::new("create-dir")
@@ -44,15 +401,646 @@ pub(crate) fn parse_cli() -> ArgMatches {
                 .action(clap::ArgAction::SetTrue)
                 .help("Save the split files in a directory with the name of the column value"),
         )
-        .get_matches()
+        .arg(
+            Arg::new("shard-by-initial")
+                .long("shard-by-initial")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["create-dir", "transpose-columns"])
+                .help("Save each split file under a directory named by the uppercased first character of its category, e.g. N/NY.csv, _ when it isn't a letter or digit"),
+        )
+        .arg(
+            Arg::new("column-match")
+                .long("column-match")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<ColumnMatch>()
+                }))
+                .default_value("exact")
+                .help("Match --column as a prefix or glob of a header when no header is exactly it, the match having to be unique"),
+        )
+        .arg(
+            Arg::new("force-create-dir")
+                .long("force-create-dir")
+                .action(clap::ArgAction::SetTrue)
+                .help("Create the output directory and its parents when missing, without --create-dir"),
+        )
+        .arg(
+            Arg::new("category-path-sep")
+                .long("category-path-sep")
+                .value_parser(clap::value_parser!(char))
+                .requires("create-dir")
+                .help("Split the column value on this character into nested directories"),
+        )
+        .arg(
+            Arg::new("max-name-length")
+                .long("max-name-length")
+                .value_parser(clap::builder::ValueParser::new(parse_max_name_length))
+                .help("Truncate the category file and directory names to this many characters, ending them with a hash of the full value"),
+        )
+        .arg(
+            Arg::new("max-record-bytes")
+                .long("max-record-bytes")
+                .value_parser(clap::value_parser!(usize))
+                .help("Maximum number of bytes of the fields of a record, measured once it is parsed, so it doesn't limit the memory taken while parsing"),
+        )
+        .arg(
+            Arg::new("on-oversized-record")
+                .long("on-oversized-record")
+                .default_value("skip")
+                .requires("max-record-bytes")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<OversizedRecordPolicy>()
+                }))
+                .help("Skip records longer than --max-record-bytes or stop with an error [skip, error]"),
+        )
+        .arg(
+            Arg::new("on-invalid-category")
+                .long("on-invalid-category")
+                .default_value("error")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<InvalidCategoryPolicy>()
+                }))
+                .help("Skip the records of a category escaping the output directory, like ../etc, or not matching --category-validate, replace its .., / and \\ with _ (sanitize), write them to the invalid category or stop with an error"),
+        )
+        .arg(
+            Arg::new("category-validate")
+                .long("category-validate")
+                .value_name("REGEX")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<Regex>()
+                }))
+                .help("Pattern each derived category must match, e.g. ^[A-Z]{2}$, the others being invalid categories"),
+        )
+        .arg(
+            Arg::new("max-errors")
+                .long("max-errors")
+                .value_parser(clap::value_parser!(usize))
+                .help("Stop with an error once more records were skipped as oversized or of an invalid category, unlimited by default"),
+        )
+        .arg(
+            Arg::new("retry-open")
+                .long("retry-open")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with("bytes")
+                .help("Retry opening a category file up to this many times when out of file handles, closing the least recently used half of the open files first"),
+        )
+        .arg(
+            Arg::new("sort-within-category")
+                .long("sort-within-category")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<SortKey>()
+                }))
+                .help("Sort the records of each category by COLUMN[:asc|desc], holding them in memory until the end"),
+        )
+        .arg(
+            Arg::new("spill-threshold")
+                .long("spill-threshold")
+                .value_parser(clap::value_parser!(usize))
+                .requires("sort-within-category")
+                .help("Spill the buffered records of a category to a temporary file once they exceed this count"),
+        )
+        .arg(
+            Arg::new("ascii-only-filenames")
+                .long("ascii-only-filenames")
+                .action(clap::ArgAction::SetTrue)
+                .help("Transliterate the column values to ASCII for the file names, keeping the data intact"),
+        )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .action(clap::ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<Predicate>()
+                }))
+                .help("Only write the records matching 'COLUMN OP VALUE', OP being ==, !=, >, >=, < or <=. Repeatable"),
+        )
+        .arg(
+            Arg::new("filter-logic")
+                .long("filter-logic")
+                .default_value("and")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<FilterLogic>()
+                }))
+                .help("Whether a record must match all the filters (and) or any of them (or)"),
+        )
+        .arg(
+            Arg::new("null-category")
+                .long("null-category")
+                .default_value("unknown")
+                .help("Category of the records with an empty or null split column value, skip dropping them instead"),
+        )
+        .arg(
+            Arg::new("category-map")
+                .long("category-map")
+                .help("Two column CSV file renaming split column values to category names, e.g. 1,north"),
+        )
+        .arg(
+            Arg::new("alias")
+                .long("alias")
+                .action(clap::ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(parse_alias))
+                .help("Write the records of a split value to another category as RAW=CATEGORY, taking precedence over --category-map. Repeatable"),
+        )
+        .arg(
+            Arg::new("header-normalize")
+                .long("header-normalize")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<HeaderCase>()
+                }))
+                .help("Normalize the written headers: snake and kebab join their lowercase words with _ and -, lower and upper only change the case"),
+        )
+        .arg(
+            Arg::new("category-transform")
+                .long("category-transform")
+                .action(clap::ArgAction::Append)
+                .value_delimiter(',')
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<CategoryTransform>()
+                }))
+                .conflicts_with_all(["bytes", "transpose-columns"])
+                .help("Rewrite the category names with upper, lower or trim, applied in order after --alias and --category-map, the written fields being kept as is. Repeatable"),
+        )
+        .arg(
+            Arg::new("join-file")
+                .long("join-file")
+                .requires_all(["join-on", "join-key"])
+                .conflicts_with_all(["input-column", "key-expr", "key-columns", "category-map", "transpose-columns"])
+                .help("Reference CSV file to split by the --join-key of the rows matching each record on --join-on, the misses going to --null-category"),
+        )
+        .arg(
+            Arg::new("join-on")
+                .long("join-on")
+                .requires("join-file")
+                .help("Column of both the input and the --join-file the records are matched on"),
+        )
+        .arg(
+            Arg::new("join-key")
+                .long("join-key")
+                .requires("join-file")
+                .help("Column of the --join-file holding the category of the matched records"),
+        )
+        .arg(
+            Arg::new("unmapped-category")
+                .long("unmapped-category")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<UnmappedCategoryPolicy>()
+                }))
+                .default_value("raw")
+                .help("Category of the values missing from --category-map: raw keeps the value, null uses --null-category"),
+        )
+        .arg(
+            Arg::new("categories-file")
+                .long("categories-file")
+                .help("File listing the expected categories one per line, their files being created up front even without records"),
+        )
+        .arg(
+            Arg::new("unexpected-category")
+                .long("unexpected-category")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<UnexpectedCategoryPolicy>()
+                }))
+                .default_value("error")
+                .requires("categories-file")
+                .help("What happens to a category missing from --categories-file: error stops the split, other writes its records to other"),
+        )
+        .arg(
+            Arg::new("freeze-categories-after")
+                .long("freeze-categories-after")
+                .value_name("ROWS")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with_all(["bytes", "header-map", "headers-only", "input-glob", "inspect-category", "sorted-input", "transpose-columns"])
+                .help("Lock the categories once this many records were read, a category first appearing afterwards being handled by --frozen-category"),
+        )
+        .arg(
+            Arg::new("frozen-category")
+                .long("frozen-category")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<UnexpectedCategoryPolicy>()
+                }))
+                .default_value("other")
+                .requires("freeze-categories-after")
+                .help("What happens to a category new after --freeze-categories-after: other writes its records to other, error stops the split"),
+        )
+        .arg(
+            Arg::new("null-values")
+                .long("null-values")
+                .value_delimiter(',')
+                .help("Comma separated split column values treated as empty, e.g. NA,N/A,NULL"),
+        )
+        .arg(
+            Arg::new("expect-headers")
+                .long("expect-headers")
+                .value_delimiter(',')
+                .help("Comma separated headers the input must have exactly, in order"),
+        )
+        .arg(
+            Arg::new("expect-headers-subset")
+                .long("expect-headers-subset")
+                .action(clap::ArgAction::SetTrue)
+                .requires("expect-headers")
+                .help("Only require the --expect-headers columns to be present, in any order"),
+        )
+        .arg(
+            Arg::new("header-map")
+                .long("header-map")
+                .conflicts_with_all(["sort-within-category", "dedupe-key", "transpose-columns"])
+                .help("CSV file listing on each line a category then the columns written for it"),
+        )
+        .arg(
+            Arg::new("expand-json")
+                .long("expand-json")
+                .conflicts_with_all(["transpose-columns", "inspect-category", "confirm-categories-above"])
+                .help("JSON object column replaced by a <column>.<key> column for each of its keys"),
+        )
+        .arg(
+            Arg::new("strip-quotes")
+                .long("strip-quotes")
+                .action(clap::ArgAction::SetTrue)
+                .help("Remove one layer of double quotes surrounding each written field"),
+        )
+        .arg(
+            Arg::new("normalize-line-endings")
+                .long("normalize-line-endings")
+                .action(clap::ArgAction::SetTrue)
+                .help("Strip the carriage returns left at the end of the last field of the records, for inputs mixing CRLF and LF"),
+        )
+        .arg(
+            Arg::new("trim")
+                .long("trim")
+                .action(clap::ArgAction::SetTrue)
+                .help("Trim the whitespace around the input headers and fields"),
+        )
+        .arg(
+            Arg::new("no-quoting")
+                .long("no-quoting")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("fixed-width")
+                .help("Read the quotes of the input as data, splitting the fields on the delimiter alone"),
+        )
+        .arg(
+            Arg::new("read-buffer")
+                .long("read-buffer")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .default_value("16777216")
+                .help("Bytes buffered from the input"),
+        )
+        .arg(
+            Arg::new("fixed-width")
+                .long("fixed-width")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<FixedWidth>()
+                }))
+                .help("Read a fixed-width input cut into columns at these character ranges, e.g. 0-5,5-10,10-30. The columns are named by --expect-headers, or by the first line cut the same way"),
+        )
+        .arg(
+            Arg::new("trailer-rows")
+                .long("trailer-rows")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with("transpose-columns")
+                .help("Drop this many footer records from the end of the input"),
+        )
+        .arg(
+            Arg::new("skip-empty-lines")
+                .long("skip-empty-lines")
+                .action(clap::ArgAction::SetTrue)
+                .help("Drop blank and whitespace only lines instead of failing on them"),
+        )
+        .arg(
+            Arg::new("success-marker")
+                .long("success-marker")
+                .action(clap::ArgAction::SetTrue)
+                .help("Write an empty _SUCCESS file to the output directory once the split completed"),
+        )
+        .arg(
+            Arg::new("allow-schema-drift")
+                .long("allow-schema-drift")
+                .action(clap::ArgAction::SetTrue)
+                .help("Only warn when a watched file has other headers than the first one"),
+        )
+        .arg(
+            Arg::new("writer-thread")
+                .long("writer-thread")
+                .action(clap::ArgAction::SetTrue)
+                .help("Write the split files from a dedicated thread fed through a channel, while the other threads categorize the records"),
+        )
+        .arg(
+            Arg::new("channel-capacity")
+                .long("channel-capacity")
+                .default_value("4")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .requires("writer-thread")
+                .help("Chunks of records queued for the writer thread before reading blocks"),
+        )
+        .arg(
+            Arg::new("single-threaded")
+                .long("single-threaded")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("writer-thread")
+                .help("Categorize and write the records one after the other without rayon, as a reference for the parallel output"),
+        )
+        .arg(
+            Arg::new("concurrency-strategy")
+                .long("concurrency-strategy")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<ConcurrencyStrategy>()
+                }))
+                .default_value("fold-reduce")
+                .conflicts_with("single-threaded")
+                .help("How the records are categorized in parallel, to benchmark them: fold-reduce merges a map per thread, dashmap shares a sharded map, channel sends the records to a collecting thread"),
+        )
+        .arg(
+            Arg::new("sorted-input")
+                .long("sorted-input")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "writer-thread", "sort-within-category", "count-trailer", "key-expr", "key-columns",
+                    "watch", "categories-file",
+                ])
+                .help("The input is sorted by the split column: write the records in a single pass, closing each file once the key changes"),
+        )
+        .arg(
+            Arg::new("check-sorted")
+                .long("check-sorted")
+                .action(clap::ArgAction::SetTrue)
+                .requires("sorted-input")
+                .help("Warn when a category of --sorted-input comes back after its file was closed"),
+        )
+        .arg(
+            Arg::new("bytes")
+                .long("bytes")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "watch", "inspect-category", "transpose-columns", "key-expr", "key-columns", "join-file",
+                    "category-map", "alias", "header-map", "expand-json", "filter", "dedupe", "dedupe-key",
+                    "sort-within-category", "ascii-only-filenames", "strip-quotes", "trailer-rows",
+                    "skip-empty-lines", "normalize-line-endings", "max-record-bytes", "rows", "writer-thread",
+                    "single-threaded", "count-trailer", "sorted-input", "categories-file",
+                ])
+                .help("Split the records as raw bytes without validating them as UTF-8, for speed on inputs known to be valid"),
+        )
+        .arg(
+            Arg::new("rows")
+                .long("rows")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<RowRange>()
+                }))
+                .help("Only split the records from START to END, 1-based and inclusive, as START:END"),
+        )
+        .arg(
+            Arg::new("max-runtime")
+                .long("max-runtime")
+                .value_parser(clap::builder::ValueParser::new(parse_duration))
+                .help("Stop cleanly once the run took longer than this duration, e.g. 30s, 10m or 2h"),
+        )
+        .arg(
+            Arg::new("progress-to")
+                .long("progress-to")
+                .conflicts_with_all(["inspect-category", "transpose-columns", "print-schema-sql"])
+                .help("Append an NDJSON progress line to this file after every chunk of records"),
+        )
+        .arg(
+            Arg::new("write-header")
+                .long("write-header")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<WriteHeader>()
+                }))
+                .default_value("if-new")
+                .help("When to write the headers to a category file: always, never or if-new, only to the files the run creates"),
+        )
+        .arg(
+            Arg::new("output-encoding")
+                .long("output-encoding")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<OutputEncoding>()
+                }))
+                .default_value("utf8")
+                .conflicts_with("bytes")
+                .help("Encoding of the split files: utf8, windows-1252 or latin1, an alias of windows-1252"),
+        )
+        .arg(
+            Arg::new("on-unmappable-char")
+                .long("on-unmappable-char")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<UnmappablePolicy>()
+                }))
+                .default_value("replace")
+                .help("What happens to a character the --output-encoding can't represent: replace writes ?, error stops the split"),
+        )
+        .arg(
+            Arg::new("excel-sep-hint")
+                .long("excel-sep-hint")
+                .action(clap::ArgAction::SetTrue)
+                .help("Start the category files the run creates or finds empty with a sep=<delimiter> line, for Excel to pick the delimiter"),
+        )
+        .arg(
+            Arg::new("run-id")
+                .long("run-id")
+                .help("Id of the run in the --stats-json file and the --append-run-id-column column, a random UUID by default"),
+        )
+        .arg(
+            Arg::new("append-run-id-column")
+                .long("append-run-id-column")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["bytes", "transpose-columns"])
+                .help("Add a run_id column holding the --run-id to every written record"),
+        )
+        .arg(
+            Arg::new("row-hash-column")
+                .long("row-hash-column")
+                .value_name("NAME")
+                .conflicts_with_all(["bytes", "transpose-columns"])
+                .help("Add a column holding a hash of the other written fields of each record, to deduplicate or detect changes downstream"),
+        )
+        .arg(
+            Arg::new("row-hash-algorithm")
+                .long("row-hash-algorithm")
+                .default_value("xxh3")
+                .requires("row-hash-column")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<RowHashAlgorithm>()
+                }))
+                .help("Hash of --row-hash-column: 'xxh3' (fast) or 'sha256'"),
+        )
+        .arg(
+            Arg::new("emit-schema")
+                .long("emit-schema")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["bytes", "inspect-category", "transpose-columns"])
+                .help("Write a <category>.schema.json file next to each category file, with the name, type and nullability of its columns guessed from its first records"),
+        )
+        .arg(
+            Arg::new("count-trailer")
+                .long("count-trailer")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<CountTrailer>()
+                }))
+                .conflicts_with_all(["watch", "inspect-category", "transpose-columns"])
+                .help("Write the records count of each category file once split: comment appends a #ROWS=<count> line, sidecar writes it to <category>.count"),
+        )
+        .arg(
+            Arg::new("comment-char")
+                .long("comment-char")
+                .value_parser(clap::builder::ValueParser::new(parse_comment_char))
+                .help("Skip the input lines starting with this ASCII character, which also starts the --count-trailer comment line instead of #"),
+        )
+        .arg(
+            Arg::new("tee")
+                .long("tee")
+                .conflicts_with_all(["bytes", "header-map", "headers-only", "transpose-columns", "inspect-category"])
+                .help("Also write every record written to a category to this single CSV file, with the same columns and headers"),
+        )
+        .arg(
+            Arg::new("summary-format")
+                .long("summary-format")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<SummaryFormat>()
+                }))
+                .conflicts_with_all(["watch", "inspect-category", "transpose-columns"])
+                .help("Print the summary of the split to stdout as a text table or as JSON, the logs going to stderr with json"),
+        )
+        .arg(
+            Arg::new("stats-json")
+                .long("stats-json")
+                .conflicts_with_all(["watch", "inspect-category", "transpose-columns"])
+                .help("Write the timing and throughput metrics of the run to this JSON file"),
+        )
+        .arg(
+            Arg::new("manifest")
+                .long("manifest")
+                .conflicts_with_all(["bytes", "headers-only", "inspect-category", "transpose-columns"])
+                .help("Write the inputs split and the records of each category file to this JSON file, rewritten as each input of the run is done"),
+        )
+        .arg(
+            Arg::new("tar")
+                .long("tar")
+                .conflicts_with_all(["watch", "inspect-category", "output-dir"])
+                .help("Write the output files as the entries of a tar archive instead"),
+        )
+        .arg(
+            Arg::new("xlsx")
+                .long("xlsx")
+                .conflicts_with_all([
+                    "watch", "inspect-category", "output-dir", "tar", "create-dir",
+                    "shard-by-initial", "transpose-columns", "excel-sep-hint", "output-encoding",
+                    "count-trailer",
+                ])
+                .help("Write each category as a sheet of this XLSX workbook instead, with the header row frozen unless --write-header never"),
+        )
+        .arg(
+            Arg::new("confirm-categories-above")
+                .long("confirm-categories-above")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with_all(["watch", "transpose-columns"])
+                .help("Count the categories first and ask before writing more than this many files"),
+        )
+        .arg(
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .action(clap::ArgAction::SetTrue)
+                .help("Confirm writing more categories than --confirm-categories-above without asking"),
+        )
+        .arg(
+            Arg::new("inspect-category")
+                .long("inspect-category")
+                .conflicts_with_all(["watch", "transpose-columns"])
+                .help("Print the records of the input that go to this category with their line numbers, without writing files"),
+        )
+        .arg(
+            Arg::new("headers-only")
+                .long("headers-only")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "watch", "transpose-columns", "inspect-category", "bytes", "expand-json", "sorted-input",
+                    "sort-within-category", "count-trailer", "emit-schema", "writer-thread",
+                ])
+                .help("Create the file of every category with just the headers, without writing any record"),
+        )
+        .arg(
+            Arg::new("field-count-report")
+                .long("field-count-report")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["watch", "transpose-columns", "inspect-category", "print-schema-sql", "peek", "validate-only", "measure-cardinality-sampled", "input-glob"])
+                .help("Print a histogram of the records by their number of fields, without writing files"),
+        )
+        .arg(
+            Arg::new("validate-only")
+                .long("validate-only")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["watch", "transpose-columns", "inspect-category", "print-schema-sql", "peek", "measure-cardinality-sampled"])
+                .help("Check that every record parses with the headers, as UTF-8 and within --max-record-bytes, printing PASS or FAIL with the counts, without writing files"),
+        )
+        .arg(
+            Arg::new("measure-cardinality-sampled")
+                .long("measure-cardinality-sampled")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<CardinalitySample>()
+                }))
+                .conflicts_with_all(["watch", "transpose-columns", "inspect-category", "print-schema-sql", "peek"])
+                .help("Print an estimate of the distinct categories from first:<n> records or every:<n>th record, without writing files"),
+        )
+        .arg(
+            Arg::new("peek")
+                .long("peek")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with_all(["watch", "transpose-columns", "inspect-category", "print-schema-sql"])
+                .help("Print the raw first N lines of the input with visible tabs, carriage returns, BOMs and invalid bytes, without parsing it or writing files"),
+        )
+        .arg(
+            Arg::new("print-schema-sql")
+                .long("print-schema-sql")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["watch", "transpose-columns", "inspect-category"])
+                .help("Print a CREATE TABLE statement with the column types guessed from the first records, without writing files"),
+        )
+        .arg(
+            Arg::new("sql-dialect")
+                .long("sql-dialect")
+                .value_parser(clap::builder::ValueParser::new(|value: &str| {
+                    value.parse::<SqlDialect>()
+                }))
+                .default_value("postgres")
+                .help("SQL dialect of --print-schema-sql: postgres, mysql or sqlite"),
+        )
+        .arg(
+            Arg::new("transpose-columns")
+                .long("transpose-columns")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["watch", "input-column"])
+                .help("Write each column to its own <header>.csv file instead of splitting rows"),
+        )
+        .arg(
+            Arg::new("dedupe")
+                .long("dedupe")
+                .action(clap::ArgAction::SetTrue)
+                .help("Drop duplicate records within each category"),
+        )
+        .arg(
+            Arg::new("dedupe-global")
+                .long("dedupe-global")
+                .action(clap::ArgAction::SetTrue)
+                .help("Drop the duplicates across all the categories, keeping the first in the input"),
+        )
+        .arg(
+            Arg::new("dedupe-key")
+                .long("dedupe-key")
+                .value_delimiter(',')
+                .requires("dedupe-mode")
+                .help("Comma separated columns identifying a duplicate, defaults to the whole record"),
+        )
+        // --dedupe-key identifies the duplicates of either dedupe
+        .group(
+            ArgGroup::new("dedupe-mode")
+                .args(["dedupe", "dedupe-global"])
+                .multiple(true),
+        )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Delimiter;
-    use clap::Command;
+    use crate::delimiter::Delimiter;
     use std::str::FromStr;
+    use std::sync::Mutex;
 
     #[test]
     fn test_parse_cli_command() {
@@ -66,4 +1054,280 @@ mod tests {
 
         assert_eq!(delimiter, Delimiter::SemiColon)
     }
+
+    /// Held by the tests reading the `CSV_SPLITTER_*` variables while another sets them,
+    /// the environment being shared by the test threads
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_config_layers() {
+        let _env_lock = ENV_LOCK.lock().unwrap();
+        let config: Table = r#"
+            column = "City"
+            dir = "from_file"
+            delimiter = ";"
+            dedupe = true
+            dedupe-key = ["City", "Population"]
+        "#
+        .parse()
+        .unwrap();
+        env::set_var("CSV_SPLITTER_DIR", "from_env");
+
+        let matches = build_cli(&config)
+            .unwrap()
+            .try_get_matches_from(vec!["test", "-p", "city.csv", "-d", "|"]);
+        env::remove_var("CSV_SPLITTER_DIR");
+        let matches = matches.unwrap();
+
+        let dedupe_key: Vec<&String> = matches.get_many::<String>("dedupe-key").unwrap().collect();
+        assert_eq!(matches.get_one::<String>("input-column").unwrap(), "City");
+        assert_eq!(matches.get_one::<String>("output-dir").unwrap(), "from_env");
+        assert_eq!(
+            matches.get_one::<DelimiterChoice>("delimiter").unwrap(),
+            &DelimiterChoice::Fixed(Delimiter::Pipe)
+        );
+        assert!(matches.get_flag("dedupe"));
+        assert_eq!(dedupe_key, vec!["City", "Population"]);
+    }
+
+    #[test]
+    fn test_resolve_output_dir() {
+        let matches = cli_command()
+            .try_get_matches_from(vec![
+                "test",
+                "-p",
+                "city.csv",
+                "-c",
+                "State",
+                "-o",
+                "out/%Y-%m-%d",
+                "--run-date",
+                "2024-06-01",
+            ])
+            .unwrap();
+
+        assert_eq!(
+            resolve_output_dir(&matches),
+            PathBuf::from("out/2024-06-01")
+        );
+    }
+
+    #[test]
+    fn test_read_buffer_range() {
+        let parse = |read_buffer: &str| {
+            cli_command().try_get_matches_from(vec![
+                "test",
+                "-p",
+                "city.csv",
+                "-c",
+                "State",
+                "-o",
+                "out",
+                "--read-buffer",
+                read_buffer,
+            ])
+        };
+
+        assert!(parse("0").is_err());
+        assert_eq!(
+            parse("1").unwrap().get_one::<usize>("read-buffer"),
+            Some(&1)
+        );
+    }
+
+    #[test]
+    fn test_inspect_category_without_dir() {
+        let matches = cli_command()
+            .try_get_matches_from(vec![
+                "test",
+                "-p",
+                "city.csv",
+                "-c",
+                "State",
+                "--inspect-category",
+                "AK",
+            ])
+            .unwrap();
+
+        assert_eq!(
+            matches
+                .get_one::<String>("inspect-category")
+                .map(String::as_str),
+            Some("AK")
+        );
+    }
+
+    #[test]
+    fn test_dedupe_modes() {
+        let parse = |dedupe_args: &[&str]| {
+            let mut args: Vec<&str> = vec!["test", "-p", "city.csv", "-c", "State", "-o", "out"];
+            args.extend_from_slice(dedupe_args);
+            cli_command().try_get_matches_from(args)
+        };
+
+        assert!(parse(&["--dedupe-global"])
+            .unwrap()
+            .get_flag("dedupe-global"));
+        assert!(parse(&["--dedupe-global", "--dedupe-key", "City"]).is_ok());
+        assert!(parse(&["--dedupe", "--dedupe-key", "City"]).is_ok());
+        assert_eq!(
+            parse(&["--dedupe-key", "City"]).unwrap_err().kind(),
+            clap::error::ErrorKind::MissingRequiredArgument
+        );
+    }
+
+    #[test]
+    fn test_xlsx_count_trailer() {
+        let result = cli_command().try_get_matches_from(vec![
+            "test",
+            "-p",
+            "city.csv",
+            "-c",
+            "State",
+            "--xlsx",
+            "city.xlsx",
+            "--count-trailer",
+            "comment",
+        ]);
+
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("10d").is_err());
+        assert!(parse_duration("18446744073709551615h").is_err());
+        assert_eq!(
+            parse_duration("18446744073709551615s").unwrap(),
+            Duration::from_secs(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_parse_alias() {
+        assert_eq!(
+            parse_alias("New York City=NY").unwrap(),
+            (String::from("New York City"), String::from("NY"))
+        );
+        assert_eq!(
+            parse_alias("a=b=c").unwrap(),
+            (String::from("a"), String::from("b=c"))
+        );
+        assert!(parse_alias("NYC").is_err());
+        assert!(parse_alias("=NY").is_err());
+    }
+
+    #[test]
+    fn test_parse_comment_char() {
+        assert_eq!(parse_comment_char(";").unwrap(), b';');
+        assert!(parse_comment_char("").is_err());
+        assert!(parse_comment_char("//").is_err());
+        assert!(parse_comment_char("§").is_err());
+    }
+
+    #[test]
+    fn test_parse_key_separator() {
+        assert_eq!(parse_key_separator("--").unwrap(), "--");
+        assert!(parse_key_separator("").is_err());
+        assert!(parse_key_separator("%").is_err());
+        assert!(parse_key_separator("a%b").is_err());
+    }
+
+    #[test]
+    fn test_config_repeated_values() {
+        let _env_lock = ENV_LOCK.lock().unwrap();
+        let config: Table = r#"
+            column = "City"
+            dir = "from_file"
+            filter = ["Population>100", "State==AK"]
+        "#
+        .parse()
+        .unwrap();
+
+        let matches = build_cli(&config)
+            .unwrap()
+            .try_get_matches_from(vec!["test", "-p", "city.csv"])
+            .unwrap();
+
+        assert_eq!(matches.get_many::<Predicate>("filter").unwrap().count(), 2);
+        let dumped: Table = dump_config(&matches).parse().unwrap();
+        let round_trip = build_cli(&dumped)
+            .unwrap()
+            .try_get_matches_from(vec!["test"])
+            .unwrap();
+        assert_eq!(
+            round_trip.get_many::<Predicate>("filter").unwrap().count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_dump_config() {
+        let _env_lock = ENV_LOCK.lock().unwrap();
+        let config: Table = r#"
+            column = "City"
+            dir = "from_file"
+            dedupe-key = ["City", "Population"]
+        "#
+        .parse()
+        .unwrap();
+
+        let matches = build_cli(&config)
+            .unwrap()
+            .try_get_matches_from(vec!["test", "-p", "city.csv", "-o", "from_cli", "--dedupe"])
+            .unwrap();
+        let dumped: Table = dump_config(&matches).parse().unwrap();
+
+        assert_eq!(dumped["dir"].as_str(), Some("from_cli"));
+        assert_eq!(dumped["column"].as_str(), Some("City"));
+        assert_eq!(
+            dumped["dedupe-key"],
+            Value::Array(vec![
+                Value::String(String::from("City")),
+                Value::String(String::from("Population"))
+            ])
+        );
+        assert_eq!(dumped["dedupe"].as_bool(), Some(true));
+        assert_eq!(dumped["delimiter-out"].as_str(), Some("|"));
+        assert!(!dumped.contains_key("dump-config"));
+    }
+
+    #[test]
+    fn test_config_unknown_key() {
+        let _env_lock = ENV_LOCK.lock().unwrap();
+        let config: Table = "colum = \"City\"".parse().unwrap();
+
+        let result = build_cli(&config);
+
+        assert!(matches!(result, Err(AppError::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn test_find_config_path() {
+        let args: Vec<OsString> = ["test", "-c", "State", "--config", "splitter.toml"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        let inline_args: Vec<OsString> = ["test", "--config=splitter.toml"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+
+        assert_eq!(
+            find_config_path(&args),
+            Some(PathBuf::from("splitter.toml"))
+        );
+        assert_eq!(
+            find_config_path(&inline_args),
+            Some(PathBuf::from("splitter.toml"))
+        );
+    }
 }
@@ -0,0 +1,89 @@
+use crate::app_error::AppError;
+use crate::data_filtering::{create_category_writer, SUCCESS_MARKER};
+use crate::data_loading::{read_file, ReaderConfig};
+use crate::delimiter::Delimiter;
+use crate::output_backend::OutputWriter;
+use crate::record_context::RecordProcessingContext;
+use crate::split_summary::SplitSummary;
+use csv::{Reader, StringRecord, Writer};
+use std::io::Read;
+use std::path::Path;
+
+/// Write each column of a CSV file to its own `<header>.csv` file, one value per line.
+/// The number of open files is bounded by the number of columns
+pub(crate) fn split_file_by_column(
+    path: &Path,
+    delimiter: &Delimiter,
+    context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let mut summary: SplitSummary = SplitSummary::default();
+    let mut reader: Reader<Box<dyn Read>> = read_file(
+        path,
+        &ReaderConfig {
+            flexible: false,
+            ..context.reader_config(delimiter)
+        },
+    )?;
+    let headers: StringRecord = reader.headers()?.clone();
+
+    let mut writers: Vec<Writer<OutputWriter>> = headers
+        .iter()
+        .map(|header| {
+            let column_context: RecordProcessingContext = RecordProcessingContext {
+                headers: StringRecord::from(vec![header]),
+                create_directory: false,
+                ..context.clone()
+            };
+            create_category_writer(header, &column_context, &mut summary)
+        })
+        .collect::<Result<_, _>>()?;
+
+    for result in reader.records() {
+        let record: StringRecord = result?;
+        for (writer, field) in writers.iter_mut().zip(record.iter()) {
+            writer.write_record([field])?;
+        }
+    }
+    for writer in writers.iter_mut() {
+        writer.flush()?;
+    }
+    if context.success_marker {
+        context
+            .output_backend
+            .create(&context.output_dir.join(SUCCESS_MARKER))?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_context::TestContext;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_split_file_by_column() {
+        let mut test_context = TestContext::new();
+        let output_dir = test_context.add_dir(PathBuf::from("assets/tmp/transpose_columns"));
+        let context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            ..Default::default()
+        };
+
+        let summary =
+            split_file_by_column(Path::new("assets/city.csv"), &Delimiter::Comma, &context);
+
+        let files: Vec<_> = ["City", "State", "Population", "Latitude", "Longitude"]
+            .iter()
+            .map(|header| output_dir.join(format!("{}.csv", header)))
+            .collect();
+        let all_exist = files.iter().all(|file| file.exists());
+        let state_data = fs::read_to_string(output_dir.join("State.csv")).unwrap();
+
+        assert_eq!(summary.unwrap().created.len(), 5);
+        assert!(all_exist);
+        assert!(state_data.starts_with("State\nAK\nAK\nAL\n"));
+        assert_eq!(state_data.lines().count(), 13);
+    }
+}
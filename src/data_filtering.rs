@@ -1,252 +1,4995 @@
-use crate::record_context::RecordProcessingContext;
-use csv::{Reader, StringRecord, StringRecordsIter, Writer, WriterBuilder};
-use std::collections::HashMap;
+use crate::app_error::AppError;
+use crate::category_inspecting::distinct_categories;
+use crate::data_loading::{extract_file_name, read_file, ReaderConfig};
+use crate::delimiter::Delimiter;
+use crate::json_expanding::JsonExpansion;
+use crate::key_expr::KeyExpr;
+use crate::output_backend::OutputWriter;
+use crate::output_encoding::encode_output;
+use crate::predicate::{self, FilterLogic};
+use crate::record_context::{
+    CategoryFreeze, CategoryProjection, ColumnMatch, ConcurrencyStrategy, CountTrailer,
+    InvalidCategoryPolicy, OversizedRecordPolicy, RecordProcessingContext, RowRange,
+    UnexpectedCategoryPolicy, UnmappedCategoryPolicy, WriteHeader, WriterUsage,
+};
+use crate::row_hashing::hash_row;
+use crate::schema_inferring::{self, TypeSampler};
+use crate::split_summary::SplitSummary;
+use csv::{
+    ByteRecord, Position, QuoteStyle, Reader, ReaderBuilder, StringRecord, Writer, WriterBuilder,
+};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
 use std::fs;
-use std::fs::{File, OpenOptions};
-use std::io::{BufWriter, Error};
-use std::path::PathBuf;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::string::String;
-use std::sync::MutexGuard;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, MutexGuard, RwLockWriteGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use dashmap::DashMap;
+use deunicode::deunicode;
 use rayon::prelude::*;
+use regex::Regex;
+use tracing::{event, Level};
 
-/// Write records to CSV file
-pub(crate) fn write_records_to_csv(
-    reader: &mut Reader<File>,
-    context: &RecordProcessingContext,
-) -> Result<(), Error> {
-    let chunk_size: usize = 100_000;
+/// Numbers the spilled segment files, unique within the process
+static SPILL_SEGMENT_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
-    let record_iter: StringRecordsIter<File> = reader.records();
-    let mut chunk: Vec<_> = Vec::with_capacity(chunk_size);
+/// File written to the output directory by `--success-marker` after a complete split
+pub(crate) const SUCCESS_MARKER: &str = "_SUCCESS";
+/// Header of the `--append-run-id-column` column
+pub(crate) const RUN_ID_COLUMN: &str = "run_id";
+/// Longest file or directory name most filesystems accept, in bytes
+const MAX_FILE_NAME_BYTES: usize = 255;
+/// Longest extension a flat category is named with, of its `--emit-schema` file. The
+/// `.csv` file and the `.count` sidecar have shorter ones
+const LONGEST_CATEGORY_EXTENSION: &str = ".schema.json";
+/// Category of the values missing from `--categories-file` with `--unexpected-category other`
+pub(crate) const OTHER_CATEGORY: &str = "other";
+/// Category of the records of an invalid category with `--on-invalid-category invalid`
+pub(crate) const INVALID_CATEGORY: &str = "invalid";
+/// OS error codes of an open failing for lack of file handles, ENFILE and EMFILE on Linux
+/// and macOS
+const OUT_OF_FILE_HANDLES: [i32; 2] = [23, 24];
+/// Delay before the first `--retry-open` retry
+const RETRY_OPEN_DELAY: Duration = Duration::from_millis(10);
+/// Longest delay before a `--retry-open` retry, however many came before
+const MAX_RETRY_OPEN_DELAY: Duration = Duration::from_secs(1);
+/// `--null-category` value dropping the records of the null category
+pub(crate) const SKIP_NULL_CATEGORY: &str = "skip";
+
+/// Split a CSV file into multiple files based on a column values. The writers of the
+/// context are reused, so several input files can accumulate into the same category files
+pub(crate) fn split_file_by_category(
+    path: &Path,
+    input_column: &str,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let summary: SplitSummary = split_file(path, &[(input_column, base_context)], delimiter)?;
+    record_manifest(path, &summary, base_context)?;
+    if !summary.timed_out {
+        write_success_marker(base_context)?;
+    }
+    Ok(summary)
+}
+
+/// Split several CSV files in parallel into the same category files, one rayon task per
+/// file. The writers are shared, each chunk holding them while it is written, so the
+/// records of a file keep their order but those of different files interleave
+pub(crate) fn split_files_by_category(
+    inputs: &[(PathBuf, Delimiter)],
+    input_column: &str,
+    base_context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let summary: SplitSummary = inputs
+        .par_iter()
+        .map(|(path, delimiter)| {
+            let summary: SplitSummary =
+                split_file(path, &[(input_column, base_context)], delimiter)?;
+            record_manifest(path, &summary, base_context)?;
+            Ok::<SplitSummary, AppError>(summary)
+        })
+        .try_reduce(SplitSummary::default, |mut summary, other| {
+            summary.merge(other);
+            Ok(summary)
+        })?;
+    if !summary.timed_out {
+        write_success_marker(base_context)?;
+    }
+    Ok(summary)
+}
 
-    for result in record_iter {
-        let record: StringRecord = result?;
-        chunk.push(record);
+/// Create the file of every category of a CSV file with just its headers, for
+/// `--headers-only`. A first pass only collects the distinct categories, no record being
+/// projected or written
+pub(crate) fn split_file_headers_only(
+    path: &Path,
+    input_column: &str,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let (categories, _, context) =
+        distinct_categories(path, input_column, delimiter, base_context, None)?;
+    let mut categories: Vec<String> = categories.into_iter().collect();
+    categories.sort();
 
-        if chunk.len() == chunk_size {
-            process_chunk(&chunk, context)?;
-            chunk.clear()
+    create_output_dir(&context)?;
+    let mut summary: SplitSummary = SplitSummary::default();
+    {
+        let mut writers: MutexGuard<HashMap<String, Writer<OutputWriter>>> =
+            context.writers.lock().unwrap();
+        for category in categories {
+            if writers.contains_key(&category) {
+                continue;
+            }
+            if context.invalid_category_policy == InvalidCategoryPolicy::Skip
+                && !is_valid_category(&category, &context)
+            {
+                event!(Level::WARN, "Skipping the invalid category {:?}", category);
+                continue;
+            }
+            let writer: Writer<OutputWriter> =
+                create_category_writer(&category, &context, &mut summary)?;
+            writers.insert(category, writer);
         }
     }
-    if !chunk.is_empty() {
-        process_chunk(&chunk, context)?;
+    flush_writers(&context)?;
+    write_success_marker(base_context)?;
+    Ok(summary)
+}
+
+/// Split a CSV file independently by each column in a single read, the categories of a
+/// column going to the `by_<column>` directory with writers of their own
+pub(crate) fn split_file_by_columns(
+    path: &Path,
+    input_columns: &[String],
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let mut splits: Vec<(&str, RecordProcessingContext)> = Vec::with_capacity(input_columns.len());
+    for column in input_columns {
+        if column.contains("..") || column.contains('/') || column.contains('\\') {
+            return Err(AppError::InvalidArguments(format!(
+                "Invalid column name for a directory: {}",
+                column
+            )));
+        }
+        splits.push((
+            column,
+            split_context(&format!("by_{}", column), base_context)?,
+        ));
+    }
+    split_file_independently(path, &splits, delimiter, base_context)
+}
+
+/// Split a CSV file by each named group of `--key-regex` in a single read, the group
+/// captured from the split column value giving the categories of the `by_<group>`
+/// directory
+pub(crate) fn split_file_by_key_groups(
+    path: &Path,
+    input_column: &str,
+    key_regex: &Regex,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let mut splits: Vec<(&str, RecordProcessingContext)> = Vec::new();
+    for group in key_regex.capture_names().flatten() {
+        splits.push((
+            input_column,
+            RecordProcessingContext {
+                key_group: Some((key_regex.clone(), group.to_string())),
+                ..split_context(&format!("by_{}", group), base_context)?
+            },
+        ));
+    }
+    split_file_independently(path, &splits, delimiter, base_context)
+}
+
+/// Context of one of the independent splits of a single read, writing to its own
+/// directory of the output directory with writers of its own
+fn split_context(
+    dir_name: &str,
+    base_context: &RecordProcessingContext,
+) -> Result<RecordProcessingContext, AppError> {
+    let output_dir: PathBuf = base_context.output_dir.join(dir_name);
+    base_context.output_backend.create_dir_all(&output_dir)?;
+    Ok(RecordProcessingContext {
+        output_dir,
+        split_dir: Some(dir_name.to_string()),
+        writers: Arc::default(),
+        seen_records: Arc::default(),
+        global_seen_records: Arc::default(),
+        category_buffers: Arc::default(),
+        spilled_segments: Arc::default(),
+        file_categories: Arc::default(),
+        category_rows: Arc::default(),
+        category_samplers: Arc::default(),
+        writer_usage: Arc::default(),
+        category_freeze: Arc::default(),
+        assigned_categories: Arc::default(),
+        ..base_context.clone()
+    })
+}
+
+/// Run the independent splits of a single read, writing the success marker of the output
+/// directory once they all completed
+fn split_file_independently(
+    path: &Path,
+    splits: &[(&str, RecordProcessingContext)],
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let splits: Vec<(&str, &RecordProcessingContext)> = splits
+        .iter()
+        .map(|(input_column, context)| (*input_column, context))
+        .collect();
+    let summary: SplitSummary = split_file(path, &splits, delimiter)?;
+    if !summary.timed_out {
+        write_success_marker(base_context)?;
+    }
+    Ok(summary)
+}
+
+/// Read a CSV file once, feeding every record to each split column with its own context
+fn split_file(
+    path: &Path,
+    splits: &[(&str, &RecordProcessingContext)],
+    delimiter: &Delimiter,
+) -> Result<SplitSummary, AppError> {
+    let reader_config: ReaderConfig = splits[0].1.reader_config(delimiter);
+    let mut reader: Reader<Box<dyn Read>> = read_file(path, &reader_config)?;
+    let headers: StringRecord = reader.headers()?.clone();
+    for (_, base_context) in splits {
+        check_expected_headers(path, &headers, base_context)?;
+        check_schema_drift(path, &headers, base_context)?;
     }
+    // The split sees the headers of the expanded JSON column, its keys being data
+    // dependent they take a first pass over the input
+    let json_expansion: Option<Arc<JsonExpansion>> = match &splits[0].1.expand_json {
+        Some(column) => Some(Arc::new(JsonExpansion::scan(path, &reader_config, column)?)),
+        None => None,
+    };
+    let headers: StringRecord = match &json_expansion {
+        Some(json_expansion) => json_expansion.headers(&headers),
+        None => headers,
+    };
+    let contexts: Vec<RecordProcessingContext> = splits
+        .iter()
+        .map(|(input_column, base_context)| {
+            let context: RecordProcessingContext =
+                prepare_context(path, &headers, input_column, delimiter, base_context)?;
+            Ok(RecordProcessingContext {
+                json_expansion: json_expansion.clone(),
+                ..context
+            })
+        })
+        .collect::<Result<Vec<RecordProcessingContext>, AppError>>()?;
+
+    create_output_dir(&contexts[0])?;
+    open_tee(&contexts[0])?;
+    let mut summary: SplitSummary = SplitSummary::default();
+    for context in &contexts {
+        summary.merge(create_expected_writers(context)?);
+    }
+    summary.merge(write_records_to_csv(&mut reader, &contexts)?);
+    // The writer thread only reports its skipped records once done
+    check_max_errors(&summary, &contexts[0])?;
+    for context in &contexts {
+        if context.sort_by.is_some() {
+            summary.merge(write_sorted_records(context)?);
+        }
+        // The schemas are written before the trailers, which have no place in a schema
+        if context.emit_schema {
+            write_schemas(context)?;
+        }
+        if let Some(count_trailer) = context.count_trailer {
+            write_count_trailers(count_trailer, context)?;
+        }
+        flush_writers(context)?;
+    }
+    Ok(summary)
+}
 
+/// Create the flat output directory and its parents with `--force-create-dir`, the
+/// category directories of `--create-dir` being created as their first record comes
+pub(crate) fn create_output_dir(context: &RecordProcessingContext) -> Result<(), Error> {
+    if context.force_create_dir {
+        context.output_backend.create_dir_all(&context.output_dir)?;
+    }
     Ok(())
 }
 
-/// Process records in parallel
-fn process_chunk(
-    chunk: &Vec<StringRecord>,
-    context: &RecordProcessingContext,
-) -> Result<(), Error> {
-    let writers: HashMap<String, Vec<StringRecord>> = filter_records(chunk, context);
-    write_records(writers, context)?;
+/// Create the `--tee` file with the output headers, once for all the inputs split with
+/// the same writers
+fn open_tee(context: &RecordProcessingContext) -> Result<(), AppError> {
+    let Some(tee_path) = &context.tee_path else {
+        return Ok(());
+    };
+    let mut tee_writer: MutexGuard<Option<Writer<File>>> = context.tee_writer.lock().unwrap();
+    if tee_writer.is_none() {
+        let mut writer: Writer<File> = WriterBuilder::new()
+            .delimiter(context.delimiter)
+            .flexible(context.allow_schema_drift)
+            .quote_style(quote_style("", context))
+            .from_path(tee_path)?;
+        write_output_record(&mut writer, &context.headers, "", context)?;
+        *tee_writer = Some(writer);
+    }
     Ok(())
 }
 
-/// Filter records by category
-fn filter_records(
-    chunk: &Vec<StringRecord>,
+/// Create the files of the `--categories-file` categories before any record is read, so
+/// a category without records still gets a file with just the headers
+fn create_expected_writers(context: &RecordProcessingContext) -> Result<SplitSummary, AppError> {
+    let mut summary: SplitSummary = SplitSummary::default();
+    let mut writers: MutexGuard<HashMap<String, Writer<OutputWriter>>> =
+        context.writers.lock().unwrap();
+    let mut categories: Vec<&String> = context.expected_categories.iter().collect();
+    categories.sort();
+    for category in categories {
+        if !writers.contains_key(category) {
+            let writer: Writer<OutputWriter> =
+                open_category_writer(category, &mut writers, context, &mut summary)?;
+            writers.insert(category.clone(), writer);
+            if context.retry_open > 0 {
+                context.writer_usage.lock().unwrap().touch(category);
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Check the headers of an input against the first input split with the same writers,
+/// the difference is only a warning with `--allow-schema-drift`
+pub(crate) fn check_schema_drift(
+    path: &Path,
+    headers: &StringRecord,
     context: &RecordProcessingContext,
-) -> HashMap<String, Vec<StringRecord>> {
-    chunk
-        .par_iter()
-        .fold_with(
-            HashMap::new(),
-            |mut acc: HashMap<String, Vec<StringRecord>>, record| {
-                let category: String = get_category(record, context);
-                let filtered_records: StringRecord = context
-                    .header_indexes
-                    .iter()
-                    .filter_map(|&idx| record.get(idx).map(|field| field.to_string()))
-                    .collect();
-                acc.entry(category).or_default().push(filtered_records);
-                acc
-            },
+) -> Result<(), AppError> {
+    let mut first_headers: MutexGuard<Option<(String, StringRecord)>> =
+        context.first_headers.lock().unwrap();
+    let Some((first_file, expected)) = first_headers.as_ref() else {
+        *first_headers = Some((path.display().to_string(), headers.clone()));
+        return Ok(());
+    };
+    let Some(difference) = header_difference(headers, expected) else {
+        return Ok(());
+    };
+
+    if context.allow_schema_drift {
+        event!(
+            Level::WARN,
+            "The headers of {:?} differ from {}: {}",
+            path,
+            first_file,
+            difference
+        );
+        return Ok(());
+    }
+    Err(AppError::SchemaDrift {
+        file: path.display().to_string(),
+        first_file: first_file.clone(),
+        difference,
+    })
+}
+
+/// Check the headers of an input against `--expect-headers`, exactly or with
+/// `--expect-headers-subset` only requiring the expected columns to be present
+pub(crate) fn check_expected_headers(
+    path: &Path,
+    headers: &StringRecord,
+    context: &RecordProcessingContext,
+) -> Result<(), AppError> {
+    if context.expected_headers.is_empty() {
+        return Ok(());
+    }
+    let difference: Option<String> = if context.expect_headers_subset {
+        let missing: Vec<&str> = context
+            .expected_headers
+            .iter()
+            .filter(|expected| !headers.iter().any(|header| header == expected.as_str()))
+            .map(String::as_str)
+            .collect();
+        (!missing.is_empty()).then(|| format!("missing columns {:?}", missing))
+    } else {
+        header_difference(
+            headers,
+            &StringRecord::from(context.expected_headers.clone()),
         )
-        .reduce(HashMap::new, |mut acc, map| {
-            for (key, mut value) in map {
-                acc.entry(key).or_default().append(&mut value);
-            }
-            acc
+    };
+    match difference {
+        Some(difference) => Err(AppError::UnexpectedHeaders {
+            file: path.display().to_string(),
+            difference,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Describe the first difference between headers and the expected ones, if any
+fn header_difference(headers: &StringRecord, expected: &StringRecord) -> Option<String> {
+    if headers.len() != expected.len() {
+        return Some(format!(
+            "{} columns instead of {}",
+            headers.len(),
+            expected.len()
+        ));
+    }
+    headers
+        .iter()
+        .zip(expected.iter())
+        .position(|(header, expected)| header != expected)
+        .map(|idx| {
+            format!(
+                "column {} is {:?} instead of {:?}",
+                idx, &headers[idx], &expected[idx]
+            )
         })
 }
 
-/// Write records to CSV file
-fn write_records(
-    writers: HashMap<String, Vec<StringRecord>>,
-    context: &RecordProcessingContext,
-) -> Result<(), Error> {
-    let mut context_writers: MutexGuard<HashMap<String, Writer<BufWriter<File>>>> =
-        context.writers.lock().unwrap();
-    for (category, records) in writers {
-        let writer: &mut Writer<BufWriter<File>> =
-            context_writers.entry(category.clone()).or_insert_with(|| {
-                let file_path: PathBuf = create_category_path(&category, context).unwrap();
-                let file_exists: bool = file_path.exists();
-                let file: File = OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(&file_path)
-                    .unwrap();
+/// Resolve the column indexes of a split against the headers of the input
+pub(crate) fn prepare_context(
+    path: &Path,
+    headers: &StringRecord,
+    input_column: &str,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<RecordProcessingContext, AppError> {
+    let file_name: String = if base_context.file_name.is_empty() {
+        extract_file_name(path)?
+    } else {
+        base_context.file_name.clone()
+    };
+
+    // Get the index of the column to split by, none for a key expression as every column
+    // is then written
+    let (split_column_idx, key_expr): (Option<usize>, Option<KeyExpr>) =
+        match &base_context.key_expr {
+            Some(key_expr) => (None, Some(key_expr.bind(headers)?)),
+            None => (
+                Some(find_split_column(
+                    headers,
+                    input_column,
+                    base_context.column_match,
+                )?),
+                None,
+            ),
+        };
+    let split_key: String = match split_column_idx {
+        Some(split_column_idx) => format!(
+            "split column {:?} at index {}",
+            input_column, split_column_idx
+        ),
+        None => format!("key expression {:?}", input_column),
+    };
+    event!(
+        Level::INFO,
+        "Effective configuration: input delimiter {:?}, output delimiter {:?}, {}, {} headers, {} output",
+        delimiter.as_char(),
+        char::from(base_context.delimiter),
+        split_key,
+        headers.len(),
+        if base_context.create_directory { "create-dir" } else { "flat" }
+    );
+    let filter_column_indexes: Vec<usize> = base_context
+        .filters
+        .iter()
+        .map(|filter| {
+            headers
+                .iter()
+                .position(|h| h == filter.column())
+                .ok_or_else(|| AppError::ColumnNotFound(filter.column().to_string()))
+        })
+        .collect::<Result<Vec<usize>, AppError>>()?;
+    let file_headers: StringRecord = drop_columns(
+        get_headers(headers, split_column_idx),
+        headers,
+        &base_context.dropped_columns,
+    )?;
+    if base_context.raw_output && file_headers.len() != 1 {
+        return Err(AppError::InvalidArguments(format!(
+            "--delimiter-out none needs a single output column, found {}",
+            file_headers.len()
+        )));
+    }
+    let header_indexes: Vec<usize> = get_header_indexes(headers, &file_headers);
+    let dedupe_key_indexes: Option<Vec<usize>> =
+        get_dedupe_key_indexes(&base_context.dedupe_key, headers, &file_headers)?;
+    let sort_column_idx: Option<usize> = match &base_context.sort_by {
+        Some(sort_key) => get_sort_column_idx(&sort_key.column, headers, &file_headers)?,
+        None => None,
+    };
+    let mut category_projections: HashMap<String, CategoryProjection> = HashMap::new();
+    for (category, columns) in base_context.header_map.iter() {
+        let category_headers: StringRecord = StringRecord::from(columns.clone());
+        let indexes: Vec<usize> = columns
+            .iter()
+            .map(|column| {
+                headers
+                    .iter()
+                    .position(|h| h == column)
+                    .ok_or_else(|| AppError::ColumnNotFound(column.to_string()))
+            })
+            .collect::<Result<Vec<usize>, AppError>>()?;
+        if base_context.raw_output && indexes.len() != 1 {
+            return Err(AppError::InvalidArguments(format!(
+                "--delimiter-out none needs a single output column, found {} for {:?}",
+                indexes.len(),
+                category
+            )));
+        }
+        category_projections.insert(
+            category.clone(),
+            (
+                indexes,
+                add_appended_headers(
+                    transform_headers(category_headers, base_context)?,
+                    base_context,
+                ),
+            ),
+        );
+    }
+    let file_headers: StringRecord =
+        add_appended_headers(transform_headers(file_headers, base_context)?, base_context);
 
-                let buf_writer: BufWriter<File> = BufWriter::new(file);
-                let mut csv_writer: Writer<BufWriter<File>> = WriterBuilder::new()
-                    .delimiter(context.delimiter)
-                    .from_writer(buf_writer);
+    Ok(RecordProcessingContext {
+        headers: file_headers,
+        file_name,
+        split_column_idx,
+        key_expr,
+        header_indexes,
+        dedupe_key_indexes,
+        sort_column_idx,
+        filter_column_indexes,
+        category_projections: Arc::new(category_projections),
+        ..base_context.clone()
+    })
+}
 
-                if !file_exists {
-                    csv_writer.write_record(&context.headers).unwrap();
-                }
+/// Index of the split column among the headers. A header named exactly like the column
+/// wins, otherwise the column is matched as a prefix or glob with `--column-match`,
+/// which has to match a single header
+fn find_split_column(
+    headers: &StringRecord,
+    input_column: &str,
+    column_match: ColumnMatch,
+) -> Result<usize, AppError> {
+    if let Some(idx) = headers.iter().position(|h| h == input_column) {
+        return Ok(idx);
+    }
+    let pattern: Option<glob::Pattern> = match column_match {
+        ColumnMatch::Glob => Some(glob::Pattern::new(input_column).map_err(|error| {
+            AppError::InvalidArguments(format!(
+                "Invalid column pattern {:?}: {}",
+                input_column, error
+            ))
+        })?),
+        _ => None,
+    };
+    let matching: Vec<(usize, &str)> = headers
+        .iter()
+        .enumerate()
+        .filter(|(_, header)| match (column_match, &pattern) {
+            (ColumnMatch::Prefix, _) => header.starts_with(input_column),
+            (ColumnMatch::Glob, Some(pattern)) => pattern.matches(header),
+            _ => false,
+        })
+        .collect();
+    match matching.as_slice() {
+        [] => Err(AppError::ColumnNotFound(input_column.to_string())),
+        [(idx, header)] => {
+            event!(
+                Level::INFO,
+                "Resolved the column {:?} to the header {:?}",
+                input_column,
+                header
+            );
+            Ok(*idx)
+        }
+        _ => Err(AppError::AmbiguousColumn {
+            pattern: input_column.to_string(),
+            matches: matching
+                .iter()
+                .map(|(_, header)| format!("{:?}", header))
+                .collect::<Vec<String>>()
+                .join(", "),
+        }),
+    }
+}
 
-                csv_writer
+/// Rewrite output headers with the header transform, if any
+fn transform_headers(
+    headers: StringRecord,
+    context: &RecordProcessingContext,
+) -> Result<StringRecord, AppError> {
+    let Some(transform) = &context.header_transform else {
+        return Ok(headers);
+    };
+    // Header of each transformed header, two of them written alike being ambiguous
+    let mut originals: HashMap<String, &str> = HashMap::new();
+    let mut transformed: StringRecord = StringRecord::new();
+    for header in headers.iter() {
+        let output_header: String = transform(header);
+        if let Some(first) = originals.insert(output_header.clone(), header) {
+            return Err(AppError::HeaderCollision {
+                first: first.to_string(),
+                second: header.to_string(),
+                header: output_header,
             });
-
-        for record in records {
-            writer.write_record(&record)?;
         }
-        writer.flush()?;
+        transformed.push_field(&output_header);
+    }
+    Ok(transformed)
+}
+
+/// Add the `--row-hash-column` and `--append-run-id-column` headers after the written
+/// columns, if any. They aren't input columns, so they go after the indexes of the written
+/// columns are resolved
+fn add_appended_headers(
+    mut headers: StringRecord,
+    context: &RecordProcessingContext,
+) -> StringRecord {
+    if let Some(row_hash_column) = &context.row_hash_column {
+        headers.push_field(row_hash_column);
+    }
+    if context.run_id_column.is_some() {
+        headers.push_field(RUN_ID_COLUMN);
+    }
+    headers
+}
+
+/// Add a split input to the `--manifest`, if any, once its records are flushed
+pub(crate) fn record_manifest(
+    path: &Path,
+    summary: &SplitSummary,
+    context: &RecordProcessingContext,
+) -> Result<(), Error> {
+    if let Some(manifest) = &context.manifest {
+        let category_rows: MutexGuard<HashMap<String, (PathBuf, usize)>> =
+            context.category_rows.lock().unwrap();
+        manifest
+            .lock()
+            .unwrap()
+            .record(path, summary, &category_rows)?;
     }
     Ok(())
 }
 
-/// Get the category value from a record
-#[inline]
-fn get_category(record: &StringRecord, context: &RecordProcessingContext) -> String {
-    match record.get(context.split_column_idx) {
-        Some(category) => category.to_string(),
-        _ => String::from("unknown"),
+/// Write the `--success-marker` file once every split file has been flushed
+pub(crate) fn write_success_marker(context: &RecordProcessingContext) -> Result<(), Error> {
+    if context.success_marker {
+        context
+            .output_backend
+            .create(&context.output_dir.join(SUCCESS_MARKER))?;
     }
+    Ok(())
 }
 
-/// Get headers
-pub(crate) fn get_headers(current_headers: &StringRecord, split_column_id: usize) -> StringRecord {
-    let headers: Vec<String> = current_headers
-        .iter()
-        .enumerate()
-        .filter_map(|(idx, field)| {
-            if idx != split_column_id {
-                Some(field.to_string())
+/// Write the records buffered for `--sort-within-category`, sorted within each category.
+/// Categories spilled to disk are merged with their segments
+fn write_sorted_records(context: &RecordProcessingContext) -> Result<SplitSummary, AppError> {
+    let buffers: HashMap<String, Vec<StringRecord>> =
+        std::mem::take(&mut *context.category_buffers.lock().unwrap());
+    let mut spilled_segments: HashMap<String, Vec<PathBuf>> =
+        std::mem::take(&mut *context.spilled_segments.lock().unwrap());
+    let mut summary: SplitSummary = SplitSummary::default();
+    let mut in_memory: HashMap<String, Vec<StringRecord>> = HashMap::new();
+    for (category, mut records) in buffers {
+        sort_records(&mut records, context);
+        match spilled_segments.remove(&category) {
+            Some(segments) => summary.merge(merge_spilled_records(
+                &category, records, segments, context,
+            )?),
+            None => {
+                in_memory.insert(category, records);
+            }
+        }
+    }
+    summary.merge(write_records(in_memory, context)?);
+    Ok(summary)
+}
+
+/// Sort records by the `--sort-within-category` column, keeping the input order of ties
+fn sort_records(records: &mut [StringRecord], context: &RecordProcessingContext) {
+    records.sort_by(|a, b| compare_records(a, b, context));
+}
+
+/// Compare two records by the sort column, they are equal when sorting by the split column
+fn compare_records(
+    a: &StringRecord,
+    b: &StringRecord,
+    context: &RecordProcessingContext,
+) -> Ordering {
+    match (&context.sort_by, context.sort_column_idx) {
+        (Some(sort_key), Some(idx)) => {
+            let ordering: Ordering = compare_fields(
+                a.get(idx).unwrap_or_default(),
+                b.get(idx).unwrap_or_default(),
+            );
+            if sort_key.descending {
+                ordering.reverse()
             } else {
-                None
+                ordering
             }
-        })
-        .collect();
-    StringRecord::from(headers)
+        }
+        _ => Ordering::Equal,
+    }
 }
 
-/// Get the header indexes
-pub(crate) fn get_header_indexes(
-    headers: &StringRecord,
-    file_headers: &StringRecord,
-) -> Vec<usize> {
-    file_headers
-        .iter()
-        .filter_map(|header| headers.iter().position(|h| h == header))
-        .collect()
+/// Sort the buffer of a category and write it to a temporary segment file
+fn spill_records(
+    category: &str,
+    mut records: Vec<StringRecord>,
+    context: &RecordProcessingContext,
+) -> Result<(), AppError> {
+    sort_records(&mut records, context);
+    let segment: PathBuf = env::temp_dir().join(format!(
+        "csv-splitter-{}-{}.csv",
+        process::id(),
+        SPILL_SEGMENT_COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+    ));
+    event!(
+        Level::DEBUG,
+        "Spilling {} records of {} to {:?}",
+        records.len(),
+        category,
+        segment
+    );
+    let mut writer: Writer<File> = WriterBuilder::new().from_path(&segment)?;
+    for record in &records {
+        writer.write_record(record)?;
+    }
+    writer.flush()?;
+    context
+        .spilled_segments
+        .lock()
+        .unwrap()
+        .entry(category.to_string())
+        .or_default()
+        .push(segment);
+    Ok(())
 }
 
-/// Create a path for a category
-fn create_category_path(
+/// Merge the sorted segments of a category with its remaining sorted records, writing
+/// them in batches of `chunk_size` and removing the segments afterwards
+fn merge_spilled_records(
     category: &str,
+    records: Vec<StringRecord>,
+    segments: Vec<PathBuf>,
     context: &RecordProcessingContext,
-) -> Result<PathBuf, Error> {
-    if category.contains("..") || category.contains('/') || category.contains("\\") {
-        panic!("Invalid category name: {}", category);
+) -> Result<SplitSummary, AppError> {
+    let mut sources: Vec<Box<dyn Iterator<Item = Result<StringRecord, csv::Error>>>> =
+        Vec::with_capacity(segments.len() + 1);
+    for segment in &segments {
+        let reader: Reader<File> = ReaderBuilder::new().has_headers(false).from_path(segment)?;
+        sources.push(Box::new(reader.into_records()));
     }
-    let file_path: PathBuf = if context.create_directory {
-        let dir: PathBuf = context.output_dir.join(category);
-        if !dir.exists() {
-            fs::create_dir_all(&dir)?;
+    // The in-memory records come last in the input, so they go last on ties
+    sources.push(Box::new(records.into_iter().map(Ok)));
+    let mut heads: Vec<Option<StringRecord>> = sources
+        .iter_mut()
+        .map(|source| source.next().transpose())
+        .collect::<Result<Vec<Option<StringRecord>>, csv::Error>>()?;
+
+    let mut summary: SplitSummary = SplitSummary::default();
+    let mut batch: Vec<StringRecord> = Vec::with_capacity(context.chunk_size);
+    while let Some(idx) = heads
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, head)| head.as_ref().map(|record| (idx, record)))
+        .min_by(|(_, a), (_, b)| compare_records(a, b, context))
+        .map(|(idx, _)| idx)
+    {
+        batch.extend(heads[idx].take());
+        heads[idx] = sources[idx].next().transpose()?;
+        if batch.len() == context.chunk_size {
+            let records: Vec<StringRecord> = std::mem::take(&mut batch);
+            summary.merge(write_records(
+                HashMap::from([(category.to_string(), records)]),
+                context,
+            )?);
         }
-        dir.join(format!("{}.csv", context.file_name))
-    } else {
-        context.output_dir.join(format!("{}.csv", category))
-    };
-    Ok(file_path)
+    }
+    if !batch.is_empty() {
+        summary.merge(write_records(
+            HashMap::from([(category.to_string(), batch)]),
+            context,
+        )?);
+    }
+    for segment in &segments {
+        fs::remove_file(segment)?;
+    }
+    Ok(summary)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use lazy_static::lazy_static;
-    use std::path::PathBuf;
+/// Compare two fields numerically when both are numbers, as text otherwise. Numbers
+/// sort before text
+pub(crate) fn compare_fields(a: &str, b: &str) -> Ordering {
+    predicate::compare(a, b).unwrap_or_else(|| {
+        if a.trim().parse::<f64>().is_ok() {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        }
+    })
+}
 
-    lazy_static! {
-        static ref FILE_HEADERS: StringRecord = StringRecord::from(vec!["city", "state"]);
-        static ref HEADERS: StringRecord = StringRecord::from(vec!["city", "state", "year"]);
+/// Flush every open category writer
+fn flush_writers(context: &RecordProcessingContext) -> Result<(), Error> {
+    let mut writers: MutexGuard<HashMap<String, Writer<OutputWriter>>> =
+        context.writers.lock().unwrap();
+    for writer in writers.values_mut() {
+        writer.flush()?;
+    }
+    if let Some(tee_writer) = context.tee_writer.lock().unwrap().as_mut() {
+        tee_writer.flush()?;
     }
+    Ok(())
+}
 
-    struct TestContext {
-        files: Vec<PathBuf>,
+/// Write the `.schema.json` file of each category file, from the types of its first
+/// records
+fn write_schemas(context: &RecordProcessingContext) -> Result<(), Error> {
+    let category_rows: MutexGuard<HashMap<String, (PathBuf, usize)>> =
+        context.category_rows.lock().unwrap();
+    let samplers: MutexGuard<HashMap<String, TypeSampler>> =
+        context.category_samplers.lock().unwrap();
+    for (category, (file_path, _)) in category_rows.iter() {
+        let headers: &StringRecord = context
+            .category_projections
+            .get(category)
+            .map_or(&context.headers, |(_, headers)| headers);
+        let columns = samplers
+            .get(category)
+            .cloned()
+            .unwrap_or_default()
+            .columns(headers);
+        let mut output: OutputWriter = context
+            .output_backend
+            .create(&file_path.with_extension("schema.json"))?;
+        writeln!(output, "{}", schema_inferring::schema_json(&columns))?;
+        output.flush()?;
     }
+    Ok(())
+}
 
-    impl TestContext {
-        fn new() -> Self {
-            TestContext { files: Vec::new() }
+/// Write the records count of each category file after all its records, as a last
+/// `#ROWS=<count>` line or in a `.count` file next to it
+fn write_count_trailers(
+    count_trailer: CountTrailer,
+    context: &RecordProcessingContext,
+) -> Result<(), Error> {
+    let mut writers: MutexGuard<HashMap<String, Writer<OutputWriter>>> =
+        context.writers.lock().unwrap();
+    let category_rows: MutexGuard<HashMap<String, (PathBuf, usize)>> =
+        context.category_rows.lock().unwrap();
+    let writer_usage: MutexGuard<WriterUsage> = context.writer_usage.lock().unwrap();
+    for (category, (file_path, rows)) in category_rows.iter() {
+        match count_trailer {
+            CountTrailer::Comment => {
+                // The trailer follows the last record, so the writer isn't needed anymore
+                let output: Option<OutputWriter> = match writers.remove(category) {
+                    Some(writer) => Some(writer.into_inner().map_err(|error| error.into_error())?),
+                    // Closed by --retry-open to free its file handle
+                    None if writer_usage.is_closed(category) => {
+                        Some(context.output_backend.open_append(file_path)?)
+                    }
+                    None => None,
+                };
+                if let Some(mut output) = output {
+                    let comment_char: char = context.comment_char.unwrap_or(b'#').into();
+                    writeln!(output, "{}ROWS={}", comment_char, rows)?;
+                    output.flush()?;
+                }
+            }
+            CountTrailer::Sidecar => {
+                let mut output: OutputWriter = context
+                    .output_backend
+                    .create(&file_path.with_extension("count"))?;
+                writeln!(output, "{}", rows)?;
+                output.flush()?;
+            }
         }
+    }
+    Ok(())
+}
 
-        fn add_file(&mut self, file_path: PathBuf) {
-            self.files.push(file_path);
-        }
+/// Write records to CSV file, each chunk going through every split context. With
+/// `--writer-thread` the writing is handed to a dedicated thread
+pub(crate) fn write_records_to_csv<R: Read>(
+    reader: &mut Reader<R>,
+    contexts: &[RecordProcessingContext],
+) -> Result<SplitSummary, AppError> {
+    if contexts[0].writer_thread {
+        return write_records_on_writer_thread(reader, contexts);
     }
+    if contexts[0].sorted_input {
+        return write_sorted_input(reader, &contexts[0]);
+    }
+    read_chunks(reader, &contexts[0], |chunk: &Vec<StringRecord>| {
+        let mut summary: SplitSummary = SplitSummary::default();
+        for context in contexts {
+            summary.merge(process_chunk(chunk, context)?);
+        }
+        Ok(summary)
+    })
+}
 
-    impl Drop for TestContext {
-        fn drop(&mut self) {
-            for file in &self.files {
-                if file.exists() {
-                    fs::remove_file(file).unwrap();
+/// Write an input sorted by the split column in a single sequential pass, each run of
+/// records of a category going to its writer, which is closed once the key changes. A
+/// category coming back is appended to its file again
+fn write_sorted_input<R: Read>(
+    reader: &mut Reader<R>,
+    context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let mut current: Option<String> = None;
+    let mut closed: HashSet<String> = HashSet::new();
+    let mut warned: bool = false;
+    read_chunks(reader, context, |chunk: &Vec<StringRecord>| {
+        let mut summary: SplitSummary = SplitSummary::default();
+        let (kept, global_duplicates_removed) = drop_global_duplicates(chunk, context);
+        summary.global_duplicates_removed += global_duplicates_removed;
+        let mut run: Vec<StringRecord> = Vec::new();
+        for record in kept
+            .as_ref()
+            .unwrap_or(chunk)
+            .iter()
+            .filter(|record| matches_filters(record, context))
+        {
+            let (category, fields) = project_record(record, context);
+            if current.as_ref() != Some(&category) {
+                if let Some(previous) = current.replace(category.clone()) {
+                    if !run.is_empty() {
+                        let records: Vec<StringRecord> = std::mem::take(&mut run);
+                        summary.merge(write_records(
+                            HashMap::from([(previous.clone(), records)]),
+                            context,
+                        )?);
+                    }
+                    if let Some(mut writer) = context.writers.lock().unwrap().remove(&previous) {
+                        writer.flush()?;
+                    }
+                    closed.insert(previous);
+                }
+                if context.check_sorted && !warned && closed.contains(&category) {
+                    event!(
+                        Level::WARN,
+                        "The input isn't sorted by the split column, {:?} comes back at line {}",
+                        category,
+                        record.position().map_or(0, |position| position.line())
+                    );
+                    warned = true;
                 }
             }
+            run.push(fields);
         }
+        if let (Some(category), false) = (&current, run.is_empty()) {
+            summary.merge(write_records(
+                HashMap::from([(category.clone(), run)]),
+                context,
+            )?);
+        }
+        Ok(summary)
+    })
+}
+
+/// Record of the input as `read_chunks` reads it, decoded as UTF-8 or left as raw
+/// bytes for `--bytes`
+pub(crate) trait InputRecord: Sized {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> csv::Result<Option<Self>>;
+    fn position(&self) -> Option<&Position>;
+    fn field_count(&self) -> usize;
+    /// Bytes of the record without its delimiters
+    fn byte_len(&self) -> usize;
+    /// Whether the record is a blank line, read as a single empty or whitespace field
+    fn is_blank(&self) -> bool;
+    /// The record with the carriage returns ending its last field removed
+    fn trim_carriage_returns(self) -> Self;
+    /// The record with the `--expand-json` column expanded, when one was given
+    fn expand(self, context: &RecordProcessingContext) -> Result<Self, AppError>;
+}
+
+impl InputRecord for StringRecord {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> csv::Result<Option<Self>> {
+        let mut record: StringRecord = StringRecord::new();
+        Ok(reader.read_record(&mut record)?.then_some(record))
     }
 
-    #[test]
-    fn test_split_file_by_category() {
-        let mut context = TestContext::new();
+    fn position(&self) -> Option<&Position> {
+        StringRecord::position(self)
+    }
 
-        let input_file = PathBuf::from("assets/city.csv");
-        let output_dir = PathBuf::from("assets/tmp");
-        let delimiter = Delimiter::Comma;
-        let input_column = "State";
+    fn field_count(&self) -> usize {
+        self.len()
+    }
 
-        if !input_file.exists() {
-            panic!("Input file doesn't exist: {}", input_file.display());
+    fn byte_len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn is_blank(&self) -> bool {
+        self.len() <= 1 && self.iter().all(|field| field.trim().is_empty())
+    }
+
+    fn trim_carriage_returns(self) -> Self {
+        let last: &str = self.get(self.len().wrapping_sub(1)).unwrap_or_default();
+        if !last.ends_with('\r') {
+            return self;
         }
+        let mut trimmed: StringRecord = self.iter().take(self.len() - 1).collect();
+        trimmed.push_field(last.trim_end_matches('\r'));
+        trimmed.set_position(self.position().cloned());
+        trimmed
+    }
 
-        context.add_file(output_dir.join("AK.csv"));
-        context.add_file(output_dir.join("AL.csv"));
-        context.add_file(output_dir.join("NY.csv"));
-        context.add_file(output_dir.join("CA.csv"));
+    fn expand(self, context: &RecordProcessingContext) -> Result<Self, AppError> {
+        match &context.json_expansion {
+            Some(json_expansion) => json_expansion.expand(&self),
+            None => Ok(self),
+        }
+    }
+}
 
-        split_file_by_category(
-            &input_file,
-            &input_column,
-            output_dir.clone(),
-            false,
-            &delimiter,
-        )
-        .unwrap();
-        let ak_file_path = format!("{}/AK.csv", output_dir.display());
-        let al_file_path = format!("{}/AL.csv", output_dir.display());
+impl InputRecord for ByteRecord {
+    fn read_from<R: Read>(reader: &mut Reader<R>) -> csv::Result<Option<Self>> {
+        let mut record: ByteRecord = ByteRecord::new();
+        Ok(reader.read_byte_record(&mut record)?.then_some(record))
+    }
 
-        let ak_data = fs::read_to_string(ak_file_path).unwrap();
-        let al_data = fs::read_to_string(al_file_path).unwrap();
+    fn position(&self) -> Option<&Position> {
+        ByteRecord::position(self)
+    }
 
-        assert!(ak_data.contains("City|Population|Latitude|Longitude"));
-        assert!(ak_data.contains("Davidson Landing||65.241944|-165.2716667"));
-        assert!(ak_data.contains("Kenai|7610|60.5544444|-151.2583333"));
+    fn field_count(&self) -> usize {
+        self.len()
+    }
 
-        assert!(al_data.contains("City|Population|Latitude|Longitude"));
-        assert!(al_data.contains("Oakman||33.7133333|-87.38861111"));
+    fn byte_len(&self) -> usize {
+        self.as_slice().len()
     }
 
-    #[test]
+    fn is_blank(&self) -> bool {
+        self.len() <= 1 && self.iter().all(|field| field.trim_ascii().is_empty())
+    }
+
+    fn trim_carriage_returns(self) -> Self {
+        let last: &[u8] = self.get(self.len().wrapping_sub(1)).unwrap_or_default();
+        if !last.ends_with(b"\r") {
+            return self;
+        }
+        let end: usize = last
+            .iter()
+            .rposition(|&byte| byte != b'\r')
+            .map_or(0, |idx| idx + 1);
+        let mut trimmed: ByteRecord = self.iter().take(self.len() - 1).collect();
+        trimmed.push_field(&last[..end]);
+        trimmed.set_position(self.position().cloned());
+        trimmed
+    }
+
+    /// `--bytes` doesn't expand JSON columns
+    fn expand(self, _context: &RecordProcessingContext) -> Result<Self, AppError> {
+        Ok(self)
+    }
+}
+
+/// Read the records in chunks of `chunk_size`, skipping the oversized ones and those
+/// out of `--rows`. The record checks are the same for every split, so they use the
+/// first context
+pub(crate) fn read_chunks<R: Read, T: InputRecord, F>(
+    reader: &mut Reader<R>,
+    context: &RecordProcessingContext,
+    mut on_chunk: F,
+) -> Result<SplitSummary, AppError>
+where
+    F: FnMut(&Vec<T>) -> Result<SplitSummary, AppError>,
+{
+    let chunk_size: usize = context.chunk_size;
+    let header_count: usize = reader.headers()?.len();
+    let mut summary: SplitSummary = SplitSummary::default();
+
+    let mut chunk: Vec<_> = Vec::with_capacity(chunk_size);
+    let mut read_started_at: Instant = Instant::now();
+    let mut bytes_read: u64 = 0;
+
+    for result in input_records(reader, context) {
+        let Some((record, record_end)): Option<(T, u64)> = result? else {
+            summary.empty_lines_skipped += 1;
+            continue;
+        };
+        bytes_read = record
+            .position()
+            .map_or(bytes_read, |position| position.byte());
+        check_field_count(&record, header_count, context)?;
+        if is_oversized(&record, context)? {
+            summary.oversized_records_skipped += 1;
+            check_max_errors(&summary, context)?;
+            continue;
+        }
+        chunk.push(record.expand(context)?);
+
+        // A chunk ends at the freeze, so the next one is categorized with the categories
+        // of all the records before it
+        let freezing: bool = context.freeze_categories_after.is_some_and(|freeze_after| {
+            context.category_freeze.read().unwrap().rows() + chunk.len() == freeze_after
+        });
+        let caught_up: bool = context
+            .follow
+            .as_ref()
+            .is_some_and(|follow| follow.is_caught_up(record_end));
+        if chunk.len() == chunk_size || freezing || caught_up {
+            summary.read_time += read_started_at.elapsed();
+            summary.rows_processed += chunk.len();
+            summary.merge(on_chunk(&chunk)?);
+            check_max_errors(&summary, context)?;
+            report_progress(&summary, bytes_read, context)?;
+            chunk.clear();
+            read_started_at = Instant::now();
+            if context
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                event!(Level::WARN, "Reached --max-runtime, stopping the split");
+                summary.timed_out = true;
+                break;
+            }
+        }
+    }
+    summary.read_time += read_started_at.elapsed();
+    if context
+        .follow
+        .as_ref()
+        .is_some_and(|follow| follow.is_past_deadline())
+    {
+        event!(
+            Level::WARN,
+            "Reached --max-runtime, stopping following the input"
+        );
+        summary.timed_out = true;
+    }
+    if !chunk.is_empty() {
+        summary.rows_processed += chunk.len();
+        summary.merge(on_chunk(&chunk)?);
+        check_max_errors(&summary, context)?;
+        report_progress(&summary, bytes_read, context)?;
+    }
+
+    Ok(summary)
+}
+
+/// Stop the run once more records were skipped than `--max-errors`, the records split so
+/// far staying written
+pub(crate) fn check_max_errors(
+    summary: &SplitSummary,
+    context: &RecordProcessingContext,
+) -> Result<(), AppError> {
+    match context.max_errors {
+        Some(max_errors) if summary.skipped_records() > max_errors => {
+            Err(AppError::TooManyErrors {
+                errors: summary.skipped_records(),
+                max_errors,
+                rows: summary.rows_processed,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Append the progress so far to `--progress-to`, if set. The categories are those with
+/// a file open by now, also by a writer thread or the split of another input
+pub(crate) fn report_progress(
+    summary: &SplitSummary,
+    bytes_read: u64,
+    context: &RecordProcessingContext,
+) -> Result<(), AppError> {
+    if let Some(progress_feed) = &context.progress_feed {
+        let categories: usize = context.category_rows.lock().unwrap().len();
+        progress_feed
+            .lock()
+            .unwrap()
+            .report(summary.rows_processed, categories, bytes_read)?;
+    }
+    Ok(())
+}
+
+/// Records of the input as the split reads them, each with the byte it ends at for
+/// `--follow` to tell when it is caught up with the input. The `--trailer-rows` are
+/// dropped, only the `--rows` range is read and the line endings are normalized. A blank
+/// line skipped by `--skip-empty-lines` is `None`, still counting as a record of the range
+pub(crate) fn input_records<'a, R: Read, T: InputRecord + 'a>(
+    reader: &'a mut Reader<R>,
+    context: &'a RecordProcessingContext,
+) -> impl Iterator<Item = Result<Option<(T, u64)>, AppError>> + 'a {
+    let records = iter::from_fn(move || match T::read_from(reader) {
+        Ok(Some(record)) => Some(Ok((record, reader.position().byte()))),
+        Ok(None) => None,
+        Err(error) => Some(Err(error)),
+    });
+    let row_range: RowRange = context.row_range.unwrap_or(RowRange {
+        start: 1,
+        end: u64::MAX,
+    });
+    drop_trailer(records, context.trailer_rows)
+        .zip(1..)
+        .skip_while(move |(_, record_number)| *record_number < row_range.start)
+        .take_while(move |(_, record_number)| *record_number <= row_range.end)
+        .map(move |(result, _)| {
+            let (record, record_end): (T, u64) = result?;
+            let record: T = normalize_line_ending(record, context);
+            if context.skip_empty_lines && record.is_blank() {
+                return Ok(None);
+            }
+            Ok(Some((record, record_end)))
+        })
+}
+
+/// Check that a record has as many fields as the headers when the reader is flexible, a
+/// record of another length being an error as it is without `--skip-empty-lines`
+pub(crate) fn check_field_count<T: InputRecord>(
+    record: &T,
+    header_count: usize,
+    context: &RecordProcessingContext,
+) -> Result<(), AppError> {
+    if context.reads_flexibly() && record.field_count() != header_count {
+        let line: u64 = record.position().map_or(0, |position| position.line());
+        return Err(AppError::Io(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Record at line {} has {} fields instead of {}",
+                line,
+                record.field_count(),
+                header_count
+            ),
+        )));
+    }
+    Ok(())
+}
+
+/// Strip the carriage returns ending the last field with `--normalize-line-endings`. The
+/// reader already ends the records at `\r\n`, `\n` and `\r`, so they are left in a quoted
+/// last field, e.g. `"AK\r"`. Line endings within the field are kept
+fn normalize_line_ending<T: InputRecord>(record: T, context: &RecordProcessingContext) -> T {
+    if context.normalize_line_endings {
+        record.trim_carriage_returns()
+    } else {
+        record
+    }
+}
+
+/// Hold the last `trailer_rows` records back and drop them once the input ends, an input
+/// shorter than that yielding nothing
+fn drop_trailer<T, I: Iterator<Item = T>>(
+    records: I,
+    trailer_rows: usize,
+) -> impl Iterator<Item = T> {
+    let mut trailer: VecDeque<T> = VecDeque::with_capacity(trailer_rows + 1);
+    records.filter_map(move |record| {
+        trailer.push_back(record);
+        if trailer.len() > trailer_rows {
+            trailer.pop_front()
+        } else {
+            None
+        }
+    })
+}
+
+/// Categorized records of a chunk for the split context at the index
+type RecordBatch = (usize, HashMap<String, Vec<StringRecord>>);
+
+/// Categorize the chunks on the reading thread and send them to a writer thread owning
+/// every writer, so the writers are never contended. The reading thread blocks once
+/// `channel_capacity` chunks are waiting to be written
+fn write_records_on_writer_thread<R: Read>(
+    reader: &mut Reader<R>,
+    contexts: &[RecordProcessingContext],
+) -> Result<SplitSummary, AppError> {
+    let (tx, rx) = mpsc::sync_channel::<RecordBatch>(contexts[0].channel_capacity);
+    thread::scope(|scope| {
+        let writer = scope.spawn(move || run_writer_thread(rx, contexts));
+        let read_result: Result<SplitSummary, AppError> =
+            read_chunks(reader, &contexts[0], |chunk: &Vec<StringRecord>| {
+                let mut summary: SplitSummary = SplitSummary::default();
+                for (idx, context) in contexts.iter().enumerate() {
+                    let filter_started_at: Instant = Instant::now();
+                    let (kept, global_duplicates_removed) = drop_global_duplicates(chunk, context);
+                    let records: HashMap<String, Vec<StringRecord>> =
+                        filter_records(kept.as_ref().unwrap_or(chunk), context);
+                    summary.filter_time += filter_started_at.elapsed();
+                    summary.global_duplicates_removed += global_duplicates_removed;
+                    freeze_categories(&records, chunk.len(), context)?;
+                    if context.sort_by.is_some() {
+                        summary.merge(buffer_records(records, context)?);
+                    } else if tx.send((idx, records)).is_err() {
+                        // The writer thread stopped, its error is returned once joined
+                        return Err(AppError::Io(Error::other("The writer thread stopped")));
+                    }
+                }
+                Ok(summary)
+            });
+        drop(tx);
+
+        let write_result: Result<SplitSummary, AppError> = writer.join().unwrap();
+        let mut summary: SplitSummary = write_result?;
+        summary.merge(read_result?);
+        Ok(summary)
+    })
+}
+
+/// Write the received batches until the channel closes, then hand the writers back to
+/// the contexts for the rest of the split
+fn run_writer_thread(
+    rx: Receiver<RecordBatch>,
+    contexts: &[RecordProcessingContext],
+) -> Result<SplitSummary, AppError> {
+    let mut writers: Vec<HashMap<String, Writer<OutputWriter>>> = contexts
+        .iter()
+        .map(|context| std::mem::take(&mut *context.writers.lock().unwrap()))
+        .collect();
+    let mut seen_records: Vec<HashMap<String, HashSet<u64>>> = contexts
+        .iter()
+        .map(|context| std::mem::take(&mut *context.seen_records.lock().unwrap()))
+        .collect();
+
+    let mut result: Result<SplitSummary, AppError> = Ok(SplitSummary::default());
+    for (idx, records) in rx {
+        match write_category_records(
+            records,
+            &mut writers[idx],
+            &mut seen_records[idx],
+            &contexts[idx],
+        ) {
+            Ok(batch_summary) => {
+                if let Ok(summary) = result.as_mut() {
+                    summary.merge(batch_summary);
+                }
+            }
+            Err(error) => {
+                result = Err(error);
+                break;
+            }
+        }
+    }
+
+    for ((context, writers), seen_records) in contexts.iter().zip(writers).zip(seen_records) {
+        *context.writers.lock().unwrap() = writers;
+        *context.seen_records.lock().unwrap() = seen_records;
+    }
+    result
+}
+
+/// Check the record against `--max-record-bytes`, erroring when the policy says so. The
+/// reader has already parsed the whole record, the check keeping it out of the files
+/// without bounding the memory it took
+fn is_oversized<T: InputRecord>(
+    record: &T,
+    context: &RecordProcessingContext,
+) -> Result<bool, AppError> {
+    let record_bytes: usize = record.byte_len();
+    match context.max_record_bytes {
+        Some(max_record_bytes) if record_bytes > max_record_bytes => {
+            let line: u64 = record.position().map_or(0, |position| position.line());
+            match context.oversized_record_policy {
+                OversizedRecordPolicy::Skip => {
+                    event!(
+                        Level::WARN,
+                        "Skipping record at line {} with {} bytes",
+                        line,
+                        record_bytes
+                    );
+                    Ok(true)
+                }
+                OversizedRecordPolicy::Error => Err(AppError::OversizedRecord {
+                    line,
+                    bytes: record_bytes,
+                }),
+            }
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Process records in parallel
+fn process_chunk(
+    chunk: &Vec<StringRecord>,
+    context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    if context.single_threaded && context.sort_by.is_none() {
+        return write_records_immediately(chunk, context);
+    }
+    let filter_started_at: Instant = Instant::now();
+    let (kept, global_duplicates_removed) = drop_global_duplicates(chunk, context);
+    let writers: HashMap<String, Vec<StringRecord>> =
+        filter_records(kept.as_ref().unwrap_or(chunk), context);
+    let filter_time: Duration = filter_started_at.elapsed();
+    freeze_categories(&writers, chunk.len(), context)?;
+    let mut summary: SplitSummary = if context.sort_by.is_some() {
+        buffer_records(writers, context)?
+    } else {
+        write_records(writers, context)?
+    };
+    summary.filter_time += filter_time;
+    summary.global_duplicates_removed += global_duplicates_removed;
+    Ok(summary)
+}
+
+/// Categorize and write the records one after the other for `--single-threaded`, each
+/// being written to its file before the next one is categorized, as a naive sequential
+/// split would
+fn write_records_immediately(
+    chunk: &Vec<StringRecord>,
+    context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let mut summary: SplitSummary = SplitSummary::default();
+    let (kept, global_duplicates_removed) = drop_global_duplicates(chunk, context);
+    summary.global_duplicates_removed += global_duplicates_removed;
+    freeze_categories(&HashMap::new(), global_duplicates_removed, context)?;
+    for record in kept.as_ref().unwrap_or(chunk) {
+        let filter_started_at: Instant = Instant::now();
+        let records: HashMap<String, Vec<StringRecord>> = if matches_filters(record, context) {
+            categorize_record(HashMap::new(), record, context)
+        } else {
+            HashMap::new()
+        };
+        summary.filter_time += filter_started_at.elapsed();
+        freeze_categories(&records, 1, context)?;
+        summary.merge(write_records(records, context)?);
+    }
+    Ok(summary)
+}
+
+/// Record the categories of a chunk until `--freeze-categories-after` records were
+/// categorized. Afterwards the records of a new category already went to `other`, or
+/// stop the split with `--frozen-category error`
+fn freeze_categories(
+    records: &HashMap<String, Vec<StringRecord>>,
+    chunk_rows: usize,
+    context: &RecordProcessingContext,
+) -> Result<(), AppError> {
+    let Some(freeze_after) = context.freeze_categories_after else {
+        return Ok(());
+    };
+    // The skipped null category is dropped whether it is new or not
+    let mut categories = records
+        .keys()
+        .filter(|category| !is_skipped_category(category.as_bytes(), context));
+    let mut category_freeze: RwLockWriteGuard<CategoryFreeze> =
+        context.category_freeze.write().unwrap();
+    if !category_freeze.is_frozen(freeze_after) {
+        category_freeze.add_chunk(chunk_rows, categories);
+        if category_freeze.is_frozen(freeze_after) {
+            event!(
+                Level::INFO,
+                "Froze the {} categories of the first {} records",
+                category_freeze.categories(),
+                freeze_after
+            );
+        }
+        return Ok(());
+    }
+    match categories.find(|category| category_freeze.is_new(category, freeze_after)) {
+        Some(category) if context.frozen_category_policy == UnexpectedCategoryPolicy::Error => {
+            Err(AppError::FrozenCategory(category.clone()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Hold the records back for `--sort-within-category`, as sorting needs every record of
+/// a category. A buffer over `--spill-threshold` is spilled to disk
+fn buffer_records(
+    writers: HashMap<String, Vec<StringRecord>>,
+    context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let started_at: Instant = Instant::now();
+    let mut summary: SplitSummary = SplitSummary::default();
+    let mut buffers: MutexGuard<HashMap<String, Vec<StringRecord>>> =
+        context.category_buffers.lock().unwrap();
+    for (category, mut records) in writers {
+        let buffer: &mut Vec<StringRecord> = buffers.entry(category.clone()).or_default();
+        buffer.append(&mut records);
+        if context
+            .spill_threshold
+            .is_some_and(|threshold| buffer.len() > threshold)
+        {
+            spill_records(&category, std::mem::take(buffer), context)?;
+            summary.spilled_segments += 1;
+        }
+    }
+    summary.write_time += started_at.elapsed();
+    Ok(summary)
+}
+
+/// Drop the records of a chunk already kept in any category with `--dedupe-global`,
+/// keeping the first of each in input order. The hashes are computed across the rayon
+/// threads, then checked against the shared set in order. Returns the records kept, none
+/// without `--dedupe-global`, and the number dropped
+fn drop_global_duplicates(
+    chunk: &[StringRecord],
+    context: &RecordProcessingContext,
+) -> (Option<Vec<StringRecord>>, usize) {
+    if !context.dedupe_global {
+        return (None, 0);
+    }
+    // The records filtered out or of a skipped category don't hide a later repeat
+    let hash = |record: &StringRecord| -> Option<u64> {
+        if !matches_filters(record, context) {
+            return None;
+        }
+        let (category, fields) = project_record(record, context);
+        (!is_skipped_category(category.as_bytes(), context))
+            .then(|| hash_record(&fields, &context.dedupe_key_indexes))
+    };
+    let hashes: Vec<Option<u64>> = if context.single_threaded {
+        chunk.iter().map(hash).collect()
+    } else {
+        chunk.par_iter().map(hash).collect()
+    };
+    let mut seen: MutexGuard<HashSet<u64>> = context.global_seen_records.lock().unwrap();
+    let kept: Vec<StringRecord> = chunk
+        .iter()
+        .zip(hashes)
+        .filter(|(_, hash)| hash.is_none_or(|hash| seen.insert(hash)))
+        .map(|(record, _)| record.clone())
+        .collect();
+    let removed: usize = chunk.len() - kept.len();
+    (Some(kept), removed)
+}
+
+/// Filter records by category, dropping those rejected by the `--filter` predicates.
+/// The chunk is split across the rayon threads with the `--concurrency-strategy` unless
+/// `--single-threaded`
+fn filter_records(
+    chunk: &Vec<StringRecord>,
+    context: &RecordProcessingContext,
+) -> HashMap<String, Vec<StringRecord>> {
+    if context.single_threaded {
+        return chunk
+            .iter()
+            .filter(|record| matches_filters(record, context))
+            .fold(HashMap::new(), |acc, record| {
+                categorize_record(acc, record, context)
+            });
+    }
+    match context.concurrency_strategy {
+        ConcurrencyStrategy::FoldReduce => chunk
+            .par_iter()
+            .filter(|record| matches_filters(record, context))
+            .fold_with(HashMap::new(), |acc, record| {
+                categorize_record(acc, record, context)
+            })
+            .reduce(HashMap::new, |mut acc, map| {
+                for (key, mut value) in map {
+                    acc.entry(key).or_default().append(&mut value);
+                }
+                acc
+            }),
+        ConcurrencyStrategy::Dashmap => {
+            let categories: DashMap<String, Vec<(usize, StringRecord)>> = DashMap::new();
+            chunk
+                .par_iter()
+                .enumerate()
+                .filter(|(_, record)| matches_filters(record, context))
+                .for_each(|(idx, record)| {
+                    let (category, fields) = project_record(record, context);
+                    categories.entry(category).or_default().push((idx, fields));
+                });
+            restore_input_order(categories)
+        }
+        ConcurrencyStrategy::Channel => {
+            let (sender, receiver) = mpsc::channel::<(String, (usize, StringRecord))>();
+            // The records are collected on a thread of their own while the chunk is still
+            // being categorized, the channel closing once the last sender is dropped
+            let categories: HashMap<String, Vec<(usize, StringRecord)>> = thread::scope(|scope| {
+                let collector = scope.spawn(move || {
+                    let mut categories: HashMap<String, Vec<(usize, StringRecord)>> =
+                        HashMap::new();
+                    for (category, record) in receiver {
+                        categories.entry(category).or_default().push(record);
+                    }
+                    categories
+                });
+                chunk
+                    .par_iter()
+                    .enumerate()
+                    .filter(|(_, record)| matches_filters(record, context))
+                    .for_each_with(sender, |sender, (idx, record)| {
+                        let (category, fields) = project_record(record, context);
+                        sender.send((category, (idx, fields))).unwrap();
+                    });
+                collector.join().unwrap()
+            });
+            restore_input_order(categories)
+        }
+    }
+}
+
+/// Sort the records of each category back to their position in the chunk, as the
+/// shared map and channel strategies collect them in no particular order
+fn restore_input_order(
+    categories: impl IntoIterator<Item = (String, Vec<(usize, StringRecord)>)>,
+) -> HashMap<String, Vec<StringRecord>> {
+    categories
+        .into_iter()
+        .map(|(category, mut records)| {
+            records.sort_unstable_by_key(|(idx, _)| *idx);
+            let records: Vec<StringRecord> =
+                records.into_iter().map(|(_, record)| record).collect();
+            (category, records)
+        })
+        .collect()
+}
+
+/// Add the written fields of a record to the records of its category
+#[inline]
+fn categorize_record(
+    mut acc: HashMap<String, Vec<StringRecord>>,
+    record: &StringRecord,
+    context: &RecordProcessingContext,
+) -> HashMap<String, Vec<StringRecord>> {
+    let (category, filtered_records) = project_record(record, context);
+    acc.entry(category).or_default().push(filtered_records);
+    acc
+}
+
+/// Category of a record and the fields written to its file
+#[inline]
+fn project_record(
+    record: &StringRecord,
+    context: &RecordProcessingContext,
+) -> (String, StringRecord) {
+    let category: String = get_category(record, context);
+    let header_indexes: &Vec<usize> = context
+        .category_projections
+        .get(&category)
+        .map_or(&context.header_indexes, |(indexes, _)| indexes);
+    let mut filtered_records: StringRecord = header_indexes
+        .iter()
+        .filter_map(|&idx| {
+            record.get(idx).map(|field| {
+                if context.strip_quotes {
+                    strip_quotes(field).to_string()
+                } else {
+                    field.to_string()
+                }
+            })
+        })
+        .collect();
+    // The hash is of the input fields only, the run id changing from run to run
+    if context.row_hash_column.is_some() {
+        let row_hash: String = hash_row(&filtered_records, context.row_hash_algorithm);
+        filtered_records.push_field(&row_hash);
+    }
+    if let Some(run_id) = &context.run_id_column {
+        filtered_records.push_field(run_id);
+    }
+    (category, filtered_records)
+}
+
+/// Remove the double quotes surrounding a field, leaving the inner ones, e.g. `"hello"`
+/// becomes `hello` but `"say" "hi"` keeps its inner quotes. Apostrophes are kept, being
+/// part of values like `'90s`
+#[inline]
+fn strip_quotes(field: &str) -> &str {
+    field
+        .strip_prefix('"')
+        .and_then(|field| field.strip_suffix('"'))
+        .unwrap_or(field)
+}
+
+/// Evaluate the `--filter` predicates on a record, combined by `--filter-logic`
+pub(crate) fn matches_filters(record: &StringRecord, context: &RecordProcessingContext) -> bool {
+    if context.filters.is_empty() {
+        return true;
+    }
+    let mut results = context
+        .filters
+        .iter()
+        .zip(&context.filter_column_indexes)
+        .map(|(filter, &idx)| filter.matches(record.get(idx).unwrap_or_default()));
+    match context.filter_logic {
+        FilterLogic::And => results.all(|result| result),
+        FilterLogic::Or => results.any(|result| result),
+    }
+}
+
+/// Write records to CSV file
+fn write_records(
+    writers: HashMap<String, Vec<StringRecord>>,
+    context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let mut context_writers: MutexGuard<HashMap<String, Writer<OutputWriter>>> =
+        context.writers.lock().unwrap();
+    let mut seen_records: MutexGuard<HashMap<String, HashSet<u64>>> =
+        context.seen_records.lock().unwrap();
+    write_category_records(writers, &mut context_writers, &mut seen_records, context)
+}
+
+/// Write the records of each category to its writer, opening it on the first record and
+/// dropping the duplicates with `--dedupe`
+fn write_category_records(
+    writers: HashMap<String, Vec<StringRecord>>,
+    context_writers: &mut HashMap<String, Writer<OutputWriter>>,
+    seen_records: &mut HashMap<String, HashSet<u64>>,
+    context: &RecordProcessingContext,
+) -> Result<SplitSummary, AppError> {
+    let started_at: Instant = Instant::now();
+    let mut summary: SplitSummary = SplitSummary::default();
+    for (category, records) in writers {
+        if is_skipped_category(category.as_bytes(), context) {
+            summary.null_category_records_skipped += records.len();
+            continue;
+        }
+        let writer: &mut Writer<OutputWriter> = match context_writers.entry(category.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(_)
+                if context.invalid_category_policy == InvalidCategoryPolicy::Skip
+                    && !is_valid_category(&category, context) =>
+            {
+                event!(
+                    Level::WARN,
+                    "Skipping {} records of the invalid category {:?}",
+                    records.len(),
+                    category
+                );
+                summary.invalid_category_records_skipped += records.len();
+                continue;
+            }
+            // The writers of the expected categories are all created up front, then only
+            // closed by `--retry-open`
+            Entry::Vacant(_)
+                if context.unexpected_category_policy == UnexpectedCategoryPolicy::Error
+                    && !context.expected_categories.is_empty()
+                    && !context.writer_usage.lock().unwrap().is_closed(&category) =>
+            {
+                return Err(AppError::UnexpectedCategory(category));
+            }
+            Entry::Vacant(_) => {
+                let writer: Writer<OutputWriter> =
+                    open_category_writer(&category, context_writers, context, &mut summary)?;
+                context_writers.entry(category.clone()).or_insert(writer)
+            }
+        };
+        if context.retry_open > 0 {
+            context.writer_usage.lock().unwrap().touch(&category);
+        }
+
+        let mut seen: Option<&mut HashSet<u64>> = context
+            .dedupe
+            .then(|| seen_records.entry(category.clone()).or_default());
+        let mut written: usize = 0;
+        let mut tee_writer: MutexGuard<Option<Writer<File>>> = context.tee_writer.lock().unwrap();
+        let mut samplers: Option<MutexGuard<HashMap<String, TypeSampler>>> = context
+            .emit_schema
+            .then(|| context.category_samplers.lock().unwrap());
+        let mut sampler: Option<&mut TypeSampler> = samplers
+            .as_mut()
+            .map(|samplers| samplers.entry(category.clone()).or_default());
+        for record in records {
+            if let Some(seen) = seen.as_mut() {
+                if !seen.insert(hash_record(&record, &context.dedupe_key_indexes)) {
+                    summary.duplicates_removed += 1;
+                    continue;
+                }
+            }
+            if let Some(sampler) = sampler.as_mut() {
+                sampler.sample(&record);
+            }
+            write_output_record(writer, &record, &category, context)?;
+            if let Some(tee_writer) = tee_writer.as_mut() {
+                write_output_record(tee_writer, &record, "", context)?;
+            }
+            written += 1;
+        }
+        writer.flush()?;
+        if let Some((_, rows)) = context.category_rows.lock().unwrap().get_mut(&category) {
+            *rows += written;
+        }
+        // The categories of independent splits of a read are told apart by their directory
+        let summary_category: String = match &context.split_dir {
+            Some(split_dir) => format!("{}/{}", split_dir, category),
+            None => category,
+        };
+        *summary.category_rows.entry(summary_category).or_default() += written;
+    }
+    summary.write_time += started_at.elapsed();
+    Ok(summary)
+}
+
+/// Open the writer of a category on its first records, or reopen the file of a writer
+/// closed to free its handle. With `--retry-open`, an open failing for lack of file
+/// handles closes the least recently used half of the writers and is retried after a
+/// growing, jittered delay
+fn open_category_writer(
+    category: &str,
+    context_writers: &mut HashMap<String, Writer<OutputWriter>>,
+    context: &RecordProcessingContext,
+    summary: &mut SplitSummary,
+) -> Result<Writer<OutputWriter>, AppError> {
+    let mut retries: usize = 0;
+    loop {
+        let closed: Option<PathBuf> = context.writer_usage.lock().unwrap().reopen(category);
+        // Nothing is recorded in the summary until the file is open, as the open may be
+        // retried
+        let mut attempt: SplitSummary = SplitSummary::default();
+        let result: Result<Writer<OutputWriter>, AppError> = match &closed {
+            Some(file_path) => reopen_category_writer(category, file_path, context),
+            None => create_category_writer(category, context, &mut attempt),
+        };
+        match result {
+            Err(AppError::Io(error))
+                if retries < context.retry_open && is_out_of_file_handles(&error) =>
+            {
+                if let Some(file_path) = closed {
+                    context
+                        .writer_usage
+                        .lock()
+                        .unwrap()
+                        .close(category, file_path);
+                }
+                retries += 1;
+                let closed_writers: usize = close_writers(context_writers, context)?;
+                event!(
+                    Level::WARN,
+                    "Out of file handles opening the file of {:?}, closed {} writers and retrying ({}/{})",
+                    category,
+                    closed_writers,
+                    retries,
+                    context.retry_open
+                );
+                thread::sleep(retry_delay(retries));
+            }
+            result => {
+                summary.merge(attempt);
+                return result;
+            }
+        }
+    }
+}
+
+/// Whether an open failed because the process or the system has no file handle left
+fn is_out_of_file_handles(error: &Error) -> bool {
+    error
+        .raw_os_error()
+        .is_some_and(|code| OUT_OF_FILE_HANDLES.contains(&code))
+}
+
+/// Delay before the retry of an open, doubling from `RETRY_OPEN_DELAY` up to
+/// `MAX_RETRY_OPEN_DELAY` with up to `RETRY_OPEN_DELAY` of jitter, so parallel splits
+/// don't all retry at once
+fn retry_delay(retries: usize) -> Duration {
+    let delay: Duration = RETRY_OPEN_DELAY
+        .saturating_mul(2u32.saturating_pow(retries as u32 - 1))
+        .min(MAX_RETRY_OPEN_DELAY);
+    let jitter: u32 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.subsec_nanos());
+    delay + RETRY_OPEN_DELAY.mul_f64(f64::from(jitter) / 1e9)
+}
+
+/// Flush and close the least recently used half of the writers, at least one, to free
+/// their file handles. Their files are reopened on their next records
+fn close_writers(
+    context_writers: &mut HashMap<String, Writer<OutputWriter>>,
+    context: &RecordProcessingContext,
+) -> Result<usize, AppError> {
+    let mut writer_usage: MutexGuard<WriterUsage> = context.writer_usage.lock().unwrap();
+    let categories: Vec<String> =
+        writer_usage.least_recently_used(context_writers.keys(), context_writers.len().div_ceil(2));
+    let category_rows: MutexGuard<HashMap<String, (PathBuf, usize)>> =
+        context.category_rows.lock().unwrap();
+    for category in &categories {
+        if let Some(mut writer) = context_writers.remove(category) {
+            writer.flush()?;
+            if let Some((file_path, _)) = category_rows.get(category) {
+                writer_usage.close(category, file_path.clone());
+            }
+        }
+    }
+    Ok(categories.len())
+}
+
+/// Reopen the file of a closed writer for appending, its headers being there already
+fn reopen_category_writer(
+    category: &str,
+    file_path: &Path,
+    context: &RecordProcessingContext,
+) -> Result<Writer<OutputWriter>, AppError> {
+    let output: OutputWriter = encode_output(
+        context.output_backend.open_append(file_path)?,
+        context.output_encoding,
+        context.unmappable_policy,
+    );
+    Ok(category_csv_writer(output, category, context))
+}
+
+/// Open the file of a category for appending, writing the headers if it is new or as
+/// `--write-header` says. The file is recorded in the summary as created or appended to
+pub(crate) fn create_category_writer(
+    category: &str,
+    context: &RecordProcessingContext,
+    summary: &mut SplitSummary,
+) -> Result<Writer<OutputWriter>, AppError> {
+    if let Some(pattern) = context
+        .category_pattern
+        .as_ref()
+        .filter(|_| !matches_category_pattern(category, context))
+    {
+        return Err(AppError::CategoryMismatch {
+            category: category.to_string(),
+            pattern: pattern.to_string(),
+        });
+    }
+    let file_category: String = if context.ascii_file_names {
+        transliterate_category(category, context, summary)?
+    } else {
+        category.to_string()
+    };
+    let file_category: String = match context.invalid_category_policy {
+        InvalidCategoryPolicy::Sanitize if escapes_output_dir(&file_category, context) => {
+            let sanitized: String = sanitize_category(&file_category, context);
+            event!(
+                Level::WARN,
+                "Writing the invalid category {:?} to {:?}",
+                category,
+                sanitized
+            );
+            sanitized
+        }
+        // Transliterating can add a separator, `½` becoming `1/2`, the error naming the
+        // value of the records
+        InvalidCategoryPolicy::Error if escapes_output_dir(&file_category, context) => {
+            return Err(AppError::InvalidCategory(category.to_string()));
+        }
+        _ => file_category,
+    };
+    let file_category: String = match shorten_category(&file_category, context) {
+        Some(shortened) => {
+            summary
+                .renamed
+                .push((category.to_string(), shortened.clone()));
+            shortened
+        }
+        None => file_category,
+    };
+    let file_path: PathBuf = create_category_path(&file_category, context)?;
+    let existing_len: Option<u64> = context.output_backend.existing_len(&file_path)?;
+    let file_exists: bool = existing_len.is_some();
+    match existing_len {
+        _ if context.output_backend.discards() => {}
+        Some(existing_len) => {
+            summary.existing_bytes += existing_len;
+            summary.appended.push(file_path.display().to_string());
+        }
+        None => summary.created.push(file_path.display().to_string()),
+    }
+    let mut output: OutputWriter = encode_output(
+        context.output_backend.open_append(&file_path)?,
+        context.output_encoding,
+        context.unmappable_policy,
+    );
+    // Excel only takes the delimiter from the first line, so appended files keep theirs
+    if context.excel_sep_hint && existing_len.unwrap_or(0) == 0 {
+        writeln!(output, "sep={}", context.delimiter as char)?;
+    }
+    context
+        .category_rows
+        .lock()
+        .unwrap()
+        .insert(category.to_string(), (file_path, 0));
+
+    let mut csv_writer: Writer<OutputWriter> = category_csv_writer(output, category, context);
+
+    let writes_header: bool = match context.write_header {
+        WriteHeader::Always => true,
+        WriteHeader::Never => false,
+        WriteHeader::IfNew => !file_exists,
+    };
+    if writes_header {
+        let headers: &StringRecord = context
+            .category_projections
+            .get(category)
+            .map_or(&context.headers, |(_, headers)| headers);
+        write_output_record(&mut csv_writer, headers, category, context)?;
+    }
+
+    Ok(csv_writer)
+}
+
+/// CSV writer of the records of a category
+fn category_csv_writer(
+    output: OutputWriter,
+    category: &str,
+    context: &RecordProcessingContext,
+) -> Writer<OutputWriter> {
+    // Drifted inputs write records of another length than the headers
+    WriterBuilder::new()
+        .delimiter(context.delimiter)
+        .flexible(context.allow_schema_drift)
+        .quote_style(quote_style(category, context))
+        .from_writer(output)
+}
+
+/// Quoting of the writer of a category file. The fields are already quoted with
+/// `--quote-empty`, so the writer leaves them as they are
+fn quote_style(category: &str, context: &RecordProcessingContext) -> QuoteStyle {
+    if context.quote_empty {
+        QuoteStyle::Never
+    } else {
+        field_quote_style(category, context)
+    }
+}
+
+/// Quoting of the fields written to a category file
+fn field_quote_style(category: &str, context: &RecordProcessingContext) -> QuoteStyle {
+    if context.raw_output {
+        QuoteStyle::Never
+    } else if context
+        .always_quoted_categories
+        .iter()
+        .any(|quoted| quoted == category)
+    {
+        QuoteStyle::Always
+    } else {
+        QuoteStyle::Necessary
+    }
+}
+
+/// Write a record to the file of a category, or to the `--tee` for the empty category.
+/// With `--quote-empty` the csv writer can't quote a field alone, so the fields are
+/// quoted here: the empty ones always, the others when the writer would
+fn write_output_record<W: Write>(
+    writer: &mut Writer<W>,
+    record: &StringRecord,
+    category: &str,
+    context: &RecordProcessingContext,
+) -> csv::Result<()> {
+    if !context.quote_empty {
+        return writer.write_record(record);
+    }
+    let always: bool = matches!(field_quote_style(category, context), QuoteStyle::Always);
+    writer.write_record(record.iter().map(|field| {
+        let needs_quotes: bool = always
+            || field.is_empty()
+            || field
+                .bytes()
+                .any(|byte| matches!(byte, b'"' | b'\n' | b'\r') || byte == context.delimiter);
+        if needs_quotes {
+            Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")).into_bytes())
+        } else {
+            Cow::Borrowed(field.as_bytes())
+        }
+    }))
+}
+
+/// Transliterate a category to ASCII for its file name, the records keep the original
+/// value. Two categories ending up with the same file name are an error
+fn transliterate_category(
+    category: &str,
+    context: &RecordProcessingContext,
+    summary: &mut SplitSummary,
+) -> Result<String, AppError> {
+    let file_category: String = deunicode(category);
+    let mut file_categories: MutexGuard<HashMap<String, String>> =
+        context.file_categories.lock().unwrap();
+    match file_categories.entry(file_category.clone()) {
+        Entry::Occupied(entry) if entry.get() != category => {
+            return Err(AppError::CategoryNameCollision {
+                first: entry.get().clone(),
+                second: category.to_string(),
+                file_name: file_category,
+            });
+        }
+        Entry::Occupied(_) => {}
+        Entry::Vacant(entry) => {
+            entry.insert(category.to_string());
+        }
+    }
+    if file_category != category {
+        summary
+            .renamed
+            .push((category.to_string(), file_category.clone()));
+    }
+    Ok(file_category)
+}
+
+/// Truncate the components of a category longer than `--max-name-length` characters or
+/// than the filesystem allows, `None` when none is. A truncated component ends with a hash
+/// of its full value, for values sharing a prefix to keep distinct files
+fn shorten_category(category: &str, context: &RecordProcessingContext) -> Option<String> {
+    // Flat categories name the file itself and its sidecars, room being left for the
+    // longest extension whichever are written, for the names not to change between runs
+    let max_bytes: usize = if context.create_directory {
+        MAX_FILE_NAME_BYTES
+    } else {
+        MAX_FILE_NAME_BYTES - LONGEST_CATEGORY_EXTENSION.len()
+    };
+    let max_chars: usize = context.max_name_length.unwrap_or(usize::MAX);
+    let components: Vec<&str> = category_components(category, context);
+    if components
+        .iter()
+        .all(|component| component.len() <= max_bytes && component.chars().count() <= max_chars)
+    {
+        return None;
+    }
+    let shortened: Vec<String> = components
+        .iter()
+        .map(|component| {
+            if component.len() <= max_bytes && component.chars().count() <= max_chars {
+                return component.to_string();
+            }
+            let suffix: String = format!("-{:08x}", name_hash(component) as u32);
+            let mut prefix: String = String::new();
+            for (idx, current) in component.chars().enumerate() {
+                if idx + suffix.len() >= max_chars
+                    || prefix.len() + current.len_utf8() + suffix.len() > max_bytes
+                {
+                    break;
+                }
+                prefix.push(current);
+            }
+            prefix + &suffix
+        })
+        .collect();
+    let separator: String = context
+        .category_path_separator
+        .filter(|_| context.create_directory)
+        .map(String::from)
+        .unwrap_or_default();
+    Some(shortened.join(&separator))
+}
+
+/// FNV-1a hash of a category, stable across runs and builds for the truncated file names
+/// of appending runs to match
+fn name_hash(category: &str) -> u64 {
+    category.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Hash the fields identifying a duplicate record, all of them when no key is given
+fn hash_record(record: &StringRecord, key_indexes: &Option<Vec<usize>>) -> u64 {
+    let mut hasher: DefaultHasher = DefaultHasher::new();
+    match key_indexes {
+        Some(key_indexes) => key_indexes
+            .iter()
+            .for_each(|&idx| record.get(idx).hash(&mut hasher)),
+        None => record.iter().for_each(|field| field.hash(&mut hasher)),
+    }
+    hasher.finish()
+}
+
+/// Get the category value from a record, or computed by `--key-expr`. It is the null
+/// category when empty, missing or one of the `--null-values`, other values are renamed
+/// through `--category-map`
+#[inline]
+pub(crate) fn get_category(record: &StringRecord, context: &RecordProcessingContext) -> String {
+    match &context.key_expr {
+        Some(key_expr) => name_category(Some(&key_expr.evaluate(record)), context),
+        None => {
+            let value: Option<&str> = context
+                .split_column_idx
+                .and_then(|split_column_idx| record.get(split_column_idx));
+            match &context.key_group {
+                Some((key_regex, group)) => name_category(
+                    value
+                        .and_then(|value| key_regex.captures(value))
+                        .and_then(|captures| captures.name(group))
+                        .map(|capture| capture.as_str()),
+                    context,
+                ),
+                None => name_category(value, context),
+            }
+        }
+    }
+}
+
+/// Whether the records of a category are dropped by `--null-category skip`, compared as
+/// bytes for `--bytes`
+pub(crate) fn is_skipped_category(category: &[u8], context: &RecordProcessingContext) -> bool {
+    context.skip_null_category && category == context.null_category.as_bytes()
+}
+
+/// Name the category of a split value, an `--alias` taking precedence over the
+/// `--category-map`
+#[inline]
+fn name_category(value: Option<&str>, context: &RecordProcessingContext) -> String {
+    let (category, assigned): (String, bool) = match map_category(value, context) {
+        Some(category) => (category, false),
+        None => (context.null_category.clone(), true),
+    };
+    // The null category of `--null-category skip` is dropped, never renamed to a category
+    // that would be written
+    if assigned && context.skip_null_category {
+        return category;
+    }
+    if context.invalid_category_policy == InvalidCategoryPolicy::Invalid
+        && (escapes_output_dir(&ascii_category(&category, context), context)
+            || !assigned
+                && context
+                    .category_pattern
+                    .as_ref()
+                    .is_some_and(|pattern| !pattern.is_match(&category)))
+    {
+        return assign_category(INVALID_CATEGORY, context);
+    }
+    if context.unexpected_category_policy == UnexpectedCategoryPolicy::Other
+        && !context.expected_categories.is_empty()
+        && !context.expected_categories.contains(&category)
+    {
+        return assign_category(OTHER_CATEGORY, context);
+    }
+    if let Some(freeze_after) = context.freeze_categories_after {
+        if context.frozen_category_policy == UnexpectedCategoryPolicy::Other
+            && context
+                .category_freeze
+                .read()
+                .unwrap()
+                .is_new(&category, freeze_after)
+        {
+            return assign_category(OTHER_CATEGORY, context);
+        }
+    }
+    if assigned {
+        assign_category(&category, context)
+    } else {
+        category
+    }
+}
+
+/// Record a category named by the split itself, for `--category-validate` to let it be
+pub(crate) fn assign_category(category: &str, context: &RecordProcessingContext) -> String {
+    if context.category_pattern.is_some()
+        && !context
+            .assigned_categories
+            .read()
+            .unwrap()
+            .contains(category)
+    {
+        context
+            .assigned_categories
+            .write()
+            .unwrap()
+            .insert(category.to_string());
+    }
+    category.to_string()
+}
+
+/// Name of the category of a split column value through the aliases, category map and
+/// category transforms, none for the null category. A value transformed to nothing, or to
+/// only `--category-path-sep` separators, is the null category
+fn map_category(value: Option<&str>, context: &RecordProcessingContext) -> Option<String> {
+    let name: Option<String> = match value {
+        Some(category)
+            if !category.is_empty() && !context.null_values.iter().any(|null| null == category) =>
+        {
+            if let Some(alias) = context.category_aliases.get(category) {
+                Some(alias.clone())
+            } else if context.category_map.is_empty() {
+                Some(category.to_string())
+            } else {
+                match context.category_map.get(category) {
+                    Some(name) => Some(name.clone()),
+                    None if context.unmapped_category_policy == UnmappedCategoryPolicy::Null => {
+                        None
+                    }
+                    None => Some(category.to_string()),
+                }
+            }
+        }
+        _ => None,
+    };
+    name.map(|name| {
+        context
+            .category_transforms
+            .iter()
+            .fold(name, |name, transform| transform.apply(&name))
+    })
+    .filter(|name| !name.is_empty() && !category_components(name, context).is_empty())
+}
+
+/// Get headers, without the split column if there is one
+pub(crate) fn get_headers(
+    current_headers: &StringRecord,
+    split_column_id: Option<usize>,
+) -> StringRecord {
+    let headers: Vec<String> = current_headers
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, field)| {
+            if Some(idx) != split_column_id {
+                Some(field.to_string())
+            } else {
+                None
+            }
+        })
+        .collect();
+    StringRecord::from(headers)
+}
+
+/// Remove the `--drop` columns from the written headers, each having to be an input column
+fn drop_columns(
+    file_headers: StringRecord,
+    headers: &StringRecord,
+    dropped_columns: &[String],
+) -> Result<StringRecord, AppError> {
+    if let Some(unknown) = dropped_columns
+        .iter()
+        .find(|column| !headers.iter().any(|h| h == column.as_str()))
+    {
+        return Err(AppError::ColumnNotFound(unknown.clone()));
+    }
+    Ok(file_headers
+        .iter()
+        .filter(|header| !dropped_columns.iter().any(|column| column == header))
+        .collect())
+}
+
+/// Get the header indexes
+pub(crate) fn get_header_indexes(
+    headers: &StringRecord,
+    file_headers: &StringRecord,
+) -> Vec<usize> {
+    file_headers
+        .iter()
+        .filter_map(|header| headers.iter().position(|h| h == header))
+        .collect()
+}
+
+/// Get the indexes of the dedupe key columns within the written records, `None` when
+/// the whole record is the key. The split column is constant within a category, so it
+/// doesn't take part in the key
+fn get_dedupe_key_indexes(
+    dedupe_key: &[String],
+    headers: &StringRecord,
+    file_headers: &StringRecord,
+) -> Result<Option<Vec<usize>>, AppError> {
+    if dedupe_key.is_empty() {
+        return Ok(None);
+    }
+    let mut key_indexes: Vec<usize> = Vec::with_capacity(dedupe_key.len());
+    for column in dedupe_key {
+        match file_headers.iter().position(|h| h == column) {
+            Some(idx) => key_indexes.push(idx),
+            None if headers.iter().any(|h| h == column) => {}
+            None => return Err(AppError::ColumnNotFound(column.to_string())),
+        }
+    }
+    Ok(Some(key_indexes))
+}
+
+/// Get the index of the sort column within the written records, `None` when sorting
+/// by the split column, which is constant within a category
+fn get_sort_column_idx(
+    column: &str,
+    headers: &StringRecord,
+    file_headers: &StringRecord,
+) -> Result<Option<usize>, AppError> {
+    match file_headers.iter().position(|h| h == column) {
+        Some(idx) => Ok(Some(idx)),
+        None if headers.iter().any(|h| h == column) => Ok(None),
+        None => Err(AppError::ColumnNotFound(column.to_string())),
+    }
+}
+
+/// Create a path for a category
+fn create_category_path(
+    category: &str,
+    context: &RecordProcessingContext,
+) -> Result<PathBuf, AppError> {
+    if escapes_output_dir(category, context) {
+        return Err(AppError::InvalidCategory(category.to_string()));
+    }
+    let file_path: PathBuf = if context.create_directory {
+        let dir: PathBuf = category_components(category, context)
+            .iter()
+            .fold(context.output_dir.clone(), |dir, component| {
+                dir.join(component)
+            });
+        context.output_backend.create_dir_all(&dir)?;
+        dir.join(format!("{}.csv", context.file_name))
+    } else if context.shard_by_initial {
+        let dir: PathBuf = context.output_dir.join(category_initial(category));
+        context.output_backend.create_dir_all(&dir)?;
+        dir.join(format!("{}.csv", category))
+    } else {
+        context.output_dir.join(format!("{}.csv", category))
+    };
+    Ok(file_path)
+}
+
+/// Directory of a category with `--shard-by-initial`, its first character uppercased or
+/// `_` when it isn't an ASCII letter or digit
+fn category_initial(category: &str) -> String {
+    match category.chars().next() {
+        Some(initial) if initial.is_ascii_alphanumeric() => {
+            initial.to_ascii_uppercase().to_string()
+        }
+        _ => String::from("_"),
+    }
+}
+
+/// Split a category into the directories of `--category-path-sep`, a single component
+/// otherwise
+fn category_components<'a>(category: &'a str, context: &RecordProcessingContext) -> Vec<&'a str> {
+    match context.category_path_separator {
+        Some(separator) if context.create_directory => category
+            .split(separator)
+            .filter(|component| !component.is_empty())
+            .collect(),
+        _ => vec![category],
+    }
+}
+
+/// Check that a category matches `--category-validate` and that none of the components
+/// of its file name could escape the output directory
+pub(crate) fn is_valid_category(category: &str, context: &RecordProcessingContext) -> bool {
+    matches_category_pattern(category, context)
+        && !escapes_output_dir(&ascii_category(category, context), context)
+}
+
+/// Category as its file is named, transliterated to ASCII with `--ascii-only-filenames`
+fn ascii_category<'a>(category: &'a str, context: &RecordProcessingContext) -> Cow<'a, str> {
+    if context.ascii_file_names {
+        Cow::Owned(deunicode(category))
+    } else {
+        Cow::Borrowed(category)
+    }
+}
+
+/// Whether a category matches `--category-validate`, the categories named by the split
+/// itself always matching
+fn matches_category_pattern(category: &str, context: &RecordProcessingContext) -> bool {
+    context.category_pattern.as_ref().is_none_or(|pattern| {
+        pattern.is_match(category)
+            || context
+                .assigned_categories
+                .read()
+                .unwrap()
+                .contains(category)
+    })
+}
+
+/// Check whether a component of a category could escape the output directory, a category
+/// without any naming the output directory itself
+fn escapes_output_dir(category: &str, context: &RecordProcessingContext) -> bool {
+    let components: Vec<&str> = category_components(category, context);
+    components.is_empty()
+        || components.iter().any(|component| {
+            component.contains("..") || component.contains('/') || component.contains('\\')
+        })
+}
+
+/// Replace the `..`, `/` and `\` of each component of a category with `_`
+fn sanitize_category(category: &str, context: &RecordProcessingContext) -> String {
+    let components: Vec<String> = category_components(category, context)
+        .iter()
+        .map(|component| component.replace("..", "_").replace(['/', '\\'], "_"))
+        .collect();
+    match context.category_path_separator {
+        Some(separator) if context.create_directory => components.join(&separator.to_string()),
+        _ => components.concat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_loading::{
+        expand_input_glob, read_category_map, read_header_map, read_join_table,
+    };
+    use crate::file_following::FollowState;
+    use crate::output_backend::{LocalBackend, NullBackend, OutputBackend};
+    use crate::output_encoding::OutputEncoding;
+    use crate::record_context::{CategoryTransform, HeaderCase};
+    use crate::row_hashing::RowHashAlgorithm;
+    use crate::test_context::TestContext;
+    use lazy_static::lazy_static;
+    use regex::Regex;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    lazy_static! {
+        static ref FILE_HEADERS: StringRecord = StringRecord::from(vec!["city", "state"]);
+        static ref HEADERS: StringRecord = StringRecord::from(vec!["city", "state", "year"]);
+    }
+
+    #[test]
+    fn test_split_file_by_category() {
+        let mut context = TestContext::new();
+
+        let input_file = PathBuf::from("assets/city.csv");
+        let output_dir = PathBuf::from("assets/tmp");
+        let delimiter = Delimiter::Comma;
+        let input_column = "State";
+
+        if !input_file.exists() {
+            panic!("Input file doesn't exist: {}", input_file.display());
+        }
+
+        context.add_file(output_dir.join("AK.csv"));
+        context.add_file(output_dir.join("AL.csv"));
+        context.add_file(output_dir.join("NY.csv"));
+        context.add_file(output_dir.join("CA.csv"));
+
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            ..Default::default()
+        };
+        split_file_by_category(&input_file, input_column, &delimiter, &split_context).unwrap();
+        let ak_file_path = format!("{}/AK.csv", output_dir.display());
+        let al_file_path = format!("{}/AL.csv", output_dir.display());
+
+        let ak_data = fs::read_to_string(ak_file_path).unwrap();
+        let al_data = fs::read_to_string(al_file_path).unwrap();
+
+        assert!(ak_data.contains("City|Population|Latitude|Longitude"));
+        assert!(ak_data.contains("Davidson Landing||65.241944|-165.2716667"));
+        assert!(ak_data.contains("Kenai|7610|60.5544444|-151.2583333"));
+
+        assert!(al_data.contains("City|Population|Latitude|Longitude"));
+        assert!(al_data.contains("Oakman||33.7133333|-87.38861111"));
+    }
+
+    #[test]
+    fn test_dedupe_across_chunks() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/dedupe"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 2,
+            dedupe: true,
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/duplicated_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        assert_eq!(summary.duplicates_removed, 2);
+        assert_eq!(ak_data, "City|Population\nKenai|7610\nKenai|7611\n");
+        assert_eq!(al_data, "City|Population\nSelma|18980\n");
+    }
+
+    #[test]
+    fn test_dedupe_by_key() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/dedupe_key"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 2,
+            dedupe: true,
+            dedupe_key: vec![String::from("City")],
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/duplicated_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert_eq!(summary.duplicates_removed, 3);
+        assert_eq!(ak_data, "City|Population\nKenai|7610\n");
+    }
+
+    #[test]
+    fn test_dedupe_global() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/dedupe_global"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 2,
+            dedupe_global: true,
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/cross_duplicated_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let il_data = fs::read_to_string(output_dir.join("IL.csv")).unwrap();
+        let or_data = fs::read_to_string(output_dir.join("OR.csv")).unwrap();
+        let ma_data = fs::read_to_string(output_dir.join("MA.csv")).unwrap();
+        assert_eq!(summary.global_duplicates_removed, 3);
+        assert_eq!(summary.duplicates_removed, 0);
+        assert_eq!(il_data, "City|Population\nSpringfield|114394\n");
+        assert_eq!(or_data, "City|Population\nSalem|175535\n");
+        assert_eq!(ma_data, "City|Population\nBoston|675647\n");
+    }
+
+    #[test]
+    fn test_dedupe_global_sorted_input() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/dedupe_global_sorted"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 2,
+            dedupe: true,
+            dedupe_global: true,
+            sorted_input: true,
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/cross_duplicated_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ma_data = fs::read_to_string(output_dir.join("MA.csv")).unwrap();
+        assert_eq!(summary.global_duplicates_removed, 3);
+        assert_eq!(ma_data, "City|Population\nBoston|675647\n");
+    }
+
+    #[test]
+    fn test_header_transform() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/header_transform"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            header_transform: Some(Arc::new(|header: &str| header.to_lowercase())),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert!(ak_data.starts_with("city|population|latitude|longitude\n"));
+        assert!(ak_data.contains("Kenai|7610|60.5544444|-151.2583333"));
+    }
+
+    #[test]
+    fn test_header_normalize() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/header_normalize"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            header_transform: Some(Arc::new(|header: &str| HeaderCase::Snake.normalize(header))),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/accented_city.csv"),
+            "City",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let bogota_data = fs::read_to_string(output_dir.join("Bogotá.csv")).unwrap();
+        assert!(bogota_data.starts_with("neighborhood\n"));
+
+        let headers = StringRecord::from(vec!["First Name", "first_name", "City"]);
+        let result = prepare_context(
+            Path::new("people.csv"),
+            &headers,
+            "City",
+            &Delimiter::Comma,
+            &split_context,
+        );
+        assert!(matches!(
+            result,
+            Err(AppError::HeaderCollision { first, second, header })
+                if first == "First Name" && second == "first_name" && header == "first_name"
+        ));
+    }
+
+    #[test]
+    fn test_category_path_separator() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/category_path"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            create_directory: true,
+            category_path_separator: Some('.'),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/hierarchical_city.csv"),
+            "Region",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let albany_file = output_dir.join("US/NY/Albany/hierarchical_city.csv");
+        let albany_data = fs::read_to_string(albany_file).unwrap();
+        assert_eq!(albany_data, "City|Population\nAlbany|93523\n");
+        assert!(output_dir.join("US/NY/Buffalo").is_dir());
+        assert!(output_dir.join("US/CA/Fresno").is_dir());
+    }
+
+    #[test]
+    fn test_category_path_separator_only() {
+        let mut context = TestContext::new();
+        let path_dir = context.add_dir(PathBuf::from("assets/tmp/category_path_only"));
+        let output_dir = path_dir.join("out");
+        fs::create_dir_all(&output_dir).unwrap();
+        let input_path = path_dir.join("region_city.csv");
+        fs::write(&input_path, "City,Region\nAlbany,US.NY\nNowhere,..\n").unwrap();
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            create_directory: true,
+            category_path_separator: Some('.'),
+            ..Default::default()
+        };
+
+        split_file_by_category(&input_path, "Region", &Delimiter::Comma, &split_context).unwrap();
+
+        let unknown_file = output_dir.join("unknown/region_city.csv");
+        assert_eq!(fs::read_to_string(unknown_file).unwrap(), "City\nNowhere\n");
+        assert!(!output_dir.join("region_city.csv").exists());
+    }
+
+    #[test]
+    fn test_shard_by_initial() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/shard_by_initial"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            shard_by_initial: true,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let mut shards: Vec<String> = fs::read_dir(&output_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        shards.sort();
+        assert_eq!(shards, vec!["A", "C", "N"]);
+        assert!(output_dir.join("A/AK.csv").is_file());
+        assert!(output_dir.join("A/AL.csv").is_file());
+        let ny_data = fs::read_to_string(output_dir.join("N/NY.csv")).unwrap();
+        assert_eq!(ny_data.lines().count(), 4);
+        assert_eq!(category_initial("ca"), "C");
+        assert_eq!(category_initial("9th"), "9");
+        assert_eq!(category_initial("-x"), "_");
+        assert_eq!(category_initial("Łódź"), "_");
+    }
+
+    #[test]
+    fn test_max_record_bytes_skip() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/max_record_bytes"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            max_record_bytes: Some(64),
+            oversized_record_policy: OversizedRecordPolicy::Skip,
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/oversized_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert_eq!(summary.oversized_records_skipped, 1);
+        assert_eq!(ak_data, "City|Population\nKenai|7610\nNome|3598\n");
+    }
+
+    #[test]
+    fn test_max_record_bytes_error() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/max_record_bytes_error"));
+        let split_context = RecordProcessingContext {
+            output_dir,
+            max_record_bytes: Some(64),
+            oversized_record_policy: OversizedRecordPolicy::Error,
+            ..Default::default()
+        };
+
+        let result = split_file_by_category(
+            Path::new("assets/oversized_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        );
+
+        assert!(matches!(
+            result,
+            Err(AppError::OversizedRecord { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_success_marker() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/success_marker"));
+        let failed_output_dir = context.add_dir(PathBuf::from("assets/tmp/success_marker_failed"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            success_marker: true,
+            ..Default::default()
+        };
+        let failed_split_context = RecordProcessingContext {
+            output_dir: failed_output_dir.clone(),
+            success_marker: true,
+            max_record_bytes: Some(64),
+            oversized_record_policy: OversizedRecordPolicy::Error,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let result = split_file_by_category(
+            Path::new("assets/oversized_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &failed_split_context,
+        );
+
+        assert!(output_dir.join(SUCCESS_MARKER).exists());
+        assert!(result.is_err());
+        assert!(!failed_output_dir.join(SUCCESS_MARKER).exists());
+    }
+
+    #[test]
+    fn test_max_runtime() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/max_runtime"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 2,
+            success_marker: true,
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert!(summary.timed_out);
+        assert_eq!(summary.created.len(), 1);
+        assert_eq!(ak_data.lines().count(), 3);
+        assert!(!output_dir.join(SUCCESS_MARKER).exists());
+    }
+
+    #[test]
+    fn test_follow() {
+        let mut context = TestContext::new();
+        let follow_dir = context.add_dir(PathBuf::from("assets/tmp/follow"));
+        let output_dir = follow_dir.join("out");
+        fs::create_dir_all(&output_dir).unwrap();
+        let input_path = follow_dir.join("growing_city.csv");
+        fs::write(&input_path, "City,State\nKenai,AK\nOakman,AL\n").unwrap();
+        let deadline: Instant = Instant::now() + Duration::from_millis(1500);
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            follow: Some(Arc::new(FollowState::new(Some(deadline)))),
+            deadline: Some(deadline),
+            ..Default::default()
+        };
+
+        let (summary, live_al_data) = thread::scope(|scope| {
+            let appender = scope.spawn(|| {
+                thread::sleep(Duration::from_millis(400));
+                let mut input = fs::OpenOptions::new()
+                    .append(true)
+                    .open(&input_path)
+                    .unwrap();
+                input.write_all(b"Sandfort,AL\nBuffalo,NY\n").unwrap();
+                thread::sleep(Duration::from_millis(500));
+                fs::read_to_string(output_dir.join("AL.csv")).unwrap()
+            });
+            let summary =
+                split_file_by_category(&input_path, "State", &Delimiter::Comma, &split_context)
+                    .unwrap();
+            (summary, appender.join().unwrap())
+        });
+
+        // The appended rows were written while the input was still followed
+        assert_eq!(live_al_data, "City\nOakman\nSandfort\n");
+        assert_eq!(summary.rows_processed, 4);
+        assert!(summary.timed_out);
+        let ny_data = fs::read_to_string(output_dir.join("NY.csv")).unwrap();
+        assert_eq!(ny_data, "City\nBuffalo\n");
+    }
+
+    #[test]
+    fn test_created_and_appended_files() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/created_appended"));
+        let split = || {
+            let split_context = RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                ..Default::default()
+            };
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &split_context,
+            )
+            .unwrap()
+        };
+
+        let first_summary = split();
+        let second_summary = split();
+
+        let mut created = first_summary.created.clone();
+        created.sort();
+        let mut appended = second_summary.appended.clone();
+        appended.sort();
+        assert_eq!(created.len(), 4);
+        assert!(first_summary.appended.is_empty());
+        assert!(second_summary.created.is_empty());
+        assert_eq!(appended, created);
+        assert!(created[0].ends_with("AK.csv"));
+    }
+
+    #[test]
+    fn test_sort_within_category() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/sort_within_category"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 2,
+            sort_by: Some("Population".parse().unwrap()),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ny_data = fs::read_to_string(output_dir.join("NY.csv")).unwrap();
+        let ca_data = fs::read_to_string(output_dir.join("CA.csv")).unwrap();
+        let first_fields = |data: &str| -> Vec<String> {
+            data.lines()
+                .skip(1)
+                .map(|line| line.split('|').next().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(
+            first_fields(&ny_data),
+            vec!["Albany", "Yonkers", "Brooklyn"]
+        );
+        assert_eq!(first_fields(&ca_data), vec!["Burbank", "Fresno", "Brea"]);
+    }
+
+    #[test]
+    fn test_sort_within_category_descending() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/sort_descending"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            sort_by: Some("City:desc".parse().unwrap()),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        let al_cities: Vec<&str> = al_data
+            .lines()
+            .skip(1)
+            .map(|line| line.split('|').next().unwrap())
+            .collect();
+        assert_eq!(
+            al_cities,
+            vec!["Selma", "Sandfort", "Richards Crossroads", "Oakman"]
+        );
+    }
+
+    #[test]
+    fn test_sort_within_category_spill() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/sort_spill"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 2,
+            sort_by: Some("Population".parse().unwrap()),
+            spill_threshold: Some(1),
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ny_data = fs::read_to_string(output_dir.join("NY.csv")).unwrap();
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        let first_fields = |data: &str| -> Vec<String> {
+            data.lines()
+                .skip(1)
+                .map(|line| line.split('|').next().unwrap().to_string())
+                .collect()
+        };
+        assert!(summary.spilled_segments > 0);
+        assert!(split_context.spilled_segments.lock().unwrap().is_empty());
+        assert_eq!(
+            first_fields(&ny_data),
+            vec!["Albany", "Yonkers", "Brooklyn"]
+        );
+        assert_eq!(
+            first_fields(&al_data),
+            vec!["Selma", "Oakman", "Richards Crossroads", "Sandfort"]
+        );
+    }
+
+    #[test]
+    fn test_compare_fields() {
+        assert_eq!(compare_fields("93523", "196425"), Ordering::Less);
+        assert_eq!(compare_fields("-1.5", "-2"), Ordering::Greater);
+        assert_eq!(compare_fields("10", ""), Ordering::Less);
+        assert_eq!(compare_fields("Albany", "Yonkers"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_ascii_file_names() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/ascii_file_names"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            ascii_file_names: true,
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/accented_city.csv"),
+            "City",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let bogota_data = fs::read_to_string(output_dir.join("Bogota.csv")).unwrap();
+        let mut transliterated = summary.renamed.clone();
+        transliterated.sort();
+        assert_eq!(bogota_data, "Neighborhood\nChapinero\nUsaquén\n");
+        assert!(output_dir.join("Medellin.csv").exists());
+        assert_eq!(
+            transliterated,
+            vec![
+                (String::from("Bogotá"), String::from("Bogota")),
+                (String::from("Medellín"), String::from("Medellin")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ascii_file_names_escape() {
+        let mut context = TestContext::new();
+        let escape_dir = context.add_dir(PathBuf::from("assets/tmp/ascii_file_names_escape"));
+        let input_path = escape_dir.join("half_city.csv");
+        fs::write(&input_path, "City,State\nKenai,AK\nHalfway,½\n").unwrap();
+        let output_dir = escape_dir.join("out");
+        fs::create_dir_all(&output_dir).unwrap();
+        let split_with = |policy: InvalidCategoryPolicy| {
+            let split_context = RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                ascii_file_names: true,
+                invalid_category_policy: policy,
+                ..Default::default()
+            };
+            split_file_by_category(&input_path, "State", &Delimiter::Comma, &split_context)
+        };
+
+        let result = split_with(InvalidCategoryPolicy::Error);
+        assert!(matches!(result, Err(AppError::InvalidCategory(category)) if category == "½"));
+        assert!(!output_dir.join("1").exists());
+
+        split_with(InvalidCategoryPolicy::Sanitize).unwrap();
+        let half_data = fs::read_to_string(output_dir.join("1_2.csv")).unwrap();
+        assert_eq!(half_data, "City\nHalfway\n");
+    }
+
+    #[test]
+    fn test_max_name_length() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/max_name_length"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            max_name_length: Some(24),
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/address_city.csv"),
+            "Address",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let mut file_names: Vec<String> = fs::read_dir(&output_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        file_names.sort();
+
+        assert_eq!(file_names.len(), 3);
+        assert!(file_names.contains(&String::from("Kenai.csv")));
+        assert!(file_names[..2]
+            .iter()
+            .all(|name| name.starts_with("1200 Pennsylvan-") && name.len() == 28));
+        let mut renamed: Vec<String> = summary.renamed.into_iter().map(|(c, _)| c).collect();
+        renamed.sort();
+        assert_eq!(
+            renamed,
+            [
+                "1200 Pennsylvania Avenue Northwest, Suite 100, Washington",
+                "1200 Pennsylvania Avenue Northwest, Suite 200, Washington"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shorten_category() {
+        // Without a length, names are capped to what the filesystem accepts
+        let shortened = shorten_category(&"é".repeat(200), &RecordProcessingContext::default());
+        let shortened = shortened.unwrap();
+        assert_eq!(shortened.len(), 243);
+        assert!(format!("{}.schema.json", shortened).len() <= MAX_FILE_NAME_BYTES);
+        assert_eq!(
+            shorten_category("Kenai", &RecordProcessingContext::default()),
+            None
+        );
+        let nested_context = RecordProcessingContext {
+            create_directory: true,
+            category_path_separator: Some('/'),
+            max_name_length: Some(20),
+            ..Default::default()
+        };
+        let nested = shorten_category("US/1200 Pennsylvania Avenue", &nested_context);
+        assert!(nested.unwrap().starts_with("US/1200 Pennsy-"));
+    }
+
+    #[test]
+    fn test_ascii_file_names_collision() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/ascii_collision"));
+        let split_context = RecordProcessingContext {
+            output_dir,
+            ascii_file_names: true,
+            ..Default::default()
+        };
+
+        let result = split_file_by_category(
+            Path::new("assets/colliding_city.csv"),
+            "City",
+            &Delimiter::Comma,
+            &split_context,
+        );
+
+        assert!(matches!(
+            result,
+            Err(AppError::CategoryNameCollision { file_name, .. }) if file_name == "Bogota"
+        ));
+    }
+
+    #[test]
+    fn test_filters_and() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/filters_and"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            filters: vec![
+                "Population > 100000".parse().unwrap(),
+                "State != NY".parse().unwrap(),
+            ],
+            filter_logic: FilterLogic::And,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ca_data = fs::read_to_string(output_dir.join("CA.csv")).unwrap();
+        assert_eq!(ca_data.lines().count(), 3);
+        assert!(ca_data.contains("Burbank|"));
+        assert!(ca_data.contains("Fresno|"));
+        assert!(!output_dir.join("NY.csv").exists());
+        assert!(!output_dir.join("AK.csv").exists());
+    }
+
+    #[test]
+    fn test_filters_or() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/filters_or"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            filters: vec![
+                "Population > 1000000".parse().unwrap(),
+                "State == AK".parse().unwrap(),
+            ],
+            filter_logic: FilterLogic::Or,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        let ny_data = fs::read_to_string(output_dir.join("NY.csv")).unwrap();
+        assert_eq!(ak_data.lines().count(), 3);
+        assert_eq!(ny_data.lines().count(), 2);
+        assert!(ny_data.contains("Brooklyn|"));
+        assert!(!output_dir.join("CA.csv").exists());
+    }
+
+    #[test]
+    fn test_split_file_by_columns() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/split_by_columns"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            ..Default::default()
+        };
+
+        split_file_by_columns(
+            Path::new("assets/city.csv"),
+            &[String::from("State"), String::from("City")],
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("by_State/AK.csv")).unwrap();
+        let kenai_data = fs::read_to_string(output_dir.join("by_City/Kenai.csv")).unwrap();
+        assert_eq!(
+            fs::read_dir(output_dir.join("by_State")).unwrap().count(),
+            4
+        );
+        assert_eq!(
+            fs::read_dir(output_dir.join("by_City")).unwrap().count(),
+            12
+        );
+        assert!(ak_data.contains("Kenai|7610|60.5544444|-151.2583333"));
+        assert_eq!(
+            kenai_data,
+            "State|Population|Latitude|Longitude\nAK|7610|60.5544444|-151.2583333\n"
+        );
+    }
+
+    #[test]
+    fn test_split_file_by_key_groups() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/split_by_key_groups"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            ..Default::default()
+        };
+        let key_regex = Regex::new(r"^logs/(?P<year>\d{4})/(?P<month>\d{2})/").unwrap();
+
+        let summary = split_file_by_key_groups(
+            Path::new("assets/dated_paths.csv"),
+            "Path",
+            &key_regex,
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let year_data = fs::read_to_string(output_dir.join("by_year/2023.csv")).unwrap();
+        let month_data = fs::read_to_string(output_dir.join("by_month/01.csv")).unwrap();
+        let unmatched_data = fs::read_to_string(output_dir.join("by_month/unknown.csv")).unwrap();
+        assert_eq!(fs::read_dir(output_dir.join("by_year")).unwrap().count(), 3);
+        assert_eq!(
+            fs::read_dir(output_dir.join("by_month")).unwrap().count(),
+            3
+        );
+        assert_eq!(year_data, "Size\n120\n80\n");
+        assert_eq!(month_data, "Size\n120\n300\n");
+        assert_eq!(unmatched_data, "Size\n5\n");
+        assert_eq!(summary.category_rows["by_month/unknown"], 1);
+        assert_eq!(summary.category_rows["by_year/unknown"], 1);
+        assert_eq!(summary.category_rows["by_year/2023"], 2);
+    }
+
+    #[test]
+    fn test_strip_quotes() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/strip_quotes"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            strip_quotes: true,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/quoted_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert_eq!(
+            ak_data,
+            "City|Nickname\nKenai|The Kenai\nNome|\"Say \"\"hi\"\" there\"\n"
+        );
+        assert_eq!(strip_quotes("\"Nome\""), "Nome");
+        assert_eq!(strip_quotes("'Nome'"), "'Nome'");
+        assert_eq!(strip_quotes("\"Nome'"), "\"Nome'");
+        assert_eq!(strip_quotes("\""), "\"");
+    }
+
+    #[test]
+    fn test_category_map() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/category_map"));
+        let category_map = read_category_map(Path::new("assets/region_map.csv")).unwrap();
+        let split = |unmapped_category_policy: UnmappedCategoryPolicy| {
+            let split_context = RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                category_map: Arc::new(category_map.clone()),
+                unmapped_category_policy,
+                ..Default::default()
+            };
+            split_file_by_category(
+                Path::new("assets/region_city.csv"),
+                "Region",
+                &Delimiter::Comma,
+                &split_context,
+            )
+            .unwrap()
+        };
+
+        let mut created = split(UnmappedCategoryPolicy::Raw).created;
+        created.sort();
+        assert_eq!(
+            created,
+            ["9.csv", "east.csv", "north.csv", "south.csv"]
+                .map(|file| output_dir.join(file).display().to_string())
+        );
+        let north_data = fs::read_to_string(output_dir.join("north.csv")).unwrap();
+        assert_eq!(north_data, "City\nAlbany\nYonkers\n");
+
+        fs::remove_dir_all(&output_dir).unwrap();
+        fs::create_dir_all(&output_dir).unwrap();
+        split(UnmappedCategoryPolicy::Null);
+        assert!(output_dir.join("unknown.csv").exists());
+        assert!(!output_dir.join("9.csv").exists());
+    }
+
+    #[test]
+    fn test_category_aliases() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/category_aliases"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            category_aliases: Arc::new(HashMap::from([
+                (String::from("NYC"), String::from("NY")),
+                (String::from("New York City"), String::from("NY")),
+            ])),
+            ..Default::default()
+        };
+
+        let mut created = split_file_by_category(
+            Path::new("assets/alias_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap()
+        .created;
+        created.sort();
+
+        assert_eq!(
+            created,
+            ["CA.csv", "NY.csv"].map(|file| output_dir.join(file).display().to_string())
+        );
+        let ny_data = fs::read_to_string(output_dir.join("NY.csv")).unwrap();
+        assert_eq!(ny_data, "City\nAlbany\nManhattan\nBrooklyn\n");
+    }
+
+    #[test]
+    fn test_categories_file() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/categories_file"));
+        let other_dir = context.add_dir(PathBuf::from("assets/tmp/categories_file_other"));
+        let expected_categories: Arc<HashSet<String>> = Arc::new(
+            ["AK", "AL", "CA", "NY", "TX"]
+                .map(String::from)
+                .into_iter()
+                .collect(),
+        );
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            expected_categories: expected_categories.clone(),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let tx_data = fs::read_to_string(output_dir.join("TX.csv")).unwrap();
+        assert_eq!(tx_data, "City|Population|Latitude|Longitude\n");
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert_eq!(ak_data.lines().count(), 3);
+
+        let strict_context = RecordProcessingContext {
+            output_dir: other_dir.clone(),
+            expected_categories: Arc::new(HashSet::from([String::from("AK")])),
+            ..Default::default()
+        };
+        assert!(matches!(
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &strict_context,
+            ),
+            Err(AppError::UnexpectedCategory(_))
+        ));
+        let other_context = RecordProcessingContext {
+            unexpected_category_policy: UnexpectedCategoryPolicy::Other,
+            writers: Arc::default(),
+            ..strict_context
+        };
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &other_context,
+        )
+        .unwrap();
+        let other_data = fs::read_to_string(other_dir.join("other.csv")).unwrap();
+        assert_eq!(other_data.lines().count(), 11);
+    }
+
+    #[test]
+    fn test_join_file() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/join_file"));
+        let join_table =
+            read_join_table(Path::new("assets/zip_regions.csv"), "Zip", "Region").unwrap();
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            category_map: Arc::new(join_table),
+            unmapped_category_policy: UnmappedCategoryPolicy::Null,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/zip_city.csv"),
+            "Zip",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let northeast_data = fs::read_to_string(output_dir.join("northeast.csv")).unwrap();
+        assert_eq!(northeast_data, "City\nAlbany\nYonkers\n");
+        let unknown_data = fs::read_to_string(output_dir.join("unknown.csv")).unwrap();
+        assert_eq!(unknown_data, "City\nKenai\n");
+        assert!(output_dir.join("west.csv").exists());
+        assert!(output_dir.join("south.csv").exists());
+    }
+
+    #[test]
+    fn test_single_threaded() {
+        let mut context = TestContext::new();
+        let parallel_dir = context.add_dir(PathBuf::from("assets/tmp/parallel"));
+        let sequential_dir = context.add_dir(PathBuf::from("assets/tmp/single_threaded"));
+        let split = |output_dir: &PathBuf, single_threaded: bool| {
+            let split_context = RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                chunk_size: 5,
+                single_threaded,
+                ..Default::default()
+            };
+            let mut created = split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &split_context,
+            )
+            .unwrap()
+            .created;
+            if !single_threaded {
+                created.sort();
+            }
+            created
+        };
+
+        let parallel = split(&parallel_dir, false);
+        let sequential = split(&sequential_dir, true);
+
+        // The records are written as they are read, so the files are created in the order
+        // of the first record of each category
+        let names: Vec<String> = sequential
+            .iter()
+            .map(|file| {
+                Path::new(file)
+                    .file_stem()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(names, ["AK", "AL", "CA", "NY"]);
+
+        assert_eq!(parallel.len(), sequential.len());
+        for (parallel_file, sequential_file) in parallel.iter().zip(&sequential) {
+            assert_eq!(
+                fs::read(parallel_file).unwrap(),
+                fs::read(sequential_file).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_concurrency_strategies() {
+        let mut context = TestContext::new();
+        let split = |output_dir: &PathBuf, concurrency_strategy: ConcurrencyStrategy| {
+            let split_context = RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                chunk_size: 5,
+                concurrency_strategy,
+                ..Default::default()
+            };
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &split_context,
+            )
+            .unwrap();
+            let mut files: Vec<(PathBuf, Vec<u8>)> = fs::read_dir(output_dir)
+                .unwrap()
+                .map(|entry| {
+                    let path = entry.unwrap().path();
+                    (
+                        PathBuf::from(path.file_name().unwrap()),
+                        fs::read(&path).unwrap(),
+                    )
+                })
+                .collect();
+            files.sort();
+            files
+        };
+
+        let strategies = [
+            ("fold_reduce", ConcurrencyStrategy::FoldReduce),
+            ("dashmap", ConcurrencyStrategy::Dashmap),
+            ("channel", ConcurrencyStrategy::Channel),
+        ];
+        let outputs: Vec<Vec<(PathBuf, Vec<u8>)>> = strategies
+            .iter()
+            .map(|(name, strategy)| {
+                let output_dir =
+                    context.add_dir(PathBuf::from(format!("assets/tmp/strategy_{}", name)));
+                split(&output_dir, *strategy)
+            })
+            .collect();
+
+        assert_eq!(outputs[0].len(), 4);
+        assert_eq!(outputs[0], outputs[1]);
+        assert_eq!(outputs[0], outputs[2]);
+    }
+
+    #[test]
+    fn test_header_with_output_delimiter() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/quoted_header"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            delimiter: Delimiter::Comma.as_byte(),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/quoted_header_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let al_path = output_dir.join("AL.csv");
+        let al_data = fs::read_to_string(&al_path).unwrap();
+        assert_eq!(al_data, "City,\"Population, est.\"\nSelma,\"18,980\"\n");
+        let mut reader = ReaderBuilder::new().from_path(&al_path).unwrap();
+        assert_eq!(
+            reader.headers().unwrap(),
+            &StringRecord::from(vec!["City", "Population, est."])
+        );
+    }
+
+    #[test]
+    fn test_expand_json() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/expand_json"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            expand_json: Some(String::from("Info")),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/json_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert_eq!(
+            ak_data,
+            "City|Info.population|Info.county|Info.capital\nKenai|7610|Kenai Peninsula|\nNome|||\n"
+        );
+        let ny_data = fs::read_to_string(output_dir.join("NY.csv")).unwrap();
+        assert_eq!(
+            ny_data,
+            "City|Info.population|Info.county|Info.capital\nAlbany|99224||true\n"
+        );
+    }
+
+    #[test]
+    fn test_trailer_rows() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/trailer_rows"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            trailer_rows: 1,
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/footer_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        assert_eq!(summary.rows_processed, 3);
+        assert_eq!(summary.created.len(), 2);
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        assert_eq!(al_data, "City|Population\nOakman|\nSelma|18980\n");
+        assert_eq!(drop_trailer(1..=3, 5).count(), 0);
+        assert_eq!(drop_trailer(1..=3, 0).collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_normalize_line_endings() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/normalize_line_endings"));
+        let split = |normalize_line_endings: bool| {
+            let split_context = RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                normalize_line_endings,
+                ..Default::default()
+            };
+            split_file_by_category(
+                Path::new("assets/mixed_endings.csv"),
+                "State",
+                &Delimiter::Comma,
+                &split_context,
+            )
+            .unwrap();
+            ["AK", "AL", "CA"]
+                .map(|state| fs::read_to_string(output_dir.join(format!("{}.csv", state))).unwrap())
+        };
+
+        let [_, al_data, _] = split(false);
+        assert_eq!(al_data, "City\n\"Oakman\r\"\nSelma\n");
+        fs::remove_dir_all(&output_dir).unwrap();
+        fs::create_dir_all(&output_dir).unwrap();
+        let files = split(true);
+        assert_eq!(files[1], "City\nOakman\nSelma\n");
+        assert!(files.iter().all(|data| !data.contains('\r')));
+    }
+
+    #[test]
+    fn test_skip_empty_lines() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/skip_empty_lines"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            skip_empty_lines: true,
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/blank_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        assert_eq!(summary.empty_lines_skipped, 2);
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        assert_eq!(al_data, "City|Population\nOakman|\nSelma|18980\n");
+        let unknown_data = fs::read_to_string(output_dir.join("unknown.csv")).unwrap();
+        assert_eq!(unknown_data, "City|Population\n|\n");
+
+        let result = split_file_by_category(
+            Path::new("assets/blank_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &RecordProcessingContext {
+                output_dir,
+                ..Default::default()
+            },
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sorted_input() {
+        let mut context = TestContext::new();
+        let sorted_dir = context.add_dir(PathBuf::from("assets/tmp/sorted_input"));
+        let grouped_dir = context.add_dir(PathBuf::from("assets/tmp/sorted_input_grouped"));
+        let sorted_context = RecordProcessingContext {
+            output_dir: sorted_dir.clone(),
+            chunk_size: 4,
+            sorted_input: true,
+            check_sorted: true,
+            ..Default::default()
+        };
+        let grouped_context = RecordProcessingContext {
+            output_dir: grouped_dir.clone(),
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &sorted_context,
+        )
+        .unwrap();
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &grouped_context,
+        )
+        .unwrap();
+
+        // Only the writer of the last category is still open
+        let writers = sorted_context.writers.lock().unwrap();
+        assert_eq!(writers.keys().collect::<Vec<_>>(), ["NY"]);
+        assert_eq!(summary.created.len(), 4);
+        for state in ["AK", "AL", "CA", "NY"] {
+            let file = format!("{}.csv", state);
+            assert_eq!(
+                fs::read(sorted_dir.join(&file)).unwrap(),
+                fs::read(grouped_dir.join(&file)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_drop_columns() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/drop_columns"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            dropped_columns: vec![String::from("Latitude")],
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert_eq!(
+            ak_data,
+            "City|Population|Longitude\nDavidson Landing||-165.2716667\nKenai|7610|-151.2583333\n"
+        );
+
+        let result = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &RecordProcessingContext {
+                output_dir,
+                dropped_columns: vec![String::from("Altitude")],
+                ..Default::default()
+            },
+        );
+        assert!(matches!(result, Err(AppError::ColumnNotFound(column)) if column == "Altitude"));
+    }
+
+    #[test]
+    fn test_always_quote_for() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/always_quote_for"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            always_quoted_categories: vec![String::from("AK")],
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert_eq!(
+            ak_data.lines().nth(2).unwrap(),
+            "\"Kenai\"|\"7610\"|\"60.5544444\"|\"-151.2583333\""
+        );
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        assert_eq!(
+            al_data.lines().next().unwrap(),
+            "City|Population|Latitude|Longitude"
+        );
+        assert!(!al_data.contains('"'));
+    }
+
+    #[test]
+    fn test_quote_empty() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/quote_empty"));
+        let comma_dir = context.add_dir(PathBuf::from("assets/tmp/quote_empty_comma"));
+        let split = |input: &str, output_dir: &PathBuf| {
+            split_file_by_category(
+                Path::new(input),
+                "State",
+                &Delimiter::Comma,
+                &RecordProcessingContext {
+                    output_dir: output_dir.clone(),
+                    quote_empty: true,
+                    delimiter: b',',
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        };
+
+        split("assets/city.csv", &output_dir);
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        let lines: Vec<&str> = ak_data.lines().collect();
+        assert_eq!(lines[0], "City,Population,Latitude,Longitude");
+        assert_eq!(lines[1], "Davidson Landing,\"\",65.241944,-165.2716667");
+        assert_eq!(lines[2], "Kenai,7610,60.5544444,-151.2583333");
+
+        split("assets/comma_city.csv", &comma_dir);
+        let ak_data = fs::read_to_string(comma_dir.join("AK.csv")).unwrap();
+        assert_eq!(
+            ak_data,
+            "City\n\"Kenai, Borough\"\n\"Nome \"\"Gold\"\" City\"\n"
+        );
+    }
+
+    #[test]
+    fn test_fixed_width() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/fixed_width"));
+        split_file_by_category(
+            Path::new("assets/fixed_width_city.txt"),
+            "State",
+            &Delimiter::Comma,
+            &RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                fixed_width: Some("0-20,20-26,26-36".parse().unwrap()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(output_dir.join("AL.csv")).unwrap(),
+            "City|Population\nOakman|\nSelma|18980\n"
+        );
+        assert_eq!(
+            fs::read_to_string(output_dir.join("NY.csv")).unwrap(),
+            "City|Population\nAlbany|93523\n"
+        );
+    }
+
+    #[test]
+    fn test_write_header() {
+        let mut context = TestContext::new();
+        let always_dir = context.add_dir(PathBuf::from("assets/tmp/write_header_always"));
+        let never_dir = context.add_dir(PathBuf::from("assets/tmp/write_header_never"));
+        let split = |output_dir: &PathBuf, write_header: WriteHeader| {
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &RecordProcessingContext {
+                    output_dir: output_dir.clone(),
+                    write_header,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        };
+        let headers = "City|Population|Latitude|Longitude\n";
+        let ak_records =
+            "Davidson Landing||65.241944|-165.2716667\nKenai|7610|60.5544444|-151.2583333\n";
+
+        split(&always_dir, WriteHeader::Always);
+        split(&always_dir, WriteHeader::Always);
+        let ak_data = fs::read_to_string(always_dir.join("AK.csv")).unwrap();
+        assert_eq!(ak_data, [headers, ak_records, headers, ak_records].concat());
+
+        split(&never_dir, WriteHeader::Never);
+        let ak_data = fs::read_to_string(never_dir.join("AK.csv")).unwrap();
+        assert_eq!(ak_data, ak_records);
+    }
+
+    #[test]
+    fn test_excel_sep_hint() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/excel_sep_hint"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            delimiter: b';',
+            excel_sep_hint: true,
+            ..Default::default()
+        };
+
+        for _ in 0..2 {
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &RecordProcessingContext {
+                    writers: Arc::default(),
+                    ..split_context.clone()
+                },
+            )
+            .unwrap();
+        }
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        let lines: Vec<&str> = ak_data.lines().collect();
+        assert_eq!(
+            lines[..3],
+            [
+                "sep=;",
+                "City;Population;Latitude;Longitude",
+                "Davidson Landing;;65.241944;-165.2716667"
+            ]
+        );
+        assert_eq!(
+            lines.iter().filter(|line| line.starts_with("sep=")).count(),
+            1
+        );
+        assert_eq!(lines.len(), 6);
+
+        // An empty file left by another tool still gets the hint on its first line
+        let empty_dir = context.add_dir(PathBuf::from("assets/tmp/excel_sep_hint_empty"));
+        fs::write(empty_dir.join("AK.csv"), "").unwrap();
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &RecordProcessingContext {
+                output_dir: empty_dir.clone(),
+                writers: Arc::default(),
+                ..split_context.clone()
+            },
+        )
+        .unwrap();
+        let ak_data = fs::read_to_string(empty_dir.join("AK.csv")).unwrap();
+        assert!(ak_data.starts_with("sep=;\n"));
+    }
+
+    #[test]
+    fn test_run_id_column() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/run_id_column"));
+        let split = |run_id: &str| {
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &RecordProcessingContext {
+                    output_dir: output_dir.clone(),
+                    run_id_column: Some(run_id.to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        };
+
+        split("run-1");
+        split("run-2");
+        let ny_data = fs::read_to_string(output_dir.join("NY.csv")).unwrap();
+        let lines: Vec<&str> = ny_data.lines().collect();
+        assert_eq!(lines[0], "City|Population|Latitude|Longitude|run_id");
+        assert_eq!(lines[1], "Albany|93523|42.6525000|-73.7566667|run-1");
+        let run_ids: Vec<&str> = lines[1..]
+            .iter()
+            .map(|line| line.rsplit('|').next().unwrap())
+            .collect();
+        assert_eq!(
+            run_ids,
+            ["run-1", "run-1", "run-1", "run-2", "run-2", "run-2"]
+        );
+    }
+
+    #[test]
+    fn test_row_hash_column() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/row_hash_column"));
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                row_hash_column: Some(String::from("row_hash")),
+                row_hash_algorithm: RowHashAlgorithm::Sha256,
+                run_id_column: Some(String::from("run-1")),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let ny_data = fs::read_to_string(output_dir.join("NY.csv")).unwrap();
+        let lines: Vec<&str> = ny_data.lines().collect();
+        assert_eq!(
+            lines[0],
+            "City|Population|Latitude|Longitude|row_hash|run_id"
+        );
+        for line in &lines[1..] {
+            let fields: Vec<&str> = line.split('|').collect();
+            assert_eq!(
+                fields[4],
+                hash_row(
+                    &StringRecord::from(fields[..4].to_vec()),
+                    RowHashAlgorithm::Sha256
+                )
+            );
+            assert_eq!(fields[5], "run-1");
+        }
+    }
+
+    #[test]
+    fn test_split_files_by_category() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/split_files"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 1,
+            ..Default::default()
+        };
+        let inputs: Vec<(PathBuf, Delimiter)> = expand_input_glob("assets/glob_city/*.csv")
+            .unwrap()
+            .into_iter()
+            .map(|path| (path, Delimiter::Comma))
+            .collect();
+
+        let summary = split_files_by_category(&inputs, "State", &split_context).unwrap();
+
+        assert_eq!(summary.rows_processed, 9);
+        for (file, cities) in [
+            ("AK.csv", vec!["Kenai", "Kenai", "Sitka"]),
+            ("AL.csv", vec!["Oakman", "Selma", "Selma"]),
+            ("CA.csv", vec!["Brea", "Fresno"]),
+            ("NY.csv", vec!["Albany"]),
+        ] {
+            let data = fs::read_to_string(output_dir.join(file)).unwrap();
+            let mut lines: Vec<&str> = data.lines().collect();
+            assert_eq!(lines.remove(0), "City");
+            lines.sort();
+            assert_eq!(lines, cities);
+        }
+    }
+
+    #[test]
+    fn test_headers_only() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/headers_only"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            ..Default::default()
+        };
+
+        let mut created = split_file_headers_only(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap()
+        .created;
+        created.sort();
+
+        assert_eq!(
+            created,
+            ["AK.csv", "AL.csv", "CA.csv", "NY.csv"]
+                .map(|file| output_dir.join(file).display().to_string())
+        );
+        for file in &created {
+            let data = fs::read_to_string(file).unwrap();
+            assert_eq!(data, "City|Population|Latitude|Longitude\n");
+        }
+    }
+
+    /// Local backend failing to open more than `cap` files at once like a process out of
+    /// file handles
+    struct CappedBackend {
+        cap: usize,
+        open: Arc<AtomicUsize>,
+    }
+
+    struct CappedWriter {
+        inner: OutputWriter,
+        open: Arc<AtomicUsize>,
+    }
+
+    impl Write for CappedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Drop for CappedWriter {
+        fn drop(&mut self) {
+            self.open.fetch_sub(1, AtomicOrdering::SeqCst);
+        }
+    }
+
+    impl OutputBackend for CappedBackend {
+        fn existing_len(&self, path: &Path) -> std::io::Result<Option<u64>> {
+            LocalBackend.existing_len(path)
+        }
+
+        fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+            LocalBackend.create_dir_all(path)
+        }
+
+        fn open_append(&self, path: &Path) -> std::io::Result<OutputWriter> {
+            if self.open.load(AtomicOrdering::SeqCst) == self.cap {
+                return Err(Error::from_raw_os_error(24));
+            }
+            self.open.fetch_add(1, AtomicOrdering::SeqCst);
+            Ok(Box::new(CappedWriter {
+                inner: LocalBackend.open_append(path)?,
+                open: self.open.clone(),
+            }))
+        }
+
+        fn create(&self, path: &Path) -> std::io::Result<OutputWriter> {
+            LocalBackend.create(path)
+        }
+    }
+
+    #[test]
+    fn test_retry_open() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/retry_open"));
+        let split = |retry_open: usize| {
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &RecordProcessingContext {
+                    output_dir: output_dir.clone(),
+                    output_backend: Arc::new(CappedBackend {
+                        cap: 2,
+                        open: Arc::default(),
+                    }),
+                    single_threaded: true,
+                    chunk_size: 3,
+                    retry_open,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let result = split(0);
+        assert!(matches!(result, Err(AppError::Io(error)) if is_out_of_file_handles(&error)));
+        fs::remove_dir_all(&output_dir).unwrap();
+        fs::create_dir_all(&output_dir).unwrap();
+
+        let summary = split(3).unwrap();
+        assert_eq!(summary.created.len(), 4);
+        assert!(summary.appended.is_empty());
+        assert_eq!(
+            fs::read_to_string(output_dir.join("AL.csv")).unwrap(),
+            "City|Population|Latitude|Longitude\nOakman||33.7133333|-87.38861111\nRichards Crossroads||31.7369444|-85.2644444\nSandfort||32.3380556|-85.2233333\nSelma|18980|32.4072222|-87.0211111\n"
+        );
+        assert_eq!(
+            fs::read_to_string(output_dir.join("NY.csv"))
+                .unwrap()
+                .lines()
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_retry_open_expected_categories() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/retry_open_expected"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            output_backend: Arc::new(CappedBackend {
+                cap: 2,
+                open: Arc::default(),
+            }),
+            single_threaded: true,
+            chunk_size: 3,
+            retry_open: 3,
+            expected_categories: Arc::new(["AK", "AL", "CA", "NY"].map(String::from).into()),
+            unexpected_category_policy: UnexpectedCategoryPolicy::Error,
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        assert_eq!(summary.created.len(), 4);
+        assert_eq!(
+            fs::read_to_string(output_dir.join("NY.csv"))
+                .unwrap()
+                .lines()
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert!(retry_delay(1) < RETRY_OPEN_DELAY * 2);
+        assert!(retry_delay(100) <= MAX_RETRY_OPEN_DELAY + RETRY_OPEN_DELAY);
+    }
+
+    #[test]
+    fn test_key_columns() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/key_columns"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            key_expr: Some(KeyExpr::join_columns(
+                &[String::from("State"), String::from("City")],
+                "--",
+            )),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State--City",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(output_dir.join("AK--Kenai.csv")).unwrap(),
+            "City|State|Population|Latitude|Longitude\nKenai|AK|7610|60.5544444|-151.2583333\n"
+        );
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 12);
+    }
+
+    #[test]
+    fn test_force_create_dir() {
+        let mut context = TestContext::new();
+        context.add_dir(PathBuf::from("assets/tmp/force_create_dir"));
+        let output_dir = PathBuf::from("assets/tmp/force_create_dir/nested/path");
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            force_create_dir: true,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        assert!(output_dir.is_dir());
+        assert!(output_dir.join("AK.csv").exists());
+    }
+
+    #[test]
+    fn test_tee() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/tee"));
+        let tee_path = output_dir.join("all.csv");
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            filters: vec!["Population > 1000".parse().unwrap()],
+            tee_path: Some(tee_path.clone()),
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let tee_data: String = fs::read_to_string(&tee_path).unwrap();
+        let mut lines = tee_data.lines();
+        assert_eq!(lines.next(), Some("City|Population|Latitude|Longitude"));
+        assert_eq!(lines.count(), summary.category_rows.values().sum::<usize>());
+        assert!(tee_data.contains("Kenai|7610|"));
+        assert!(!tee_data.contains("Davidson Landing"));
+    }
+
+    #[test]
+    fn test_output_encoding() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/output_encoding"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            output_encoding: OutputEncoding::Windows1252,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/accented_city.csv"),
+            "City",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let bogota_data: Vec<u8> = fs::read(output_dir.join("Bogotá.csv")).unwrap();
+        assert_eq!(bogota_data, b"Neighborhood\nChapinero\nUsaqu\xe9n\n");
+        let (decoded, _, had_errors) = encoding_rs::WINDOWS_1252.decode(&bogota_data);
+        assert!(!had_errors);
+        assert_eq!(decoded, "Neighborhood\nChapinero\nUsaquén\n");
+    }
+
+    #[test]
+    fn test_max_errors() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/max_errors"));
+        let split = |max_errors: usize| {
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &RecordProcessingContext {
+                    output_dir: output_dir.clone(),
+                    chunk_size: 5,
+                    max_record_bytes: Some(37),
+                    max_errors: Some(max_errors),
+                    ..Default::default()
+                },
+            )
+        };
+
+        let summary = split(3).unwrap();
+        assert_eq!(summary.oversized_records_skipped, 3);
+        let result = split(2);
+        assert!(matches!(
+            result,
+            Err(AppError::TooManyErrors {
+                errors: 3,
+                max_errors: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_emit_schema() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/emit_schema"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 5,
+            emit_schema: true,
+            count_trailer: Some(CountTrailer::Comment),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_schema = fs::read_to_string(output_dir.join("AK.schema.json")).unwrap();
+        assert_eq!(
+            ak_schema,
+            concat!(
+                r#"[{"name":"City","type":"text","nullable":false},"#,
+                r#"{"name":"Population","type":"int","nullable":true},"#,
+                r#"{"name":"Latitude","type":"float","nullable":false},"#,
+                r#"{"name":"Longitude","type":"float","nullable":false}]"#,
+                "\n"
+            )
+        );
+        for state in ["AL", "CA", "NY"] {
+            assert!(output_dir.join(format!("{}.schema.json", state)).exists());
+        }
+    }
+
+    #[test]
+    fn test_count_trailer() {
+        let mut context = TestContext::new();
+        let comment_dir = context.add_dir(PathBuf::from("assets/tmp/count_trailer_comment"));
+        let sidecar_dir = context.add_dir(PathBuf::from("assets/tmp/count_trailer_sidecar"));
+        let split = |output_dir: &PathBuf, count_trailer: CountTrailer| {
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &RecordProcessingContext {
+                    output_dir: output_dir.clone(),
+                    chunk_size: 5,
+                    count_trailer: Some(count_trailer),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let summary = split(&comment_dir, CountTrailer::Comment);
+        let al_data = fs::read_to_string(comment_dir.join("AL.csv")).unwrap();
+        let trailer = al_data.lines().last().unwrap();
+        assert_eq!(trailer, format!("#ROWS={}", summary.category_rows["AL"]));
+        assert_eq!(al_data.lines().count(), summary.category_rows["AL"] + 2);
+        assert_eq!(al_data.matches("#ROWS=").count(), 1);
+
+        let summary = split(&sidecar_dir, CountTrailer::Sidecar);
+        for (category, rows) in &summary.category_rows {
+            let count = fs::read_to_string(sidecar_dir.join(format!("{}.count", category)));
+            assert_eq!(count.unwrap(), format!("{}\n", rows));
+        }
+        assert!(!fs::read_to_string(sidecar_dir.join("AL.csv"))
+            .unwrap()
+            .contains("#ROWS="));
+
+        // The trailer is left out when the split file is read back with the same character
+        let semicolon_dir = context.add_dir(PathBuf::from("assets/tmp/count_trailer_semicolon"));
+        let split_context = RecordProcessingContext {
+            output_dir: semicolon_dir.clone(),
+            count_trailer: Some(CountTrailer::Comment),
+            comment_char: Some(b';'),
+            ..Default::default()
+        };
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let al_path = semicolon_dir.join("AL.csv");
+        let al_data = fs::read_to_string(&al_path).unwrap();
+        let trailer = al_data.lines().last().unwrap();
+        assert_eq!(trailer, format!(";ROWS={}", summary.category_rows["AL"]));
+        let mut reader =
+            read_file(&al_path, &split_context.reader_config(&Delimiter::Pipe)).unwrap();
+        assert_eq!(reader.records().count(), summary.category_rows["AL"]);
+    }
+
+    #[test]
+    fn test_header_map() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/header_map"));
+        let header_map = read_header_map(Path::new("assets/header_map.csv")).unwrap();
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            header_map: Arc::new(header_map),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert_eq!(ak_data, "City|Population\nDavidson Landing|\nKenai|7610\n");
+        let ny_data = fs::read_to_string(output_dir.join("NY.csv")).unwrap();
+        assert!(ny_data.starts_with("Latitude|Longitude|City\n42.6525000|-73.7566667|Albany\n"));
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        assert!(al_data.starts_with("City|Population|Latitude|Longitude\n"));
+    }
+
+    #[test]
+    fn test_expect_headers() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/expect_headers"));
+        let split = |expected_headers: &[&str], expect_headers_subset: bool| {
+            let split_context = RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                expected_headers: expected_headers.iter().map(|h| h.to_string()).collect(),
+                expect_headers_subset,
+                ..Default::default()
+            };
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &split_context,
+            )
+        };
+
+        assert!(split(
+            &["City", "State", "Population", "Latitude", "Longitude"],
+            false
+        )
+        .is_ok());
+        assert!(matches!(
+            split(&["State", "City", "Population", "Latitude", "Longitude"], false),
+            Err(AppError::UnexpectedHeaders { difference, .. })
+                if difference == "column 0 is \"City\" instead of \"State\""
+        ));
+        assert!(matches!(
+            split(&["City", "State"], false),
+            Err(AppError::UnexpectedHeaders { difference, .. })
+                if difference == "5 columns instead of 2"
+        ));
+        assert!(split(&["State", "City"], true).is_ok());
+        assert!(matches!(
+            split(&["State", "Year"], true),
+            Err(AppError::UnexpectedHeaders { difference, .. })
+                if difference == "missing columns [\"Year\"]"
+        ));
+    }
+
+    #[test]
+    fn test_schema_drift() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/schema_drift"));
+        let split_context = RecordProcessingContext {
+            output_dir,
+            ..Default::default()
+        };
+        let split = |path: &str, split_context: &RecordProcessingContext| {
+            split_file_by_category(Path::new(path), "State", &Delimiter::Comma, split_context)
+        };
+
+        split("assets/city.csv", &split_context).unwrap();
+        let result = split("assets/drifted_city.csv", &split_context);
+
+        assert!(matches!(
+            result,
+            Err(AppError::SchemaDrift { difference, .. }) if difference == "6 columns instead of 5"
+        ));
+    }
+
+    #[test]
+    fn test_allow_schema_drift() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/allow_schema_drift"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            allow_schema_drift: true,
+            ..Default::default()
+        };
+
+        for path in ["assets/city.csv", "assets/drifted_city.csv"] {
+            split_file_by_category(Path::new(path), "State", &Delimiter::Comma, &split_context)
+                .unwrap();
+        }
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert!(ak_data.ends_with("Nome|3598|64.5011111|-165.4063889|Seward\n"));
+    }
+
+    #[test]
+    fn test_writer_thread() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/writer_thread"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 2,
+            dedupe: true,
+            writer_thread: true,
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/duplicated_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        assert_eq!(summary.duplicates_removed, 2);
+        assert_eq!(summary.created.len(), 2);
+        assert_eq!(ak_data, "City|Population\nKenai|7610\nKenai|7611\n");
+        assert_eq!(al_data, "City|Population\nSelma|18980\n");
+        assert_eq!(split_context.writers.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_writer_thread_channel_capacity() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/channel_capacity"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 1,
+            writer_thread: true,
+            channel_capacity: 1,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        let al_cities: Vec<&str> = al_data
+            .lines()
+            .map(|line| line.split('|').next().unwrap())
+            .collect();
+        assert_eq!(
+            al_cities,
+            vec!["City", "Oakman", "Richards Crossroads", "Sandfort", "Selma"]
+        );
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 4);
+    }
+
+    #[test]
+    fn test_row_range() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/row_range"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 2,
+            row_range: Some("3:5".parse().unwrap()),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let al_data = fs::read_to_string(output_dir.join("AL.csv")).unwrap();
+        let al_cities: Vec<&str> = al_data
+            .lines()
+            .skip(1)
+            .map(|line| line.split('|').next().unwrap())
+            .collect();
+        assert_eq!(al_cities, vec!["Oakman", "Richards Crossroads", "Sandfort"]);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 1);
+    }
+
+    /// Split the fixture with categories escaping the output directory under a policy
+    fn split_invalid_categories(
+        output_dir: &Path,
+        policy: InvalidCategoryPolicy,
+    ) -> Result<SplitSummary, AppError> {
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.to_path_buf(),
+            invalid_category_policy: policy,
+            ..Default::default()
+        };
+        split_file_by_category(
+            Path::new("assets/invalid_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+    }
+
+    #[test]
+    fn test_invalid_category_skip() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/invalid_category_skip"));
+
+        let summary = split_invalid_categories(&output_dir, InvalidCategoryPolicy::Skip).unwrap();
+
+        assert_eq!(summary.invalid_category_records_skipped, 2);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 1);
+        assert!(output_dir.join("AK.csv").exists());
+    }
+
+    #[test]
+    fn test_invalid_category_skip_ascii() {
+        let mut context = TestContext::new();
+        let skip_dir = context.add_dir(PathBuf::from("assets/tmp/invalid_category_skip_ascii"));
+        let input_path = context.add_file(skip_dir.join("half_city.csv"));
+        fs::write(&input_path, "City,State\nKenai,AK\nHalfway,½\n").unwrap();
+        let output_dir = context.add_dir(skip_dir.join("out"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            invalid_category_policy: InvalidCategoryPolicy::Skip,
+            ascii_file_names: true,
+            ..Default::default()
+        };
+
+        let summary =
+            split_file_by_category(&input_path, "State", &Delimiter::Comma, &split_context)
+                .unwrap();
+
+        assert_eq!(summary.invalid_category_records_skipped, 1);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 1);
+        assert!(output_dir.join("AK.csv").exists());
+    }
+
+    #[test]
+    fn test_invalid_category_sanitize() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/invalid_category_sanitize"));
+
+        split_invalid_categories(&output_dir, InvalidCategoryPolicy::Sanitize).unwrap();
+
+        let etc_data = fs::read_to_string(output_dir.join("__etc.csv")).unwrap();
+        assert_eq!(etc_data, "City\nNowhere\n");
+        assert!(output_dir.join("A_B.csv").exists());
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_invalid_category_error() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/invalid_category_error"));
+
+        let result = split_invalid_categories(&output_dir, InvalidCategoryPolicy::Error);
+
+        assert!(matches!(result, Err(AppError::InvalidCategory(_))));
+    }
+
+    #[test]
+    fn test_category_validate() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/category_validate"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            invalid_category_policy: InvalidCategoryPolicy::Invalid,
+            category_pattern: Some(Regex::new("^[A-Z]{2}$").unwrap()),
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/invalid_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let invalid_data = fs::read_to_string(output_dir.join("invalid.csv")).unwrap();
+        assert_eq!(invalid_data, "City\nNowhere\nSlash\n");
+        assert_eq!(summary.category_rows["AK"], 1);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 2);
+        let error_context = RecordProcessingContext {
+            output_dir: context.add_dir(PathBuf::from("assets/tmp/category_validate_error")),
+            category_pattern: Some(Regex::new("^A[KL]$").unwrap()),
+            ..Default::default()
+        };
+        let result = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &error_context,
+        );
+        assert!(matches!(
+            result,
+            Err(AppError::CategoryMismatch { category, .. }) if category == "CA" || category == "NY"
+        ));
+    }
+
+    #[test]
+    fn test_category_validate_assigned() {
+        let mut context = TestContext::new();
+        let validate_dir = context.add_dir(PathBuf::from("assets/tmp/category_validate_assigned"));
+        let split_context = RecordProcessingContext {
+            output_dir: validate_dir.clone(),
+            category_pattern: Some(Regex::new("^[A-Z]{2}$").unwrap()),
+            null_values: vec![String::from("N/A"), String::from("NULL")],
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/null_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        assert_eq!(summary.category_rows["unknown"], 3);
+        assert_eq!(summary.category_rows["NA"], 1);
+        let input_path = validate_dir.join("other_city.csv");
+        fs::write(
+            &input_path,
+            "City,State
+Kenai,AK
+Nome,other
+",
+        )
+        .unwrap();
+        let result = split_file_by_category(
+            &input_path,
+            "State",
+            &Delimiter::Comma,
+            &RecordProcessingContext {
+                output_dir: context.add_dir(validate_dir.join("out")),
+                category_pattern: Some(Regex::new("^[A-Z]{2}$").unwrap()),
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            result,
+            Err(AppError::CategoryMismatch { category, .. }) if category == "other"
+        ));
+    }
+
+    #[test]
+    fn test_raw_output() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/raw_output"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            raw_output: true,
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/comma_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let result = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                raw_output: true,
+                ..Default::default()
+            },
+        );
+
+        let ak_data = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+        assert_eq!(ak_data, "City\nKenai, Borough\nNome \"Gold\" City\n");
+        assert!(matches!(result, Err(AppError::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn test_find_split_column() {
+        let headers = StringRecord::from(vec![
+            "City",
+            "Population (2010)",
+            "Population (2020)",
+            "Latitude",
+        ]);
+
+        assert_eq!(
+            find_split_column(&headers, "Lat", ColumnMatch::Prefix).unwrap(),
+            3
+        );
+        assert_eq!(
+            find_split_column(&headers, "*(2020)", ColumnMatch::Glob).unwrap(),
+            2
+        );
+        assert_eq!(
+            find_split_column(&headers, "City", ColumnMatch::Glob).unwrap(),
+            0
+        );
+        assert!(matches!(
+            find_split_column(&headers, "Lat", ColumnMatch::Exact),
+            Err(AppError::ColumnNotFound(_))
+        ));
+        assert!(matches!(
+            find_split_column(&headers, "Population", ColumnMatch::Prefix),
+            Err(AppError::AmbiguousColumn { .. })
+        ));
+        assert!(matches!(
+            find_split_column(&headers, "Pop*", ColumnMatch::Glob),
+            Err(AppError::AmbiguousColumn { .. })
+        ));
+    }
+
+    #[test]
     fn test_get_category() {
         let context = &RecordProcessingContext {
-            split_column_idx: 1,
+            split_column_idx: Some(1),
             ..Default::default()
         };
 
@@ -256,11 +4999,263 @@ mod tests {
         assert_eq!(category, "Bogota");
     }
 
+    #[test]
+    fn test_category_transform() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/category_transform"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            category_transforms: vec![CategoryTransform::Trim, CategoryTransform::Lower],
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        assert!(output_dir.join("ak.csv").exists());
+        assert!(!output_dir.join("AK.csv").exists());
+        let ak_data: String = fs::read_to_string(output_dir.join("ak.csv")).unwrap();
+        assert!(ak_data.starts_with("City|Population|Latitude|Longitude\nDavidson Landing|"));
+
+        let padded = &RecordProcessingContext {
+            split_column_idx: Some(1),
+            category_transforms: vec![CategoryTransform::Trim, CategoryTransform::Upper],
+            ..Default::default()
+        };
+        assert_eq!(
+            get_category(&StringRecord::from(vec!["1", " ny "]), padded),
+            "NY"
+        );
+        assert_eq!(
+            get_category(&StringRecord::from(vec!["2", "   "]), padded),
+            "unknown"
+        );
+    }
+
+    #[test]
+    fn test_freeze_categories_after() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/freeze_categories"));
+        let split = |frozen_category_policy: UnexpectedCategoryPolicy| {
+            split_file_by_category(
+                Path::new("assets/city.csv"),
+                "State",
+                &Delimiter::Comma,
+                &RecordProcessingContext {
+                    output_dir: output_dir.clone(),
+                    chunk_size: 5,
+                    freeze_categories_after: Some(8),
+                    frozen_category_policy,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let summary = split(UnexpectedCategoryPolicy::Other).unwrap();
+        assert_eq!(summary.category_rows["CA"], 3);
+        assert_eq!(summary.category_rows[OTHER_CATEGORY], 3);
+        assert!(!output_dir.join("NY.csv").exists());
+        let other_data = fs::read_to_string(output_dir.join("other.csv")).unwrap();
+        let cities: Vec<&str> = other_data
+            .lines()
+            .skip(1)
+            .map(|line| line.split('|').next().unwrap())
+            .collect();
+        assert_eq!(cities, ["Albany", "Brooklyn", "Yonkers"]);
+
+        fs::remove_dir_all(&output_dir).unwrap();
+        fs::create_dir_all(&output_dir).unwrap();
+        assert!(matches!(
+            split(UnexpectedCategoryPolicy::Error),
+            Err(AppError::FrozenCategory(category)) if category == "NY"
+        ));
+    }
+
+    #[test]
+    fn test_null_output() {
+        let output_dir = PathBuf::from("assets/tmp/null_output");
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                output_backend: Arc::new(NullBackend),
+                force_create_dir: true,
+                success_marker: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(summary.rows_processed, 12);
+        assert!(summary.created.is_empty());
+        assert!(summary.appended.is_empty());
+        assert_eq!(
+            summary.category_rows,
+            HashMap::from([
+                (String::from("AK"), 2),
+                (String::from("AL"), 4),
+                (String::from("CA"), 3),
+                (String::from("NY"), 3),
+            ])
+        );
+        assert!(!output_dir.exists());
+    }
+
+    #[test]
+    fn test_skip_null_category() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/skip_null_category"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            null_category: String::new(),
+            skip_null_category: true,
+            null_values: vec![String::from("7610")],
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "Population",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        assert_eq!(summary.null_category_records_skipped, 6);
+        assert_eq!(summary.category_rows.values().sum::<usize>(), 6);
+        assert!(!output_dir.join(".csv").exists());
+        assert!(!output_dir.join("unknown.csv").exists());
+        assert!(!output_dir.join("7610.csv").exists());
+        assert!(output_dir.join("18980.csv").exists());
+    }
+
+    #[test]
+    fn test_skip_null_category_unexpected_other() {
+        let mut context = TestContext::new();
+        let skip_dir = context.add_dir(PathBuf::from("assets/tmp/skip_null_unexpected"));
+        let input_path = skip_dir.join("null_city.csv");
+        fs::write(&input_path, "City,State,Size\nA,AK,1\nB,,2\nC,NY,3\n").unwrap();
+        let output_dir = context.add_dir(skip_dir.join("out"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            null_category: String::new(),
+            skip_null_category: true,
+            expected_categories: Arc::new(HashSet::from([String::from("AK")])),
+            unexpected_category_policy: UnexpectedCategoryPolicy::Other,
+            ..Default::default()
+        };
+
+        let summary =
+            split_file_by_category(&input_path, "State", &Delimiter::Comma, &split_context)
+                .unwrap();
+
+        assert_eq!(summary.null_category_records_skipped, 1);
+        let other_data = fs::read_to_string(output_dir.join("other.csv")).unwrap();
+        assert_eq!(other_data, "City|Size\nC|3\n");
+    }
+
+    #[test]
+    fn test_skip_null_category_frozen() {
+        let mut context = TestContext::new();
+        let skip_dir = context.add_dir(PathBuf::from("assets/tmp/skip_null_frozen"));
+        let input_path = skip_dir.join("null_city.csv");
+        fs::write(&input_path, "City,State,Size\nA,AK,1\nB,,2\nC,NY,3\n").unwrap();
+        let output_dir = context.add_dir(skip_dir.join("out"));
+        let split = |frozen_category_policy: UnexpectedCategoryPolicy| {
+            let split_context = RecordProcessingContext {
+                output_dir: output_dir.clone(),
+                null_category: String::new(),
+                skip_null_category: true,
+                chunk_size: 1,
+                freeze_categories_after: Some(1),
+                frozen_category_policy,
+                ..Default::default()
+            };
+            split_file_by_category(&input_path, "State", &Delimiter::Comma, &split_context)
+        };
+
+        let summary = split(UnexpectedCategoryPolicy::Other).unwrap();
+        assert_eq!(summary.null_category_records_skipped, 1);
+        let other_data = fs::read_to_string(output_dir.join("other.csv")).unwrap();
+        assert_eq!(other_data, "City|Size\nC|3\n");
+
+        fs::remove_dir_all(&output_dir).unwrap();
+        fs::create_dir_all(&output_dir).unwrap();
+        let result = split(UnexpectedCategoryPolicy::Error);
+        assert!(matches!(result, Err(AppError::FrozenCategory(category)) if category == "NY"));
+    }
+
+    #[test]
+    fn test_get_category_null_values() {
+        let context = &RecordProcessingContext {
+            split_column_idx: Some(1),
+            null_values: vec![
+                String::from("NA"),
+                String::from("N/A"),
+                String::from("NULL"),
+            ],
+            ..Default::default()
+        };
+
+        let categories: Vec<String> = [
+            vec!["1", "NA"],
+            vec!["2", "N/A"],
+            vec!["3", "NULL"],
+            vec!["4", ""],
+            vec!["5"],
+        ]
+        .into_iter()
+        .map(|fields| get_category(&StringRecord::from(fields), context))
+        .collect();
+
+        assert!(categories.iter().all(|category| category == "unknown"));
+        assert_eq!(
+            get_category(&StringRecord::from(vec!["6", "NAN"]), context),
+            "NAN"
+        );
+    }
+
+    #[test]
+    fn test_null_values() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/null_values"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            null_category: String::from("missing"),
+            null_values: vec![
+                String::from("NA"),
+                String::from("N/A"),
+                String::from("NULL"),
+            ],
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/null_city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let missing_data = fs::read_to_string(output_dir.join("missing.csv")).unwrap();
+        assert_eq!(
+            missing_data,
+            "City|Population\nOakman|\nSandfort|\nBrea|\nNome|3598\n"
+        );
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 2);
+    }
+
     #[test]
     fn test_get_headers() {
         let headers = HEADERS.clone();
         let file_headers = FILE_HEADERS.clone();
-        let split_column_idx = 2_usize;
+        let split_column_idx = Some(2_usize);
         let headers = get_headers(&headers, split_column_idx);
 
         assert_eq!(file_headers, headers);
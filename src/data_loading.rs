@@ -1,20 +1,579 @@
-use crate::delimiter::Delimiter;
-use csv::{Reader, ReaderBuilder};
-use std::fs::File;
-use std::path::Path;
+use crate::app_error::AppError;
+use crate::delimiter::{sniff_delimiter, Delimiter, DelimiterChoice};
+use crate::file_following::{FollowReader, FollowState};
+use crate::fixed_width::{FixedWidth, FixedWidthReader};
+use csv::{Reader, ReaderBuilder, StringRecord, Trim};
+use flate2::read::MultiGzDecoder;
+use glob::{glob, Paths};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
 use std::string::String;
+use std::sync::Arc;
+use tracing::{event, Level};
 
-pub(crate) fn read_file(path: &Path, delimiter: &Delimiter) -> Result<Reader<File>, csv::Error> {
-    let reader: Reader<File> = ReaderBuilder::new()
-        .buffer_capacity(16 * 1024 * 1024)
+/// Bytes of the input read to sniff `--delimiter auto`
+const SNIFF_SAMPLE_BYTES: u64 = 64 * 1024;
+/// Confidence under which a sniffed delimiter is only used with `--force`
+const MIN_SNIFF_CONFIDENCE: f64 = 0.5;
+/// First bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// First bytes of a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Default bytes buffered from the input
+pub(crate) const DEFAULT_READ_BUFFER: usize = 16 * 1024 * 1024;
+
+/// Settings of the CSV reader of an input
+#[derive(Debug, Clone)]
+pub(crate) struct ReaderConfig {
+    pub(crate) delimiter: Delimiter,
+    /// Let records of any length through, e.g. to drop blank lines
+    pub(crate) flexible: bool,
+    /// Trim the whitespace around the headers and fields, from `--trim`
+    pub(crate) trim: bool,
+    /// Take the quotes as field quoting, off with `--no-quoting`
+    pub(crate) quoting: bool,
+    /// Bytes buffered from the input, from `--read-buffer`
+    pub(crate) buffer_capacity: usize,
+    /// Columns of a fixed-width input, from `--fixed-width`
+    pub(crate) fixed_width: Option<FixedWidth>,
+    /// Keep reading the new bytes of a growing input, from `--follow`
+    pub(crate) follow: Option<Arc<FollowState>>,
+    /// Lines starting with this character are skipped, from `--comment-char`
+    pub(crate) comment: Option<u8>,
+}
+
+impl ReaderConfig {
+    /// Strict reader of an input with the default buffer
+    pub(crate) fn new(delimiter: &Delimiter) -> Self {
+        ReaderConfig {
+            delimiter: delimiter.clone(),
+            flexible: false,
+            trim: false,
+            quoting: true,
+            buffer_capacity: DEFAULT_READ_BUFFER,
+            fixed_width: None,
+            follow: None,
+            comment: None,
+        }
+    }
+}
+
+/// Open the CSV input, streaming the response body when the path is an `http(s)://` URL.
+/// Gzip and zstd inputs are decompressed whatever their extension, and fixed-width ones
+/// cut into delimited records. A followed input is read as is
+pub(crate) fn read_file(
+    path: &Path,
+    config: &ReaderConfig,
+) -> Result<Reader<Box<dyn Read>>, AppError> {
+    let source: Box<dyn Read> = match (&config.follow, &config.fixed_width) {
+        (Some(follow), _) => {
+            Box::new(
+                FollowReader::open(path, follow).map_err(|error| match error.kind() {
+                    ErrorKind::NotFound => AppError::InputNotFound(path.display().to_string()),
+                    _ => AppError::Io(error),
+                })?,
+            )
+        }
+        (None, Some(fixed_width)) => Box::new(FixedWidthReader::new(
+            BufReader::new(read_raw(path)?),
+            fixed_width,
+            config.delimiter.as_byte(),
+        )?),
+        (None, None) => read_raw(path)?,
+    };
+
+    let reader: Reader<Box<dyn Read>> = ReaderBuilder::new()
+        .buffer_capacity(config.buffer_capacity)
         .has_headers(true)
-        .delimiter(delimiter.clone().into())
-        .from_path(path)?;
+        .delimiter(config.delimiter.as_byte())
+        .flexible(config.flexible)
+        .quoting(config.quoting)
+        .comment(config.comment)
+        .trim(if config.trim { Trim::All } else { Trim::None })
+        .from_reader(source);
 
     Ok(reader)
 }
 
-pub(crate) fn extract_file_name(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
-    let file_stem: &str = path.file_stem().unwrap().to_str().unwrap();
+/// Bytes of the input, decompressed but not parsed
+pub(crate) fn read_raw(path: &Path) -> Result<Box<dyn Read>, AppError> {
+    decompress(open_source(path)?)
+}
+
+/// Open a local file or request a URL
+fn open_source(path: &Path) -> Result<Box<dyn Read>, AppError> {
+    match path.to_str().filter(|input| is_url(input)) {
+        Some(url) => open_url(url),
+        None => Ok(Box::new(File::open(path).map_err(
+            |error| match error.kind() {
+                ErrorKind::NotFound => AppError::InputNotFound(path.display().to_string()),
+                _ => AppError::Io(error),
+            },
+        )?)),
+    }
+}
+
+/// Peek the magic bytes of the input and wrap it in the matching decoder, the peeked
+/// bytes are put back in front of the stream
+fn decompress(mut source: Box<dyn Read>) -> Result<Box<dyn Read>, AppError> {
+    let mut magic: [u8; 4] = [0; 4];
+    let mut magic_len: usize = 0;
+    while magic_len < magic.len() {
+        match source.read(&mut magic[magic_len..])? {
+            0 => break,
+            read => magic_len += read,
+        }
+    }
+    let magic: &[u8] = &magic[..magic_len];
+    let stream = Cursor::new(magic.to_vec()).chain(source);
+
+    if magic.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(MultiGzDecoder::new(stream)))
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::new(stream)?))
+    } else {
+        Ok(Box::new(stream))
+    }
+}
+
+/// List the files matching `--input-glob`, e.g. `exports/*.csv`, in path order.
+/// Directories are left out and no match at all is an error
+pub(crate) fn expand_input_glob(pattern: &str) -> Result<Vec<PathBuf>, AppError> {
+    let paths: Paths = glob(pattern).map_err(|error| {
+        AppError::InvalidArguments(format!("Invalid input glob {}: {}", pattern, error))
+    })?;
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        let path: PathBuf = path.map_err(io::Error::from)?;
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        return Err(AppError::InputNotFound(pattern.to_string()));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Get the delimiter of the input, sniffing it from the first bytes with `auto`. A low
+/// confidence guess is an error unless `force` is set
+pub(crate) fn resolve_delimiter(
+    path: &Path,
+    choice: &DelimiterChoice,
+    force: bool,
+) -> Result<Delimiter, AppError> {
+    let DelimiterChoice::Fixed(delimiter) = choice else {
+        let mut sample: Vec<u8> = Vec::new();
+        decompress(open_source(path)?)?
+            .take(SNIFF_SAMPLE_BYTES)
+            .read_to_end(&mut sample)?;
+        let (delimiter, confidence) = sniff_delimiter(&sample).ok_or_else(|| {
+            AppError::InvalidArguments(format!("Can't sniff the delimiter of {:?}", path))
+        })?;
+        event!(
+            Level::INFO,
+            "Sniffed delimiter {:?} with confidence {:.2}",
+            delimiter.as_char(),
+            confidence
+        );
+        if confidence < MIN_SNIFF_CONFIDENCE && !force {
+            return Err(AppError::InvalidArguments(format!(
+                "Ambiguous delimiter {:?} with confidence {:.2}, pass --delimiter or --force",
+                delimiter.as_char(),
+                confidence
+            )));
+        }
+        return Ok(delimiter);
+    };
+    Ok(delimiter.clone())
+}
+
+/// Request a URL and return its body as a stream
+fn open_url(url: &str) -> Result<Box<dyn Read>, AppError> {
+    match ureq::get(url).call() {
+        Ok(response) => Ok(Box::new(response.into_body().into_reader())),
+        Err(ureq::Error::StatusCode(404)) => Err(AppError::InputNotFound(url.to_string())),
+        Err(ureq::Error::StatusCode(status)) => Err(AppError::Io(Error::other(format!(
+            "Request to {} failed with status {}",
+            url, status
+        )))),
+        Err(error) => Err(AppError::Io(Error::other(format!(
+            "Request to {} failed: {}",
+            url, error
+        )))),
+    }
+}
+
+#[inline]
+pub(crate) fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// Read a `--header-map` file, each line holding a category then the columns written
+/// for it, e.g. `AK,City,Population`
+pub(crate) fn read_header_map(path: &Path) -> Result<HashMap<String, Vec<String>>, AppError> {
+    let mut reader: Reader<File> = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+    let mut header_map: HashMap<String, Vec<String>> = HashMap::new();
+    for result in reader.records() {
+        let record: StringRecord = result?;
+        let line: u64 = record.position().map_or(0, |position| position.line());
+        let mut fields = record.iter();
+        let category: String = fields.next().unwrap_or_default().to_string();
+        let columns: Vec<String> = fields.map(String::from).collect();
+        if columns.is_empty() {
+            return Err(AppError::InvalidArguments(format!(
+                "Line {} of the header map {:?} has no columns",
+                line, path
+            )));
+        }
+        if header_map.insert(category.clone(), columns).is_some() {
+            return Err(AppError::InvalidArguments(format!(
+                "The header map {:?} lists {:?} twice",
+                path, category
+            )));
+        }
+    }
+    Ok(header_map)
+}
+
+/// Read a `--category-map` file, each line holding a split column value then the name
+/// of its category, e.g. `1,north`
+pub(crate) fn read_category_map(path: &Path) -> Result<HashMap<String, String>, AppError> {
+    let mut reader: Reader<File> = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+    let mut category_map: HashMap<String, String> = HashMap::new();
+    for result in reader.records() {
+        let record: StringRecord = result?;
+        let line: u64 = record.position().map_or(0, |position| position.line());
+        if record.len() != 2 {
+            return Err(AppError::InvalidArguments(format!(
+                "Line {} of the category map {:?} has {} fields instead of 2",
+                line,
+                path,
+                record.len()
+            )));
+        }
+        if category_map
+            .insert(record[0].to_string(), record[1].to_string())
+            .is_some()
+        {
+            return Err(AppError::InvalidArguments(format!(
+                "The category map {:?} lists {:?} twice",
+                path, &record[0]
+            )));
+        }
+    }
+    Ok(category_map)
+}
+
+/// Read the `--join-file` reference CSV into the category of each `join_on` value, taken
+/// from its `join_key` column
+pub(crate) fn read_join_table(
+    path: &Path,
+    join_on: &str,
+    join_key: &str,
+) -> Result<HashMap<String, String>, AppError> {
+    let mut reader: Reader<File> = ReaderBuilder::new().from_path(path)?;
+    let headers: StringRecord = reader.headers()?.clone();
+    let column_idx = |column: &str| {
+        headers
+            .iter()
+            .position(|h| h == column)
+            .ok_or_else(|| AppError::ColumnNotFound(column.to_string()))
+    };
+    let (on_idx, key_idx): (usize, usize) = (column_idx(join_on)?, column_idx(join_key)?);
+    let mut join_table: HashMap<String, String> = HashMap::new();
+    for result in reader.records() {
+        let record: StringRecord = result?;
+        let value: &str = record.get(on_idx).unwrap_or_default();
+        let category: &str = record.get(key_idx).unwrap_or_default();
+        match join_table.insert(value.to_string(), category.to_string()) {
+            Some(previous) if previous != category => {
+                return Err(AppError::InvalidArguments(format!(
+                    "The join file {:?} maps {:?} to both {:?} and {:?}",
+                    path, value, previous, category
+                )));
+            }
+            _ => {}
+        }
+    }
+    Ok(join_table)
+}
+
+/// Read the `--categories-file` list of expected categories, one per line. Blank lines
+/// are ignored and surrounding whitespace trimmed
+pub(crate) fn read_categories_file(path: &Path) -> Result<Vec<String>, AppError> {
+    let mut categories: Vec<String> = Vec::new();
+    for line in fs::read_to_string(path)?.lines() {
+        let category: &str = line.trim();
+        if category.is_empty() || categories.iter().any(|c| c == category) {
+            continue;
+        }
+        categories.push(category.to_string());
+    }
+    Ok(categories)
+}
+
+/// Get the file stem of a local path, or of the last segment of a URL path
+pub(crate) fn extract_file_name(path: &Path) -> Result<String, AppError> {
+    let file_path: &Path = match path.to_str().filter(|input| is_url(input)) {
+        Some(url) => {
+            let url_path: &str = url.split(['?', '#']).next().unwrap_or_default();
+            let (_, host_and_path) = url_path.split_once("://").unwrap_or_default();
+            Path::new(host_and_path.split_once('/').map_or("", |(_, path)| path))
+        }
+        None => path,
+    };
+    let file_stem: &str = file_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| {
+            AppError::InvalidArguments(format!(
+                "Can't derive a file name from {:?}, use --file-name",
+                path
+            ))
+        })?;
     Ok(file_stem.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_context::TestContext;
+    use csv::StringRecord;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::thread;
+
+    /// Serve a single HTTP response on a local port and return the server URL
+    fn serve_once(status_line: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0_u8; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status_line,
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn test_read_file_from_url() {
+        let url = serve_once("200 OK", "City,State\nKenai,AK\n");
+        let mut reader = read_file(
+            Path::new(&format!("{}/city.csv", url)),
+            &ReaderConfig::new(&Delimiter::Comma),
+        )
+        .unwrap();
+
+        let headers = reader.headers().unwrap().clone();
+        let records: Vec<_> = reader.records().map(|record| record.unwrap()).collect();
+
+        assert_eq!(headers, vec!["City", "State"]);
+        assert_eq!(records[0], vec!["Kenai", "AK"]);
+    }
+
+    #[test]
+    fn test_read_file_from_url_not_found() {
+        let url = serve_once("404 Not Found", "");
+        let result = read_file(
+            Path::new(&format!("{}/city.csv", url)),
+            &ReaderConfig::new(&Delimiter::Comma),
+        );
+
+        assert!(matches!(result, Err(AppError::InputNotFound(_))));
+    }
+
+    #[test]
+    fn test_read_file_error() {
+        let url = serve_once("500 Internal Server Error", "");
+        let result = read_file(
+            Path::new(&format!("{}/city.csv", url)),
+            &ReaderConfig::new(&Delimiter::Comma),
+        );
+
+        let error = result.err().unwrap();
+        assert!(error.to_string().contains("status 500"));
+        let result = read_file(
+            Path::new("assets/missing.csv"),
+            &ReaderConfig::new(&Delimiter::Comma),
+        );
+        assert!(matches!(result, Err(AppError::InputNotFound(_))));
+    }
+
+    #[test]
+    fn test_read_file_config() {
+        let path = Path::new("assets/footer_city.csv");
+        let strict: Result<Vec<StringRecord>, csv::Error> =
+            read_file(path, &ReaderConfig::new(&Delimiter::Comma))
+                .unwrap()
+                .records()
+                .collect();
+        assert!(strict.is_err());
+
+        let config = ReaderConfig {
+            flexible: true,
+            trim: true,
+            buffer_capacity: 64,
+            ..ReaderConfig::new(&Delimiter::Comma)
+        };
+        let records: Vec<StringRecord> = read_file(path, &config)
+            .unwrap()
+            .records()
+            .map(|record| record.unwrap())
+            .collect();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[3], vec!["Total", "26590"]);
+    }
+
+    #[test]
+    fn test_read_file_no_quoting() {
+        let path = Path::new("assets/literal_quotes_city.csv");
+        let quoted: Vec<StringRecord> = read_file(path, &ReaderConfig::new(&Delimiter::Comma))
+            .unwrap()
+            .records()
+            .filter_map(Result::ok)
+            .collect();
+        assert_ne!(quoted.len(), 3);
+
+        let config = ReaderConfig {
+            quoting: false,
+            ..ReaderConfig::new(&Delimiter::Comma)
+        };
+        let records: Vec<StringRecord> = read_file(path, &config)
+            .unwrap()
+            .records()
+            .map(|record| record.unwrap())
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                vec!["Kenai \"Old Town\"", "AK", "7610"],
+                vec!["\"Oakman", "AL", "789"],
+                vec!["Sandfort", "AL", "\"2500"],
+            ]
+        );
+    }
+
+    /// Read the headers and the first record of an input
+    fn read_first_record(path: &Path) -> (StringRecord, StringRecord) {
+        let mut reader = read_file(path, &ReaderConfig::new(&Delimiter::Comma)).unwrap();
+        let headers = reader.headers().unwrap().clone();
+        let record = reader.records().next().unwrap().unwrap();
+        (headers, record)
+    }
+
+    #[test]
+    fn test_read_file_gzip() {
+        let mut context = TestContext::new();
+        let path = context.add_file(PathBuf::from("assets/tmp/gzip_input.csv"));
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(b"City,State\nKenai,AK\n").unwrap();
+        encoder.finish().unwrap();
+
+        let (headers, record) = read_first_record(&path);
+
+        assert_eq!(headers, vec!["City", "State"]);
+        assert_eq!(record, vec!["Kenai", "AK"]);
+    }
+
+    #[test]
+    fn test_read_file_zstd() {
+        let mut context = TestContext::new();
+        let path = context.add_file(PathBuf::from("assets/tmp/zstd_input"));
+        let compressed = zstd::encode_all(&b"City,State\nKenai,AK\n"[..], 0).unwrap();
+        fs::write(&path, compressed).unwrap();
+
+        let (headers, record) = read_first_record(&path);
+
+        assert_eq!(headers, vec!["City", "State"]);
+        assert_eq!(record, vec!["Kenai", "AK"]);
+    }
+
+    #[test]
+    fn test_read_join_table() {
+        let join_table =
+            read_join_table(Path::new("assets/zip_regions.csv"), "Zip", "Region").unwrap();
+
+        assert_eq!(join_table.len(), 4);
+        assert_eq!(join_table["10701"], "northeast");
+        assert_eq!(join_table["36701"], "south");
+        assert!(matches!(
+            read_join_table(Path::new("assets/zip_regions.csv"), "Zip", "County"),
+            Err(AppError::ColumnNotFound(column)) if column == "County"
+        ));
+    }
+
+    #[test]
+    fn test_read_categories_file() {
+        assert_eq!(
+            read_categories_file(Path::new("assets/expected_states.txt")).unwrap(),
+            ["AK", "AL", "CA", "TX"]
+        );
+    }
+
+    #[test]
+    fn test_expand_input_glob() {
+        let files = expand_input_glob("assets/glob_city/*.csv").unwrap();
+
+        assert_eq!(
+            files,
+            ["part_1.csv", "part_2.csv", "part_3.csv"]
+                .map(|file| Path::new("assets/glob_city").join(file))
+        );
+        assert!(matches!(
+            expand_input_glob("assets/glob_city/*.tsv"),
+            Err(AppError::InputNotFound(_))
+        ));
+        assert!(matches!(
+            expand_input_glob("assets/[city"),
+            Err(AppError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_delimiter() {
+        let path = Path::new("assets/city.csv");
+
+        assert_eq!(
+            resolve_delimiter(path, &DelimiterChoice::Auto, false).unwrap(),
+            Delimiter::Comma
+        );
+        assert_eq!(
+            resolve_delimiter(path, &DelimiterChoice::Fixed(Delimiter::Pipe), false).unwrap(),
+            Delimiter::Pipe
+        );
+    }
+
+    #[test]
+    fn test_extract_file_name_from_url() {
+        let url = Path::new("https://example.com/exports/city.csv?token=abc");
+        assert_eq!(extract_file_name(url).unwrap(), "city");
+        assert!(extract_file_name(Path::new("https://example.com/")).is_err());
+        assert_eq!(
+            extract_file_name(Path::new("assets/city.csv")).unwrap(),
+            "city"
+        );
+    }
+}
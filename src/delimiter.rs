@@ -3,7 +3,7 @@ use std::str::FromStr;
 use thiserror::Error;
 
 #[derive(Debug, PartialEq, Clone)]
-pub(crate) enum Delimiter {
+pub enum Delimiter {
     Comma,
     Pipe,
     Tab,
@@ -15,17 +15,26 @@ impl Delimiter {
     pub const PIPE: u8 = b'|';
     pub const TAB: u8 = b'\t';
     pub const SEMICOLON: u8 = b';';
+
+    /// Get the delimiter byte without consuming the value, e.g. `Delimiter::Tab` is `b'\t'`
+    pub fn as_byte(&self) -> u8 {
+        match self {
+            Delimiter::Comma => Delimiter::COMMA,
+            Delimiter::Pipe => Delimiter::PIPE,
+            Delimiter::Tab => Delimiter::TAB,
+            Delimiter::SemiColon => Delimiter::SEMICOLON,
+        }
+    }
+
+    /// Get the delimiter character without allocating, e.g. `Delimiter::Pipe` is `'|'`
+    pub fn as_char(&self) -> char {
+        char::from(self.as_byte())
+    }
 }
 
 impl fmt::Display for Delimiter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let c: char = match self {
-            Delimiter::Comma => ',',
-            Delimiter::Pipe => '|',
-            Delimiter::Tab => '\t',
-            Delimiter::SemiColon => ';',
-        };
-        write!(f, "{}", c)
+        write!(f, "{}", self.as_char())
     }
 }
 
@@ -39,23 +48,232 @@ impl FromStr for Delimiter {
     type Err = DelimiterParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        match unescape(s) {
             "," => Ok(Delimiter::Comma),
             "|" => Ok(Delimiter::Pipe),
             "\t" => Ok(Delimiter::Tab),
             ";" => Ok(Delimiter::SemiColon),
-            _ => Err(DelimiterParseError::InvalidDelimiter),
+            _ => match s.to_ascii_lowercase().as_str() {
+                "comma" => Ok(Delimiter::Comma),
+                "pipe" => Ok(Delimiter::Pipe),
+                "tab" => Ok(Delimiter::Tab),
+                "semicolon" => Ok(Delimiter::SemiColon),
+                _ => Err(DelimiterParseError::InvalidDelimiter),
+            },
+        }
+    }
+}
+
+/// Replace the two character escapes `\t`, `\n`, `\r` and `\0` typed on a command line with
+/// the character they stand for. Only tab is a delimiter, line breaks and NUL being
+/// rejected like any other character
+fn unescape(s: &str) -> &str {
+    match s {
+        "\\t" => "\t",
+        "\\n" => "\n",
+        "\\r" => "\r",
+        "\\0" => "\0",
+        _ => s,
+    }
+}
+
+/// Delimiter given with `--delimiter`, `auto` sniffs it from the first lines of the input
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum DelimiterChoice {
+    Auto,
+    Fixed(Delimiter),
+}
+
+impl FromStr for DelimiterChoice {
+    type Err = DelimiterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(DelimiterChoice::Auto);
         }
+        s.parse::<Delimiter>().map(DelimiterChoice::Fixed)
     }
 }
 
+/// Delimiter of the split files given with `--delimiter-out`, `none` writes the single
+/// output column raw, one value per line without quoting
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum OutputDelimiter {
+    Fixed(Delimiter),
+    None,
+}
+
+impl FromStr for OutputDelimiter {
+    type Err = DelimiterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("none") {
+            return Ok(OutputDelimiter::None);
+        }
+        s.parse::<Delimiter>().map(OutputDelimiter::Fixed)
+    }
+}
+
+/// Number of lines of the sample used to sniff the delimiter
+const SNIFF_LINES: usize = 20;
+
+/// Guess the delimiter of a sample of the input with the confidence of the guess, from 0
+/// (another delimiter is as consistent) to 1. Each candidate is scored by the share of
+/// lines having its most common field count, the winner's confidence being its lead over
+/// the runner-up. `None` when no candidate appears in the sample
+pub(crate) fn sniff_delimiter(sample: &[u8]) -> Option<(Delimiter, f64)> {
+    let lines: Vec<&[u8]> = sample
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty())
+        .take(SNIFF_LINES)
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let mut scores: Vec<(Delimiter, f64, usize)> = [
+        Delimiter::Comma,
+        Delimiter::SemiColon,
+        Delimiter::Tab,
+        Delimiter::Pipe,
+    ]
+    .into_iter()
+    .filter_map(|delimiter| {
+        let counts: Vec<usize> = lines
+            .iter()
+            .map(|line| count_unquoted(line, delimiter.as_byte()))
+            .collect();
+        let mode: usize = *counts
+            .iter()
+            .max_by_key(|&&count| counts.iter().filter(|&&other| other == count).count())?;
+        if mode == 0 {
+            return None;
+        }
+        let consistent: usize = counts.iter().filter(|&&count| count == mode).count();
+        Some((delimiter, consistent as f64 / counts.len() as f64, mode))
+    })
+    .collect();
+    // Ties go to the delimiter splitting the lines into more fields
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.2.cmp(&a.2)));
+
+    let mut scores_iter = scores.into_iter();
+    let (delimiter, score, _) = scores_iter.next()?;
+    let runner_up_score: f64 = scores_iter.next().map_or(0.0, |(_, score, _)| score);
+    Some((delimiter, score - runner_up_score))
+}
+
+/// Count the occurrences of a byte outside of quoted fields
+fn count_unquoted(line: &[u8], byte: u8) -> usize {
+    let mut in_quotes: bool = false;
+    line.iter()
+        .filter(|&&current| {
+            if current == b'"' {
+                in_quotes = !in_quotes;
+            }
+            current == byte && !in_quotes
+        })
+        .count()
+}
+
 impl From<Delimiter> for u8 {
     fn from(val: Delimiter) -> Self {
-        match val {
-            Delimiter::Comma => Delimiter::COMMA,
-            Delimiter::Pipe => Delimiter::PIPE,
-            Delimiter::Tab => Delimiter::TAB,
-            Delimiter::SemiColon => Delimiter::SEMICOLON,
-        }
+        val.as_byte()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str_characters() {
+        assert_eq!(Delimiter::from_str(",").unwrap(), Delimiter::Comma);
+        assert_eq!(Delimiter::from_str("|").unwrap(), Delimiter::Pipe);
+        assert_eq!(Delimiter::from_str("\t").unwrap(), Delimiter::Tab);
+        assert_eq!(Delimiter::from_str(";").unwrap(), Delimiter::SemiColon);
+    }
+
+    #[test]
+    fn test_from_str_names() {
+        assert_eq!(Delimiter::from_str("comma").unwrap(), Delimiter::Comma);
+        assert_eq!(Delimiter::from_str("PIPE").unwrap(), Delimiter::Pipe);
+        assert_eq!(Delimiter::from_str("Tab").unwrap(), Delimiter::Tab);
+        assert_eq!(
+            Delimiter::from_str("semicolon").unwrap(),
+            Delimiter::SemiColon
+        );
+    }
+
+    #[test]
+    fn test_as_byte_and_as_char() {
+        let delimiter = Delimiter::SemiColon;
+
+        assert_eq!(delimiter.as_byte(), b';');
+        assert_eq!(delimiter.as_char(), ';');
+        assert_eq!(Delimiter::Tab.as_byte(), u8::from(Delimiter::Tab));
+        assert_eq!(
+            Delimiter::Pipe.as_char().to_string(),
+            Delimiter::Pipe.to_string()
+        );
+    }
+
+    #[test]
+    fn test_sniff_delimiter_comma() {
+        let sample = b"City,State,Population\nKenai,AK,7610\n\"Selma, AL\",AL,18980\n";
+
+        assert_eq!(sniff_delimiter(sample), Some((Delimiter::Comma, 1.0)));
+        assert_eq!(
+            sniff_delimiter(b"City;State\r\nKenai;AK\r\n"),
+            Some((Delimiter::SemiColon, 1.0))
+        );
+    }
+
+    #[test]
+    fn test_sniff_delimiter_ambiguous() {
+        let sample = b"City,State;Population\nKenai,AK;7610\n";
+        let (_, confidence) = sniff_delimiter(sample).unwrap();
+
+        assert_eq!(confidence, 0.0);
+        assert_eq!(sniff_delimiter(b"City\nKenai\n"), None);
+        assert_eq!(
+            DelimiterChoice::from_str("AUTO").unwrap(),
+            DelimiterChoice::Auto
+        );
+    }
+
+    #[test]
+    fn test_output_delimiter_from_str() {
+        assert_eq!(
+            OutputDelimiter::from_str("none").unwrap(),
+            OutputDelimiter::None
+        );
+        assert_eq!(
+            OutputDelimiter::from_str("tab").unwrap(),
+            OutputDelimiter::Fixed(Delimiter::Tab)
+        );
+    }
+
+    #[test]
+    fn test_from_str_invalid() {
+        assert!(Delimiter::from_str(":").is_err());
+        assert!(Delimiter::from_str("colon").is_err());
+    }
+
+    #[test]
+    fn test_from_str_escapes() {
+        assert_eq!(Delimiter::from_str(r"\t").unwrap(), Delimiter::Tab);
+        assert_eq!(
+            DelimiterChoice::from_str(r"\t").unwrap(),
+            DelimiterChoice::Fixed(Delimiter::Tab)
+        );
+        assert_eq!(
+            OutputDelimiter::from_str(r"\t").unwrap(),
+            OutputDelimiter::Fixed(Delimiter::Tab)
+        );
+        assert_eq!(unescape(r"\n"), "\n");
+        assert!(Delimiter::from_str(r"\n").is_err());
+        assert!(Delimiter::from_str(r"\0").is_err());
+        assert!(Delimiter::from_str(r"\x").is_err());
     }
 }
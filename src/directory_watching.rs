@@ -0,0 +1,164 @@
+use crate::app_error::AppError;
+use crate::data_filtering::split_file_by_category;
+use crate::delimiter::Delimiter;
+use crate::record_context::RecordProcessingContext;
+use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use tracing::{event, Level};
+
+/// Time without new events after which a file is considered completely written,
+/// used on platforms that don't report close events
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Watch a directory and split every new CSV file that lands in it,
+/// sharing the writers of the context across all files. Returns whether the watch was
+/// stopped by `--max-runtime`
+pub(crate) fn watch_directory(
+    watch_dir: &Path,
+    input_column: &str,
+    delimiter: &Delimiter,
+    context: &RecordProcessingContext,
+) -> Result<bool, AppError> {
+    let watch_dir_path: PathBuf = fs::canonicalize(watch_dir)
+        .map_err(|_| AppError::InputNotFound(watch_dir.display().to_string()))?;
+    if watch_dir_path == fs::canonicalize(&context.output_dir)? {
+        return Err(AppError::InvalidArguments(String::from(
+            "The output directory can't be the watched directory",
+        )));
+    }
+
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher: RecommendedWatcher = notify::recommended_watcher(tx).map_err(Error::other)?;
+    watcher
+        .watch(watch_dir, RecursiveMode::NonRecursive)
+        .map_err(Error::other)?;
+    event!(Level::INFO, "Watching directory: {:?}", watch_dir);
+
+    let timed_out: bool = process_events(&rx, context.deadline, |path: &Path| {
+        event!(Level::INFO, "Splitting new file: {:?}", path);
+        match split_file_by_category(path, input_column, delimiter, context) {
+            Ok(summary) => event!(
+                Level::INFO,
+                "Finished splitting file: {:?}, {} duplicate records removed",
+                path,
+                summary.duplicates_removed
+            ),
+            Err(error) => event!(Level::ERROR, "Failed to split {:?}: {}", path, error),
+        }
+    });
+    Ok(timed_out)
+}
+
+/// Dispatch files to `on_file` once they are completely written, until the watcher is
+/// dropped or the deadline is reached, returning whether the deadline was reached
+fn process_events<F>(
+    rx: &Receiver<notify::Result<Event>>,
+    deadline: Option<Instant>,
+    mut on_file: F,
+) -> bool
+where
+    F: FnMut(&Path),
+{
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    let mut processed: HashSet<PathBuf> = HashSet::new();
+
+    loop {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return true;
+        }
+        match rx.recv_timeout(SETTLE_TIME) {
+            Ok(Ok(watch_event)) => {
+                for path in watch_event.paths.iter().filter(|path| is_csv_file(path)) {
+                    if processed.contains(path) {
+                        continue;
+                    }
+                    match watch_event.kind {
+                        // The writer closed the file, so it is safe to read right away
+                        EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+                            pending.remove(path);
+                            processed.insert(path.clone());
+                            on_file(path);
+                        }
+                        EventKind::Create(CreateKind::File | CreateKind::Any)
+                        | EventKind::Modify(
+                            ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any,
+                        ) => {
+                            pending.insert(path.clone(), Instant::now());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Ok(Err(error)) => event!(Level::WARN, "Watch error: {}", error),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+
+        for path in take_settled_files(&mut pending, Instant::now()) {
+            processed.insert(path.clone());
+            on_file(&path);
+        }
+    }
+}
+
+/// Remove and return the pending files that haven't changed for `SETTLE_TIME`
+fn take_settled_files(pending: &mut HashMap<PathBuf, Instant>, now: Instant) -> Vec<PathBuf> {
+    let settled: Vec<PathBuf> = pending
+        .iter()
+        .filter(|(_, last_event)| now.duration_since(**last_event) >= SETTLE_TIME)
+        .map(|(path, _)| path.clone())
+        .collect();
+    for path in &settled {
+        pending.remove(path);
+    }
+    settled
+}
+
+#[inline]
+fn is_csv_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_csv_file() {
+        assert!(is_csv_file(Path::new("incoming/city.csv")));
+        assert!(is_csv_file(Path::new("incoming/CITY.CSV")));
+        assert!(!is_csv_file(Path::new("incoming/city.csv.part")));
+        assert!(!is_csv_file(Path::new("incoming/city")));
+    }
+
+    #[test]
+    fn test_process_events_deadline() {
+        let (_tx, rx) = mpsc::channel::<notify::Result<Event>>();
+        let mut files: usize = 0;
+
+        let timed_out = process_events(&rx, Some(Instant::now()), |_| files += 1);
+
+        assert!(timed_out);
+        assert_eq!(files, 0);
+    }
+
+    #[test]
+    fn test_take_settled_files() {
+        let now = Instant::now();
+        let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+        pending.insert(PathBuf::from("old.csv"), now);
+        pending.insert(PathBuf::from("new.csv"), now + SETTLE_TIME);
+
+        let settled = take_settled_files(&mut pending, now + SETTLE_TIME);
+
+        assert_eq!(settled, vec![PathBuf::from("old.csv")]);
+        assert!(pending.contains_key(&PathBuf::from("new.csv")));
+    }
+}
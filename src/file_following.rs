@@ -0,0 +1,169 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{event, Level};
+
+/// Delay between two checks of a followed file for new bytes
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where `--follow` stands in the input, shared by its reader and the split of its
+/// records
+#[derive(Debug)]
+pub(crate) struct FollowState {
+    /// Bytes given to the split when they last reached the end of the file at a line end,
+    /// the records read up to there being written without waiting for a full chunk
+    caught_up_at: AtomicU64,
+    /// Instant the input ends at, from `--max-runtime`, followed until stopped otherwise
+    deadline: Option<Instant>,
+}
+
+impl FollowState {
+    pub(crate) fn new(deadline: Option<Instant>) -> Self {
+        FollowState {
+            caught_up_at: AtomicU64::new(u64::MAX),
+            deadline,
+        }
+    }
+
+    /// Whether the records read up to this byte are all the file had. The record of a
+    /// CRLF line ends before its line feed
+    pub(crate) fn is_caught_up(&self, byte: u64) -> bool {
+        let caught_up_at: u64 = self.caught_up_at.load(Ordering::Acquire);
+        caught_up_at == byte || caught_up_at == byte + 1
+    }
+
+    /// Whether the input ended at the deadline rather than being followed further
+    pub(crate) fn is_past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Reader of a growing file for `--follow`, waiting for new bytes at its end instead of
+/// ending the input. A file truncated or replaced in its place, as by a log rotation, is
+/// read again from the start, its first line being the headers already read
+pub(crate) struct FollowReader {
+    path: PathBuf,
+    file: File,
+    /// Bytes read from the current file, its headers included
+    offset: u64,
+    /// Bytes given to the split across the files followed
+    delivered: u64,
+    last_byte: Option<u8>,
+    state: Arc<FollowState>,
+}
+
+impl FollowReader {
+    pub(crate) fn open(path: &Path, state: &Arc<FollowState>) -> io::Result<Self> {
+        Ok(FollowReader {
+            path: path.to_path_buf(),
+            file: File::open(path)?,
+            offset: 0,
+            delivered: 0,
+            last_byte: None,
+            state: Arc::clone(state),
+        })
+    }
+
+    /// Switch to the file now at the path if the current one was truncated or replaced,
+    /// once its headers line is complete. Returns whether it switched
+    fn follow_rotation(&mut self) -> io::Result<bool> {
+        let metadata: Metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // Rotated away, the new file isn't there yet
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(error) => return Err(error),
+        };
+        let replaced: bool = file_id(&metadata) != file_id(&self.file.metadata()?);
+        if !replaced && metadata.len() >= self.offset {
+            return Ok(false);
+        }
+        let mut reader: BufReader<File> = BufReader::new(File::open(&self.path)?);
+        let mut headers: Vec<u8> = Vec::new();
+        reader.read_until(b'\n', &mut headers)?;
+        if headers.last() != Some(&b'\n') {
+            return Ok(false);
+        }
+        event!(
+            Level::WARN,
+            "{:?} was {}, following it from the start",
+            self.path,
+            if replaced { "replaced" } else { "truncated" }
+        );
+        let mut file: File = reader.into_inner();
+        file.seek(SeekFrom::Start(headers.len() as u64))?;
+        self.file = file;
+        self.offset = headers.len() as u64;
+        Ok(true)
+    }
+}
+
+impl Read for FollowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read: usize = self.file.read(buf)?;
+            if read > 0 {
+                self.offset += read as u64;
+                self.delivered += read as u64;
+                self.last_byte = Some(buf[read - 1]);
+                if self.last_byte == Some(b'\n') && self.offset >= self.file.metadata()?.len() {
+                    self.state
+                        .caught_up_at
+                        .store(self.delivered, Ordering::Release);
+                }
+                return Ok(read);
+            }
+            if self.state.is_past_deadline() {
+                return Ok(0);
+            }
+            if !self.follow_rotation()? {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// Identity of a file telling a new file at the same path from the old one, none where
+/// the platform doesn't give one, only a truncation being noticed then
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_context::TestContext;
+
+    #[test]
+    fn test_follow_rotation() {
+        let mut context = TestContext::new();
+        fs::create_dir_all("assets/tmp").unwrap();
+        let path = context.add_file(PathBuf::from("assets/tmp/follow_rotation.csv"));
+        fs::write(&path, "City,State\nKenai,AK\n").unwrap();
+        let state = Arc::new(FollowState::new(None));
+        let mut reader = FollowReader::open(&path, &state).unwrap();
+        let mut buf = [0; 64];
+
+        let read = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..read], b"City,State\nKenai,AK\n");
+        assert!(state.is_caught_up(20));
+        let rotated_path = context.add_file(path.with_extension("csv.1"));
+        fs::rename(&path, &rotated_path).unwrap();
+        fs::write(&path, "City,State\nSandfort,AL\n").unwrap();
+        let read = reader.read(&mut buf).unwrap();
+
+        assert_eq!(&buf[..read], b"Sandfort,AL\n");
+        assert!(state.is_caught_up(32));
+    }
+}
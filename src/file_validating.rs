@@ -0,0 +1,270 @@
+use crate::app_error::AppError;
+use crate::data_filtering::{check_expected_headers, input_records, prepare_context};
+use crate::data_loading::{read_file, ReaderConfig};
+use crate::delimiter::Delimiter;
+use crate::record_context::RecordProcessingContext;
+use csv::{ByteRecord, Reader, StringRecord};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use tracing::{event, Level};
+
+/// Invalid records logged with their line number, the others are only counted
+const LOGGED_PROBLEMS: usize = 10;
+/// Marks of the longest `--field-count-report` bar
+const HISTOGRAM_WIDTH: usize = 20;
+
+/// Records read by `--validate-only` and the invalid ones by problem
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ValidationReport {
+    pub(crate) records: usize,
+    /// Records with another number of fields than the headers
+    pub(crate) wrong_lengths: usize,
+    pub(crate) invalid_utf8: usize,
+    /// Records longer than `--max-record-bytes`
+    pub(crate) oversized: usize,
+}
+
+impl ValidationReport {
+    /// Records with at least one problem
+    pub(crate) fn invalid_records(&self) -> usize {
+        self.wrong_lengths + self.invalid_utf8 + self.oversized
+    }
+
+    /// One line verdict with the counts, e.g. `FAIL: 2 of 5 records are invalid, ...`
+    pub(crate) fn describe(&self) -> String {
+        if self.invalid_records() == 0 {
+            return format!("PASS: {} records parse cleanly", self.records);
+        }
+        format!(
+            "FAIL: {} of {} records are invalid, {} with the wrong number of fields, {} not UTF-8, {} longer than --max-record-bytes",
+            self.invalid_records(),
+            self.records,
+            self.wrong_lengths,
+            self.invalid_utf8,
+            self.oversized
+        )
+    }
+}
+
+/// Read the records of the input the split would, checking they split cleanly without
+/// writing anything: the split columns, `--filter` columns and `--expect-headers` must
+/// match the headers, and each record must have as many fields as the headers, be valid
+/// UTF-8 and fit `--max-record-bytes`. A record has a single problem, the first found
+pub(crate) fn validate_file(
+    path: &Path,
+    input_columns: &[String],
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<ValidationReport, AppError> {
+    // Reading flexibly has the lengths counted instead of stopping at the first one
+    let reader_config: ReaderConfig = ReaderConfig {
+        flexible: true,
+        ..base_context.reader_config(delimiter)
+    };
+    let mut reader: Reader<Box<dyn Read>> = read_file(path, &reader_config)?;
+    let headers: StringRecord = reader.headers()?.clone();
+    check_expected_headers(path, &headers, base_context)?;
+    for input_column in input_columns {
+        prepare_context(path, &headers, input_column, delimiter, base_context)?;
+    }
+
+    let mut report: ValidationReport = ValidationReport::default();
+    for result in input_records(&mut reader, base_context) {
+        let Some((record, _)): Option<(ByteRecord, u64)> = result? else {
+            continue;
+        };
+        report.records += 1;
+        let line: u64 = record.position().map_or(0, |position| position.line());
+        let problem: Option<String> = if record.len() != headers.len() {
+            report.wrong_lengths += 1;
+            Some(format!(
+                "Line {} has {} fields instead of {}",
+                line,
+                record.len(),
+                headers.len()
+            ))
+        } else if record
+            .iter()
+            .any(|field| std::str::from_utf8(field).is_err())
+        {
+            report.invalid_utf8 += 1;
+            Some(format!("Line {} isn't valid UTF-8", line))
+        } else {
+            match base_context.max_record_bytes {
+                Some(max_record_bytes) if record.as_slice().len() > max_record_bytes => {
+                    report.oversized += 1;
+                    Some(format!(
+                        "Line {} has {} bytes, more than --max-record-bytes {}",
+                        line,
+                        record.as_slice().len(),
+                        max_record_bytes
+                    ))
+                }
+                _ => None,
+            }
+        };
+        if let Some(problem) = problem.filter(|_| report.invalid_records() <= LOGGED_PROBLEMS) {
+            event!(Level::WARN, "{}", problem);
+        }
+    }
+    Ok(report)
+}
+
+/// Count the records of the input by their number of fields, for `--field-count-report`.
+/// The headers aren't counted
+pub(crate) fn count_fields(
+    path: &Path,
+    delimiter: &Delimiter,
+    base_context: &RecordProcessingContext,
+) -> Result<(usize, BTreeMap<usize, usize>), AppError> {
+    let reader_config: ReaderConfig = ReaderConfig {
+        flexible: true,
+        ..base_context.reader_config(delimiter)
+    };
+    let mut reader: Reader<Box<dyn Read>> = read_file(path, &reader_config)?;
+    let header_count: usize = reader.byte_headers()?.len();
+    let mut histogram: BTreeMap<usize, usize> = BTreeMap::new();
+    for result in reader.byte_records() {
+        *histogram.entry(result?.len()).or_default() += 1;
+    }
+    Ok((header_count, histogram))
+}
+
+/// Format the field counts as a histogram line per count, flagging the count of the
+/// headers, e.g. `3 fields |####################| 12 records (headers)`
+pub(crate) fn format_field_counts(
+    header_count: usize,
+    histogram: &BTreeMap<usize, usize>,
+) -> String {
+    let most: usize = histogram.values().copied().max().unwrap_or_default();
+    let width: usize = histogram
+        .keys()
+        .last()
+        .map_or(1, |count| count.to_string().len());
+    histogram
+        .iter()
+        .map(|(&fields, &records)| {
+            // Any count gets at least one mark, however rare
+            let bar: usize = (records * HISTOGRAM_WIDTH).div_ceil(most);
+            format!(
+                "{:>width$} fields |{:<bar_width$}| {} records{}\n",
+                fields,
+                "#".repeat(bar),
+                records,
+                if fields == header_count {
+                    " (headers)"
+                } else {
+                    ""
+                },
+                width = width,
+                bar_width = HISTOGRAM_WIDTH
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(path: &str, context: &RecordProcessingContext) -> ValidationReport {
+        validate_file(
+            Path::new(path),
+            &[String::from("State")],
+            &Delimiter::Comma,
+            context,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_clean_file() {
+        let report = validate("assets/city.csv", &RecordProcessingContext::default());
+
+        assert_eq!(report.records, 12);
+        assert_eq!(report.invalid_records(), 0);
+        assert_eq!(report.describe(), "PASS: 12 records parse cleanly");
+    }
+
+    #[test]
+    fn test_validate_malformed_file() {
+        let context = RecordProcessingContext {
+            max_record_bytes: Some(20),
+            ..Default::default()
+        };
+        let report = validate("assets/malformed_city.csv", &context);
+
+        assert_eq!(
+            report,
+            ValidationReport {
+                records: 5,
+                wrong_lengths: 2,
+                invalid_utf8: 1,
+                oversized: 1,
+            }
+        );
+        assert!(report
+            .describe()
+            .starts_with("FAIL: 4 of 5 records are invalid"));
+        assert!(matches!(
+            validate_file(
+                Path::new("assets/malformed_city.csv"),
+                &[String::from("Country")],
+                &Delimiter::Comma,
+                &context,
+            ),
+            Err(AppError::ColumnNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_row_range() {
+        let context = RecordProcessingContext {
+            max_record_bytes: Some(20),
+            row_range: Some("2:3".parse().unwrap()),
+            ..Default::default()
+        };
+        let report = validate("assets/malformed_city.csv", &context);
+
+        assert_eq!(
+            report,
+            ValidationReport {
+                records: 2,
+                wrong_lengths: 2,
+                invalid_utf8: 0,
+                oversized: 0,
+            }
+        );
+        let split_context = |normalize_line_endings: bool| RecordProcessingContext {
+            max_record_bytes: Some(8),
+            normalize_line_endings,
+            ..Default::default()
+        };
+        assert_eq!(
+            validate("assets/mixed_endings.csv", &split_context(false)).oversized,
+            2
+        );
+        assert_eq!(
+            validate("assets/mixed_endings.csv", &split_context(true)).oversized,
+            0
+        );
+    }
+
+    #[test]
+    fn test_field_count_report() {
+        let (header_count, histogram) = count_fields(
+            Path::new("assets/malformed_city.csv"),
+            &Delimiter::Comma,
+            &RecordProcessingContext::default(),
+        )
+        .unwrap();
+
+        assert_eq!(header_count, 3);
+        assert_eq!(histogram, BTreeMap::from([(2, 1), (3, 3), (4, 1)]));
+        assert_eq!(
+            format_field_counts(header_count, &histogram),
+            "2 fields |#######             | 1 records\n3 fields |####################| 3 records (headers)\n4 fields |#######             | 1 records\n"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use csv::{Writer, WriterBuilder};
+use std::io::{self, BufRead, Error, ErrorKind, Read};
+use std::str::FromStr;
+
+/// Columns of a fixed-width input from `--fixed-width`, e.g. `0-5,5-10,10-30`, as
+/// character ranges from `start` included to `end` excluded
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct FixedWidth {
+    ranges: Vec<(usize, usize)>,
+    /// Names of the columns from `--expect-headers`, the first line being a header line
+    /// cut like the others without them
+    headers: Option<Vec<String>>,
+}
+
+impl FromStr for FixedWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid fixed-width columns {}, expected e.g. 0-5,5-10", s);
+        let ranges: Vec<(usize, usize)> = s
+            .split(',')
+            .map(|range| {
+                let (start, end) = range.trim().split_once('-').ok_or_else(invalid)?;
+                let start: usize = start.parse().map_err(|_| invalid())?;
+                let end: usize = end.parse().map_err(|_| invalid())?;
+                if start >= end {
+                    return Err(format!("Empty fixed-width column {}", range));
+                }
+                Ok((start, end))
+            })
+            .collect::<Result<Vec<(usize, usize)>, String>>()?;
+        Ok(FixedWidth {
+            ranges,
+            headers: None,
+        })
+    }
+}
+
+impl FixedWidth {
+    /// Number of columns cut from each line
+    pub(crate) fn columns(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Name the columns, the first line of the input then being a record
+    pub(crate) fn with_headers(&self, headers: &[String]) -> Self {
+        FixedWidth {
+            ranges: self.ranges.clone(),
+            headers: Some(headers.to_vec()),
+        }
+    }
+
+    /// Cut a line into its fields, trimming the padding. A line too short for a column
+    /// leaves it empty
+    fn fields<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        let offsets: Vec<usize> = line
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([line.len()])
+            .collect();
+        let offset = |chars: usize| offsets[chars.min(offsets.len() - 1)];
+        self.ranges
+            .iter()
+            .map(|&(start, end)| line[offset(start)..offset(end)].trim())
+            .collect()
+    }
+}
+
+/// Reader of a fixed-width input giving its lines back as delimited records, so the CSV
+/// reader of the split takes it like any other input. Blank lines are left out
+pub(crate) struct FixedWidthReader<R: BufRead> {
+    inner: R,
+    fixed_width: FixedWidth,
+    writer_builder: WriterBuilder,
+    line: Vec<u8>,
+    /// Current record, written by a writer reusing its buffer for each line
+    record: Vec<u8>,
+    /// Bytes of the current record already read
+    read: usize,
+}
+
+impl<R: BufRead> FixedWidthReader<R> {
+    pub(crate) fn new(inner: R, fixed_width: &FixedWidth, delimiter: u8) -> io::Result<Self> {
+        let mut writer_builder: WriterBuilder = WriterBuilder::new();
+        writer_builder.delimiter(delimiter);
+        let mut reader: FixedWidthReader<R> = FixedWidthReader {
+            inner,
+            fixed_width: fixed_width.clone(),
+            writer_builder,
+            line: Vec::new(),
+            record: Vec::new(),
+            read: 0,
+        };
+        if let Some(headers) = &fixed_width.headers {
+            reader.write_record(headers)?;
+        }
+        Ok(reader)
+    }
+
+    fn write_record<I, T>(&mut self, fields: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let mut buffer: Vec<u8> = std::mem::take(&mut self.record);
+        buffer.clear();
+        let mut writer: Writer<Vec<u8>> = self.writer_builder.from_writer(buffer);
+        writer.write_record(fields)?;
+        self.record = writer.into_inner().map_err(|error| error.into_error())?;
+        self.read = 0;
+        Ok(())
+    }
+
+    /// Write the record of the next non blank line, returning false at the end of input
+    fn next_record(&mut self) -> io::Result<bool> {
+        loop {
+            self.line.clear();
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(false);
+            }
+            let line: &str = std::str::from_utf8(&self.line)
+                .map_err(|error| Error::new(ErrorKind::InvalidData, error))?
+                .trim_end_matches(['\r', '\n']);
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<String> = self
+                .fixed_width
+                .fields(line)
+                .into_iter()
+                .map(String::from)
+                .collect();
+            self.write_record(fields)?;
+            return Ok(true);
+        }
+    }
+}
+
+impl<R: BufRead> Read for FixedWidthReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read == self.record.len() && !self.next_record()? {
+            return Ok(0);
+        }
+        let record: &[u8] = &self.record[self.read..];
+        let read: usize = record.len().min(buf.len());
+        buf[..read].copy_from_slice(&record[..read]);
+        self.read += read;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_width_reader() {
+        let fixed_width: FixedWidth = "0-6,6-8,8-16".parse().unwrap();
+        let input: &[u8] = "Kenai AK7610\n\nOakmanAL\r\nBogotá,CO7181469\n".as_bytes();
+        let mut output = String::new();
+        FixedWidthReader::new(input, &fixed_width, b'|')
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+
+        assert_eq!(output, "Kenai|AK|7610\nOakman|AL|\nBogotá|,C|O7181469\n");
+        let named: FixedWidth = "0-6,6-8"
+            .parse::<FixedWidth>()
+            .unwrap()
+            .with_headers(&[String::from("City"), String::from("State")]);
+        let mut output = String::new();
+        FixedWidthReader::new("Kenai AK\n".as_bytes(), &named, b',')
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output, "City,State\nKenai,AK\n");
+        assert!("0-5,5-5".parse::<FixedWidth>().is_err());
+        assert!("0-5,x".parse::<FixedWidth>().is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::app_error::AppError;
+use crate::data_loading::{read_file, ReaderConfig};
+use csv::{Reader, StringRecord};
+use serde_json::{Map, Value};
+use std::io::{Error, ErrorKind, Read};
+use std::path::Path;
+
+/// Columns replacing a JSON object column for `--expand-json`, one per top-level key
+/// found in the input
+#[derive(Debug, PartialEq)]
+pub(crate) struct JsonExpansion {
+    column: String,
+    column_idx: usize,
+    /// Keys in the order they first appear in the input
+    keys: Vec<String>,
+}
+
+impl JsonExpansion {
+    /// Read the whole input once to collect the keys of the JSON column
+    pub(crate) fn scan(
+        path: &Path,
+        reader_config: &ReaderConfig,
+        column: &str,
+    ) -> Result<Self, AppError> {
+        let mut reader: Reader<Box<dyn Read>> = read_file(path, reader_config)?;
+        let column_idx: usize = reader
+            .headers()?
+            .iter()
+            .position(|h| h == column)
+            .ok_or_else(|| AppError::ColumnNotFound(column.to_string()))?;
+        let mut keys: Vec<String> = Vec::new();
+        for result in reader.records() {
+            let record: StringRecord = result?;
+            if let Some(object) = parse_object(&record, column_idx)? {
+                for key in object.keys() {
+                    if !keys.contains(key) {
+                        keys.push(key.clone());
+                    }
+                }
+            }
+        }
+        Ok(JsonExpansion {
+            column: column.to_string(),
+            column_idx,
+            keys,
+        })
+    }
+
+    /// Headers with the JSON column replaced by a `<column>.<key>` column for each key
+    pub(crate) fn headers(&self, headers: &StringRecord) -> StringRecord {
+        let mut expanded: StringRecord = StringRecord::new();
+        for (idx, header) in headers.iter().enumerate() {
+            if idx == self.column_idx {
+                for key in &self.keys {
+                    expanded.push_field(&format!("{}.{}", self.column, key));
+                }
+            } else {
+                expanded.push_field(header);
+            }
+        }
+        expanded
+    }
+
+    /// Record with the JSON value replaced by its value for each key, empty when absent.
+    /// Strings are written without their quotes and nested values as compact JSON
+    pub(crate) fn expand(&self, record: &StringRecord) -> Result<StringRecord, AppError> {
+        let object: Option<Map<String, Value>> = parse_object(record, self.column_idx)?;
+        let mut expanded: StringRecord = StringRecord::new();
+        for (idx, field) in record.iter().enumerate() {
+            if idx != self.column_idx {
+                expanded.push_field(field);
+                continue;
+            }
+            for key in &self.keys {
+                match object.as_ref().and_then(|object| object.get(key)) {
+                    Some(Value::String(text)) => expanded.push_field(text),
+                    Some(Value::Null) | None => expanded.push_field(""),
+                    Some(value) => expanded.push_field(&value.to_string()),
+                }
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+/// Parse the JSON object of a record, `None` when the field is empty or missing
+fn parse_object(
+    record: &StringRecord,
+    column_idx: usize,
+) -> Result<Option<Map<String, Value>>, AppError> {
+    let field: &str = record.get(column_idx).unwrap_or_default().trim();
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let line: u64 = record.position().map_or(0, |position| position.line());
+    match serde_json::from_str::<Value>(field) {
+        Ok(Value::Object(object)) => Ok(Some(object)),
+        Ok(_) => Err(AppError::Io(Error::new(
+            ErrorKind::InvalidData,
+            format!("The JSON value at line {} isn't an object", line),
+        ))),
+        Err(error) => Err(AppError::Io(Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid JSON at line {}: {}", line, error),
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delimiter::Delimiter;
+
+    #[test]
+    fn test_json_expansion() {
+        let expansion = JsonExpansion::scan(
+            Path::new("assets/json_city.csv"),
+            &ReaderConfig::new(&Delimiter::Comma),
+            "Info",
+        )
+        .unwrap();
+        let headers = StringRecord::from(vec!["City", "State", "Info"]);
+
+        assert_eq!(
+            expansion.headers(&headers),
+            StringRecord::from(vec![
+                "City",
+                "State",
+                "Info.population",
+                "Info.county",
+                "Info.capital"
+            ])
+        );
+        let record = StringRecord::from(vec![
+            "Albany",
+            "NY",
+            r#"{"capital":true,"population":99224}"#,
+        ]);
+        assert_eq!(
+            expansion.expand(&record).unwrap(),
+            StringRecord::from(vec!["Albany", "NY", "99224", "", "true"])
+        );
+        let invalid = StringRecord::from(vec!["Albany", "NY", "[1, 2]"]);
+        assert!(expansion.expand(&invalid).is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use crate::app_error::AppError;
+use csv::StringRecord;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+/// Expression computing the category of a record from `--key-expr`, e.g.
+/// `State + "-" + substr(Zip, 0, 3)`. The grammar is
+///
+/// ```text
+/// expr  := term ('+' term)*
+/// term  := "text" | column | column '[' n ']' | substr '(' expr ',' start ',' len ')'
+///        | col '(' "column name" ')' | '(' expr ')'
+/// ```
+///
+/// where `column` is a name of letters, digits and `_`, `[n]` takes the character at `n`
+/// and `substr` takes `len` characters from `start`, both counting from 0
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum KeyExpr {
+    Literal(String),
+    /// Column name and its index once bound to the headers
+    Field(String, Option<usize>),
+    Char(Box<KeyExpr>, usize),
+    Substr(Box<KeyExpr>, usize, usize),
+    Concat(Vec<KeyExpr>),
+    /// Values joined by a separator for `--key-columns`, escaping it within the values
+    Join(Vec<KeyExpr>, String),
+}
+
+impl KeyExpr {
+    /// Composite key of the columns in the given order, joined by `separator`
+    pub(crate) fn join_columns(columns: &[String], separator: &str) -> Self {
+        KeyExpr::Join(
+            columns
+                .iter()
+                .map(|column| KeyExpr::Field(column.clone(), None))
+                .collect(),
+            separator.to_string(),
+        )
+    }
+
+    /// Resolve the column names against the headers of an input
+    pub(crate) fn bind(&self, headers: &StringRecord) -> Result<KeyExpr, AppError> {
+        Ok(match self {
+            KeyExpr::Literal(text) => KeyExpr::Literal(text.clone()),
+            KeyExpr::Field(column, _) => {
+                let idx: usize = headers
+                    .iter()
+                    .position(|h| h == column)
+                    .ok_or_else(|| AppError::ColumnNotFound(column.clone()))?;
+                KeyExpr::Field(column.clone(), Some(idx))
+            }
+            KeyExpr::Char(expr, idx) => KeyExpr::Char(Box::new(expr.bind(headers)?), *idx),
+            KeyExpr::Substr(expr, start, len) => {
+                KeyExpr::Substr(Box::new(expr.bind(headers)?), *start, *len)
+            }
+            KeyExpr::Concat(exprs) => KeyExpr::Concat(
+                exprs
+                    .iter()
+                    .map(|expr| expr.bind(headers))
+                    .collect::<Result<Vec<KeyExpr>, AppError>>()?,
+            ),
+            KeyExpr::Join(exprs, separator) => KeyExpr::Join(
+                exprs
+                    .iter()
+                    .map(|expr| expr.bind(headers))
+                    .collect::<Result<Vec<KeyExpr>, AppError>>()?,
+                separator.clone(),
+            ),
+        })
+    }
+
+    /// Compute the value of a record, missing fields and characters being empty
+    pub(crate) fn evaluate(&self, record: &StringRecord) -> String {
+        match self {
+            KeyExpr::Literal(text) => text.clone(),
+            KeyExpr::Field(_, idx) => idx
+                .and_then(|idx| record.get(idx))
+                .unwrap_or_default()
+                .to_string(),
+            KeyExpr::Char(expr, idx) => expr
+                .evaluate(record)
+                .chars()
+                .nth(*idx)
+                .map(String::from)
+                .unwrap_or_default(),
+            KeyExpr::Substr(expr, start, len) => expr
+                .evaluate(record)
+                .chars()
+                .skip(*start)
+                .take(*len)
+                .collect(),
+            KeyExpr::Concat(exprs) => exprs.iter().map(|expr| expr.evaluate(record)).collect(),
+            KeyExpr::Join(exprs, separator) => exprs
+                .iter()
+                .map(|expr| escape_separator(&expr.evaluate(record), separator))
+                .collect::<Vec<String>>()
+                .join(separator),
+        }
+    }
+}
+
+/// Percent-encode the separator and `%` within a value of a composite key, so the key
+/// still tells its values apart, e.g. `a_b` joined by `_` becomes `a%5Fb`. The separator
+/// is neither empty nor holds a `%`, as `--key-separator` checks
+fn escape_separator(value: &str, separator: &str) -> String {
+    if !(value.contains(separator) || value.contains('%')) {
+        return value.to_string();
+    }
+    let escaped_separator: String = separator
+        .bytes()
+        .map(|byte| format!("%{:02X}", byte))
+        .collect();
+    value
+        .replace('%', "%25")
+        .replace(separator, &escaped_separator)
+}
+
+impl FromStr for KeyExpr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser: Parser = Parser {
+            chars: s.chars().peekable(),
+        };
+        let expr: KeyExpr = parser.expr()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            Some(unexpected) => Err(format!(
+                "Invalid key expression {}, unexpected {:?}",
+                s, unexpected
+            )),
+            None => Ok(expr),
+        }
+    }
+}
+
+/// Recursive descent parser of the `--key-expr` grammar
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn expr(&mut self) -> Result<KeyExpr, String> {
+        let mut terms: Vec<KeyExpr> = vec![self.term()?];
+        while self.eat('+') {
+            terms.push(self.term()?);
+        }
+        Ok(match terms.len() {
+            1 => terms.remove(0),
+            _ => KeyExpr::Concat(terms),
+        })
+    }
+
+    fn term(&mut self) -> Result<KeyExpr, String> {
+        self.skip_whitespace();
+        let term: KeyExpr = match self.chars.peek() {
+            Some('"') => KeyExpr::Literal(self.string()?),
+            Some('(') => {
+                self.chars.next();
+                let expr: KeyExpr = self.expr()?;
+                self.expect(')')?;
+                expr
+            }
+            Some(_) => {
+                let name: String = self.identifier()?;
+                match name.as_str() {
+                    "substr" if self.eat('(') => {
+                        let expr: KeyExpr = self.expr()?;
+                        self.expect(',')?;
+                        let start: usize = self.number()?;
+                        self.expect(',')?;
+                        let len: usize = self.number()?;
+                        self.expect(')')?;
+                        KeyExpr::Substr(Box::new(expr), start, len)
+                    }
+                    "col" if self.eat('(') => {
+                        self.skip_whitespace();
+                        let column: String = self.string()?;
+                        self.expect(')')?;
+                        KeyExpr::Field(column, None)
+                    }
+                    _ => KeyExpr::Field(name, None),
+                }
+            }
+            None => return Err(String::from("Invalid key expression, a term is missing")),
+        };
+        if self.eat('[') {
+            let idx: usize = self.number()?;
+            self.expect(']')?;
+            return Ok(KeyExpr::Char(Box::new(term), idx));
+        }
+        Ok(term)
+    }
+
+    /// A double quoted string, `\"` and `\\` escaping the quote and the backslash
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text: String = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.chars.next() {
+                    Some(escaped) => text.push(escaped),
+                    None => break,
+                },
+                Some(current) => text.push(current),
+                None => break,
+            }
+        }
+        Err(String::from(
+            "Invalid key expression, a string isn't closed",
+        ))
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        let mut name: String = String::new();
+        while let Some(&current) = self.chars.peek() {
+            if !(current.is_alphanumeric() || current == '_') {
+                break;
+            }
+            name.push(current);
+            self.chars.next();
+        }
+        if name.is_empty() {
+            return Err(format!(
+                "Invalid key expression, expected a column instead of {:?}",
+                self.chars.peek().copied().unwrap_or_default()
+            ));
+        }
+        Ok(name)
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        self.skip_whitespace();
+        let identifier: String = self.identifier()?;
+        identifier
+            .parse()
+            .map_err(|_| format!("Invalid key expression, {} isn't a number", identifier))
+    }
+
+    /// Consume the character if it comes next, after any whitespace
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&expected).is_some()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(format!("Invalid key expression, expected {:?}", expected))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|current| current.is_whitespace())
+            .is_some()
+        {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expr: &str, record: &[&str]) -> String {
+        let headers = StringRecord::from(vec!["First Name", "lastname", "State", "Zip"]);
+        KeyExpr::from_str(expr)
+            .unwrap()
+            .bind(&headers)
+            .unwrap()
+            .evaluate(&StringRecord::from(record.to_vec()))
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let record = ["Ada", "Lovelace", "NY", "10027"];
+
+        assert_eq!(
+            evaluate(r#"col("First Name")[0] + lastname[0]"#, &record),
+            "AL"
+        );
+        assert_eq!(
+            evaluate(r#"State + "-" + substr(Zip, 0, 3)"#, &record),
+            "NY-100"
+        );
+        assert_eq!(evaluate("substr(lastname, 4, 10)", &record), "lace");
+        assert_eq!(evaluate("(State + Zip)[9]", &record), "");
+    }
+
+    #[test]
+    fn test_join_columns() {
+        let headers = StringRecord::from(vec!["State", "County", "Zip"]);
+        let key: KeyExpr =
+            KeyExpr::join_columns(&[String::from("Zip"), String::from("State")], "--")
+                .bind(&headers)
+                .unwrap();
+
+        assert_eq!(
+            key.evaluate(&StringRecord::from(vec!["NY", "Kings", "11201"])),
+            "11201--NY"
+        );
+        assert_eq!(
+            key.evaluate(&StringRecord::from(vec!["N--Y", "Kings", "100%"])),
+            "100%25--N%2D%2DY"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(KeyExpr::from_str("State +").is_err());
+        assert!(KeyExpr::from_str("substr(Zip, 0)").is_err());
+        assert!(KeyExpr::from_str("\"open").is_err());
+        assert!(KeyExpr::from_str("State Zip").is_err());
+        assert!(KeyExpr::from_str("Year")
+            .unwrap()
+            .bind(&StringRecord::from(vec!["State"]))
+            .is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::app_error::AppError;
+use crate::data_loading::read_raw;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// First bytes of a UTF-8 byte order mark
+const UTF8_BOM: [u8; 3] = [0xef, 0xbb, 0xbf];
+
+/// Print the raw bytes of the first lines of the input for `--peek`, bypassing the CSV
+/// parser. Each line is numbered and its control characters made visible
+pub(crate) fn peek_lines<W: Write>(path: &Path, lines: usize, out: &mut W) -> Result<(), AppError> {
+    let mut reader: BufReader<Box<dyn Read>> = BufReader::new(read_raw(path)?);
+    let mut line: Vec<u8> = Vec::new();
+    for line_number in 1..=lines {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        writeln!(
+            out,
+            "{}: {}",
+            line_number,
+            render_line(&line, line_number == 1)
+        )?;
+    }
+    Ok(())
+}
+
+/// Render the bytes of a line, e.g. `a\tb\r\n` is `a⟨TAB⟩b⟨CR⟩⟨LF⟩`. Bytes that aren't
+/// valid UTF-8 are shown as hexadecimal, like `⟨0xFF⟩`
+fn render_line(line: &[u8], is_first: bool) -> String {
+    let mut rendered: String = String::new();
+    let mut rest: &[u8] = line;
+    if is_first {
+        if let Some(after_bom) = rest.strip_prefix(&UTF8_BOM) {
+            rendered.push_str("⟨BOM⟩");
+            rest = after_bom;
+        }
+    }
+    for chunk in rest.utf8_chunks() {
+        for current in chunk.valid().chars() {
+            match current {
+                '\t' => rendered.push_str("⟨TAB⟩"),
+                '\r' => rendered.push_str("⟨CR⟩"),
+                '\n' => rendered.push_str("⟨LF⟩"),
+                '\u{feff}' => rendered.push_str("⟨BOM⟩"),
+                current if current.is_control() => {
+                    rendered.push_str(&format!("⟨U+{:04X}⟩", u32::from(current)))
+                }
+                current => rendered.push(current),
+            }
+        }
+        for byte in chunk.invalid() {
+            rendered.push_str(&format!("⟨0x{:02X}⟩", byte));
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_context::TestContext;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_peek_lines() {
+        let mut context = TestContext::new();
+        fs::create_dir_all("assets/tmp").unwrap();
+        let path = context.add_file(PathBuf::from("assets/tmp/peek_lines.csv"));
+        fs::write(
+            &path,
+            b"\xef\xbb\xbfCity\tState\r\nKenai\tAK\xff\r\nOakman\tAL\n",
+        )
+        .unwrap();
+        let mut out: Vec<u8> = Vec::new();
+
+        peek_lines(&path, 2, &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1: ⟨BOM⟩City⟨TAB⟩State⟨CR⟩⟨LF⟩\n2: Kenai⟨TAB⟩AK⟨0xFF⟩⟨CR⟩⟨LF⟩\n"
+        );
+    }
+}
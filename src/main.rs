@@ -1,68 +1,799 @@
 use clap::ArgMatches;
-use csv::{Reader, StringRecord, Writer};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufWriter;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, IsTerminal};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tracing::{event, span, Level, Span};
+use uuid::Uuid;
 
-use crate::data_loading::{extract_file_name, read_file};
-use crate::delimiter::Delimiter;
-use crate::record_context::RecordProcessingContext;
+use crate::app_error::AppError;
+use crate::category_inspecting::{CardinalityEstimate, CardinalitySample};
+use crate::delimiter::{Delimiter, DelimiterChoice, OutputDelimiter};
+use crate::file_following::FollowState;
+use crate::file_validating::ValidationReport;
+use crate::fixed_width::FixedWidth;
+use crate::key_expr::KeyExpr;
+use crate::output_backend::{LocalBackend, OutputBackend};
+use crate::output_encoding::{OutputEncoding, UnmappablePolicy};
+use crate::predicate::{FilterLogic, Predicate};
+use crate::progress_feed::ProgressFeed;
+use crate::record_context::{
+    CategoryTransform, ColumnMatch, ConcurrencyStrategy, CountTrailer, HeaderCase, HeaderTransform,
+    InvalidCategoryPolicy, OversizedRecordPolicy, RecordProcessingContext, RowRange, SortKey,
+    UnexpectedCategoryPolicy, UnmappedCategoryPolicy, WriteHeader,
+};
+use crate::row_hashing::RowHashAlgorithm;
+use crate::run_manifest::RunManifest;
+use crate::run_stats::RunStats;
+use crate::schema_inferring::{ColumnSchema, SqlDialect};
+use crate::split_summary::{SplitSummary, SummaryFormat};
+use crate::staging_dir::StagingDir;
 
+mod app_error;
+mod byte_splitting;
+mod category_inspecting;
 mod cli_parsing;
+mod column_transposing;
 mod data_filtering;
 mod data_loading;
 mod delimiter;
+mod directory_watching;
+mod file_following;
+mod file_validating;
+mod fixed_width;
+mod json_expanding;
+mod key_expr;
+mod line_peeking;
+mod output_backend;
+mod output_encoding;
+mod predicate;
+mod progress_feed;
 mod record_context;
+mod row_hashing;
+mod run_date;
+mod run_manifest;
+mod run_stats;
+mod schema_inferring;
+mod split_summary;
+mod staging_dir;
+mod tar_archiving;
+#[cfg(test)]
+mod test_context;
+mod xlsx_writing;
 
-fn main() {
-    tracing_subscriber::fmt::init();
+fn main() -> ExitCode {
+    let matches: Result<ArgMatches, AppError> = cli_parsing::parse_cli();
+    // The logs leave stdout to the JSON summary
+    let json_summary: bool = matches.as_ref().is_ok_and(|matches| {
+        matches.get_one::<SummaryFormat>("summary-format") == Some(&SummaryFormat::Json)
+    });
+    if json_summary {
+        tracing_subscriber::fmt().with_writer(io::stderr).init();
+    } else {
+        tracing_subscriber::fmt::init();
+    }
     let span: Span = span!(Level::INFO, "Splitting file...");
     let _guard = span.enter();
 
-    let matches: ArgMatches = cli_parsing::parse_cli();
-    let path: &str = matches.get_one::<String>("path").unwrap();
-    let delimiter: &Delimiter = matches.get_one::<Delimiter>("delimiter").unwrap();
-    let input_column: &str = matches.get_one::<String>("input-column").unwrap();
-    let output_dir_str: &str = matches.get_one::<String>("output-dir").unwrap();
+    match matches.and_then(|matches: ArgMatches| run(&matches)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            event!(Level::ERROR, "{}", error);
+            error.into()
+        }
+    }
+}
+
+/// Split the input described by the command line arguments
+fn run(matches: &ArgMatches) -> Result<(), AppError> {
+    let started_at: Instant = Instant::now();
+    if matches.get_flag("dump-config") {
+        print!("{}", cli_parsing::dump_config(matches));
+        return Ok(());
+    }
+    if let Some(&threads) = matches.get_one::<usize>("threads") {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|error| AppError::InvalidArguments(error.to_string()))?;
+    }
+    let delimiter_choice: &DelimiterChoice =
+        matches.get_one::<DelimiterChoice>("delimiter").unwrap();
+    let force: bool = matches.get_flag("force");
+    let output_delimiter: &OutputDelimiter =
+        matches.get_one::<OutputDelimiter>("delimiter-out").unwrap();
+    let (output_delimiter, raw_output): (u8, bool) = match output_delimiter {
+        OutputDelimiter::Fixed(delimiter) => (delimiter.as_byte(), false),
+        OutputDelimiter::None => (Delimiter::PIPE, true),
+    };
     let create_dir: bool = matches.get_flag("create-dir");
+    let force_create_dir: bool = matches.get_flag("force-create-dir");
+    let column_match: ColumnMatch = *matches.get_one::<ColumnMatch>("column-match").unwrap();
+    let header_transform: Option<HeaderTransform> = matches
+        .get_one::<HeaderCase>("header-normalize")
+        .map(|&header_case| -> HeaderTransform {
+            Arc::new(move |header: &str| header_case.normalize(header))
+        });
+    let max_record_bytes: Option<usize> = matches.get_one::<usize>("max-record-bytes").copied();
+    let oversized_record_policy: OversizedRecordPolicy = *matches
+        .get_one::<OversizedRecordPolicy>("on-oversized-record")
+        .unwrap();
+    let invalid_category_policy: InvalidCategoryPolicy = *matches
+        .get_one::<InvalidCategoryPolicy>("on-invalid-category")
+        .unwrap();
+    let category_pattern: Option<Regex> = matches.get_one::<Regex>("category-validate").cloned();
+    let key_regex: Option<&Regex> = matches.get_one::<Regex>("key-regex");
+    if invalid_category_policy == InvalidCategoryPolicy::Invalid && matches.get_flag("bytes") {
+        return Err(AppError::InvalidArguments(String::from(
+            "--bytes doesn't derive the categories, --on-invalid-category invalid renames them",
+        )));
+    }
+    let max_errors: Option<usize> = matches.get_one::<usize>("max-errors").copied();
+    let ascii_file_names: bool = matches.get_flag("ascii-only-filenames");
+    let key_columns: Vec<String> = matches
+        .get_many::<String>("key-columns")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let key_separator: &String = matches.get_one::<String>("key-separator").unwrap();
+    let key_expr: Option<KeyExpr> = match matches.get_one::<KeyExpr>("key-expr") {
+        Some(key_expr) => Some(key_expr.clone()),
+        None if !key_columns.is_empty() => Some(KeyExpr::join_columns(&key_columns, key_separator)),
+        None => None,
+    };
+    let sort_by: Option<SortKey> = matches.get_one::<SortKey>("sort-within-category").cloned();
+    let spill_threshold: Option<usize> = matches.get_one::<usize>("spill-threshold").copied();
+    let category_path_separator: Option<char> =
+        matches.get_one::<char>("category-path-sep").copied();
+    let max_name_length: Option<usize> = matches.get_one::<usize>("max-name-length").copied();
+    let filters: Vec<Predicate> = matches
+        .get_many::<Predicate>("filter")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let filter_logic: FilterLogic = *matches.get_one::<FilterLogic>("filter-logic").unwrap();
+    let null_category: &String = matches.get_one::<String>("null-category").unwrap();
+    let skip_null_category: bool = null_category == data_filtering::SKIP_NULL_CATEGORY;
+    // Nothing is written to it, and no split value is named empty as that is null
+    let null_category: String = if skip_null_category {
+        String::new()
+    } else {
+        null_category.clone()
+    };
+    let dropped_columns: Vec<String> = matches
+        .get_many::<String>("drop")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let always_quoted_categories: Vec<String> = matches
+        .get_many::<String>("always-quote-for")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let quote_empty: bool = matches.get_flag("quote-empty");
+    if raw_output && quote_empty {
+        return Err(AppError::InvalidArguments(String::from(
+            "--delimiter-out none writes the values without quoting, --quote-empty quotes them",
+        )));
+    }
+    let null_values: Vec<String> = matches
+        .get_many::<String>("null-values")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let success_marker: bool = matches.get_flag("success-marker");
+    let strip_quotes: bool = matches.get_flag("strip-quotes");
+    let trim: bool = matches.get_flag("trim");
+    let no_quoting: bool = matches.get_flag("no-quoting");
+    let normalize_line_endings: bool = matches.get_flag("normalize-line-endings");
+    let read_buffer_capacity: usize = *matches.get_one::<usize>("read-buffer").unwrap();
+    let skip_empty_lines: bool = matches.get_flag("skip-empty-lines");
+    let trailer_rows: usize = matches
+        .get_one::<usize>("trailer-rows")
+        .copied()
+        .unwrap_or_default();
+    let expected_headers: Vec<String> = matches
+        .get_many::<String>("expect-headers")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let expect_headers_subset: bool = matches.get_flag("expect-headers-subset");
+    let fixed_width: Option<FixedWidth> = match matches.get_one::<FixedWidth>("fixed-width") {
+        Some(_) if matches!(delimiter_choice, DelimiterChoice::Auto) => {
+            return Err(AppError::InvalidArguments(String::from(
+                "--delimiter auto can't be used with --fixed-width",
+            )));
+        }
+        Some(fixed_width) if !expected_headers.is_empty() => {
+            if expected_headers.len() != fixed_width.columns() {
+                return Err(AppError::InvalidArguments(format!(
+                    "--expect-headers names {} columns, --fixed-width cuts {}",
+                    expected_headers.len(),
+                    fixed_width.columns()
+                )));
+            }
+            Some(fixed_width.with_headers(&expected_headers))
+        }
+        fixed_width => fixed_width.cloned(),
+    };
+    let category_transforms: Vec<CategoryTransform> = matches
+        .get_many::<CategoryTransform>("category-transform")
+        .unwrap_or_default()
+        .copied()
+        .collect();
+    let mut category_aliases: HashMap<String, String> = HashMap::new();
+    for (raw, category) in matches
+        .get_many::<(String, String)>("alias")
+        .unwrap_or_default()
+    {
+        match category_aliases.insert(raw.clone(), category.clone()) {
+            Some(previous) if previous != *category => {
+                return Err(AppError::InvalidArguments(format!(
+                    "--alias maps {:?} to both {:?} and {:?}",
+                    raw, previous, category
+                )));
+            }
+            _ => {}
+        }
+    }
+    // A join file maps each join column value to its category, the misses being null
+    let join_file: Option<&String> = matches.get_one::<String>("join-file");
+    let category_map: HashMap<String, String> =
+        match (join_file, matches.get_one::<String>("category-map")) {
+            (Some(join_file), _) => data_loading::read_join_table(
+                Path::new(join_file),
+                matches.get_one::<String>("join-on").unwrap(),
+                matches.get_one::<String>("join-key").unwrap(),
+            )?,
+            (None, Some(category_map_path)) => {
+                data_loading::read_category_map(Path::new(category_map_path))?
+            }
+            (None, None) => HashMap::new(),
+        };
+    let unmapped_category_policy: UnmappedCategoryPolicy = match join_file {
+        Some(_) => UnmappedCategoryPolicy::Null,
+        None => *matches
+            .get_one::<UnmappedCategoryPolicy>("unmapped-category")
+            .unwrap(),
+    };
+    let expected_categories: HashSet<String> = match matches.get_one::<String>("categories-file") {
+        Some(categories_path) => data_loading::read_categories_file(Path::new(categories_path))?
+            .into_iter()
+            .collect(),
+        None => HashSet::new(),
+    };
+    let unexpected_category_policy: UnexpectedCategoryPolicy = *matches
+        .get_one::<UnexpectedCategoryPolicy>("unexpected-category")
+        .unwrap();
+    let freeze_categories_after: Option<usize> =
+        matches.get_one::<usize>("freeze-categories-after").copied();
+    let frozen_category_policy: UnexpectedCategoryPolicy = *matches
+        .get_one::<UnexpectedCategoryPolicy>("frozen-category")
+        .unwrap();
+    let header_map: HashMap<String, Vec<String>> = match matches.get_one::<String>("header-map") {
+        Some(header_map_path) => data_loading::read_header_map(Path::new(header_map_path))?,
+        None => HashMap::new(),
+    };
+    let allow_schema_drift: bool = matches.get_flag("allow-schema-drift");
+    let writer_thread: bool = matches.get_flag("writer-thread");
+    let single_threaded: bool = matches.get_flag("single-threaded");
+    let sorted_input: bool = matches.get_flag("sorted-input");
+    let check_sorted: bool = matches.get_flag("check-sorted");
+    let concurrency_strategy: ConcurrencyStrategy = *matches
+        .get_one::<ConcurrencyStrategy>("concurrency-strategy")
+        .unwrap();
+    let emit_schema: bool = matches.get_flag("emit-schema");
+    let write_header: WriteHeader = *matches.get_one::<WriteHeader>("write-header").unwrap();
+    let excel_sep_hint: bool = matches.get_flag("excel-sep-hint");
+    let tee_path: Option<PathBuf> = matches.get_one::<String>("tee").map(PathBuf::from);
+    let retry_open: usize = matches
+        .get_one::<usize>("retry-open")
+        .copied()
+        .unwrap_or_default();
+    let output_encoding: OutputEncoding = *matches
+        .get_one::<OutputEncoding>("output-encoding")
+        .unwrap();
+    let unmappable_policy: UnmappablePolicy = *matches
+        .get_one::<UnmappablePolicy>("on-unmappable-char")
+        .unwrap();
+    let run_id: String = match matches.get_one::<String>("run-id") {
+        Some(run_id) => run_id.clone(),
+        None => Uuid::new_v4().to_string(),
+    };
+    let run_id_column: Option<String> = matches
+        .get_flag("append-run-id-column")
+        .then(|| run_id.clone());
+    let row_hash_column: Option<String> = matches.get_one::<String>("row-hash-column").cloned();
+    let row_hash_algorithm: RowHashAlgorithm = *matches
+        .get_one::<RowHashAlgorithm>("row-hash-algorithm")
+        .unwrap();
+    if raw_output && row_hash_column.is_some() {
+        return Err(AppError::InvalidArguments(String::from(
+            "--delimiter-out none needs a single output column, --row-hash-column adds one",
+        )));
+    }
+    if raw_output && run_id_column.is_some() {
+        return Err(AppError::InvalidArguments(String::from(
+            "--delimiter-out none needs a single output column, --append-run-id-column adds one",
+        )));
+    }
+    let count_trailer: Option<CountTrailer> =
+        matches.get_one::<CountTrailer>("count-trailer").copied();
+    let comment_char: Option<u8> = matches.get_one::<u8>("comment-char").copied();
+    let expand_json: Option<String> = matches.get_one::<String>("expand-json").cloned();
+    let manifest: Option<Arc<Mutex<RunManifest>>> =
+        matches.get_one::<String>("manifest").map(|manifest_path| {
+            Arc::new(Mutex::new(RunManifest::new(
+                Path::new(manifest_path),
+                &run_id,
+            )))
+        });
+    let progress_feed: Option<Arc<Mutex<ProgressFeed>>> =
+        match matches.get_one::<String>("progress-to") {
+            Some(progress_path) => Some(Arc::new(Mutex::new(ProgressFeed::create(Path::new(
+                progress_path,
+            ))?))),
+            None => None,
+        };
+    let channel_capacity: usize = *matches.get_one::<usize>("channel-capacity").unwrap();
+    let max_runtime: Option<Duration> = matches.get_one::<Duration>("max-runtime").copied();
+    let row_range: Option<RowRange> = matches.get_one::<RowRange>("rows").copied();
+    // A runtime too long for the clock never ends the run
+    let deadline: Option<Instant> =
+        max_runtime.and_then(|max_runtime| Instant::now().checked_add(max_runtime));
+    let follow: Option<Arc<FollowState>> = matches
+        .get_flag("follow")
+        .then(|| Arc::new(FollowState::new(deadline)));
+    let dedupe: bool = matches.get_flag("dedupe");
+    let dedupe_global: bool = matches.get_flag("dedupe-global");
+    let dedupe_key: Vec<String> = matches
+        .get_many::<String>("dedupe-key")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
 
-    let path: &Path = Path::new(path);
-    let output_dir: PathBuf = PathBuf::from(output_dir_str);
+    let tar_path: Option<&String> = matches.get_one::<String>("tar");
+    let xlsx_path: Option<&String> = matches.get_one::<String>("xlsx");
+    if raw_output && xlsx_path.is_some() {
+        return Err(AppError::InvalidArguments(String::from(
+            "--delimiter-out none writes the values without quoting, --xlsx reads them back as delimited records",
+        )));
+    }
+    // Removed once the run ends, the files having been packed or the run having failed
+    let staging_dir: Option<StagingDir> = match (tar_path, xlsx_path) {
+        (Some(_), _) => Some(StagingDir::create("tar")?),
+        (_, Some(_)) => Some(StagingDir::create("xlsx")?),
+        _ => None,
+    };
+    let (output_backend, output_dir): (Arc<dyn OutputBackend>, PathBuf) = match &staging_dir {
+        Some(staging_dir) => (Arc::new(LocalBackend), staging_dir.path().to_path_buf()),
+        None => output_backend::backend_for(&cli_parsing::resolve_output_dir(matches))?,
+    };
+    if create_dir {
+        output_backend.create_dir_all(&output_dir)?;
+    }
+    let file_name: String = matches
+        .get_one::<String>("file-name")
+        .cloned()
+        .unwrap_or_default();
+    // Each file would otherwise name the category files it happens to open first
+    if create_dir && file_name.is_empty() && matches.contains_id("input-glob") {
+        return Err(AppError::InvalidArguments(String::from(
+            "--input-glob with --create-dir needs a --file-name",
+        )));
+    }
 
-    event!(Level::INFO, "Reading file: {:?}", path);
-    let mut reader: Reader<File> = read_file(path, delimiter).unwrap();
+    let context: RecordProcessingContext = RecordProcessingContext {
+        output_dir,
+        output_backend,
+        create_directory: create_dir,
+        shard_by_initial: matches.get_flag("shard-by-initial"),
+        force_create_dir,
+        column_match,
+        header_transform,
+        key_expr,
+        category_path_separator,
+        max_name_length,
+        max_record_bytes,
+        oversized_record_policy,
+        sort_by,
+        spill_threshold,
+        ascii_file_names,
+        filters,
+        filter_logic,
+        null_category,
+        skip_null_category,
+        null_values,
+        success_marker,
+        strip_quotes,
+        trim,
+        no_quoting,
+        normalize_line_endings,
+        read_buffer_capacity,
+        fixed_width,
+        follow,
+        skip_empty_lines,
+        trailer_rows,
+        expected_headers,
+        expect_headers_subset,
+        header_map: Arc::new(header_map),
+        category_aliases: Arc::new(category_aliases),
+        category_transforms,
+        category_map: Arc::new(category_map),
+        unmapped_category_policy,
+        expected_categories: Arc::new(expected_categories),
+        unexpected_category_policy,
+        freeze_categories_after,
+        frozen_category_policy,
+        allow_schema_drift,
+        writer_thread,
+        single_threaded,
+        sorted_input,
+        check_sorted,
+        concurrency_strategy,
+        write_header,
+        excel_sep_hint,
+        tee_path,
+        retry_open,
+        output_encoding,
+        unmappable_policy,
+        run_id_column,
+        row_hash_column,
+        row_hash_algorithm,
+        count_trailer,
+        comment_char,
+        emit_schema,
+        progress_feed,
+        manifest,
+        expand_json,
+        channel_capacity,
+        deadline,
+        row_range,
+        invalid_category_policy,
+        category_pattern,
+        max_errors,
+        file_name,
+        delimiter: output_delimiter,
+        raw_output,
+        dropped_columns,
+        always_quoted_categories,
+        quote_empty,
+        dedupe,
+        dedupe_key,
+        dedupe_global,
+        ..Default::default()
+    };
 
-    let file_name: String = extract_file_name(path).unwrap();
-    let headers: StringRecord = reader.headers().unwrap().clone();
+    if matches.get_flag("transpose-columns") {
+        let path: &Path = Path::new(matches.get_one::<String>("path").unwrap());
+        let delimiter: Delimiter = data_loading::resolve_delimiter(path, delimiter_choice, force)?;
+        event!(Level::INFO, "Writing columns of {:?} to CSV...", path);
+        let summary: SplitSummary =
+            column_transposing::split_file_by_column(path, &delimiter, &context)?;
+        event!(
+            Level::INFO,
+            "Finished writing {} columns to CSV",
+            summary.created.len() + summary.appended.len()
+        );
+        if let Some(tar_path) = tar_path {
+            archive_output(&context.output_dir, Path::new(tar_path))?;
+        }
+        return Ok(());
+    }
 
-    let category_writers: Arc<Mutex<HashMap<String, Writer<BufWriter<File>>>>> =
-        Arc::new(Mutex::new(HashMap::new()));
-    // Get the index of the column to split by
+    if let Some(&lines) = matches.get_one::<usize>("peek") {
+        let path: &Path = Path::new(matches.get_one::<String>("path").unwrap());
+        line_peeking::peek_lines(path, lines, &mut io::stdout().lock())?;
+        return Ok(());
+    }
 
-    let split_column_idx: usize = headers.iter().position(|h| h == input_column).unwrap();
-    let file_headers: StringRecord = data_filtering::get_headers(&headers, split_column_idx);
-    let header_indexes: Vec<usize> = data_filtering::get_header_indexes(&headers, &file_headers);
+    if matches.get_flag("field-count-report") {
+        let path: &Path = Path::new(matches.get_one::<String>("path").unwrap());
+        let delimiter: Delimiter = data_loading::resolve_delimiter(path, delimiter_choice, force)?;
+        let (header_count, histogram) = file_validating::count_fields(path, &delimiter, &context)?;
+        print!(
+            "{}",
+            file_validating::format_field_counts(header_count, &histogram)
+        );
+        return Ok(());
+    }
 
-    let context: Arc<RecordProcessingContext> = Arc::new(RecordProcessingContext {
-        headers: file_headers,
-        output_dir,
-        create_directory: create_dir,
-        file_name,
-        delimiter: Delimiter::PIPE,
-        split_column_idx,
-        writers: category_writers.clone(),
-        header_indexes,
-    });
+    if matches.get_flag("print-schema-sql") {
+        let path: &Path = Path::new(matches.get_one::<String>("path").unwrap());
+        let delimiter: Delimiter = data_loading::resolve_delimiter(path, delimiter_choice, force)?;
+        let dialect: SqlDialect = *matches.get_one::<SqlDialect>("sql-dialect").unwrap();
+        let table: String = if context.file_name.is_empty() {
+            data_loading::extract_file_name(path)?
+        } else {
+            context.file_name.clone()
+        };
+        let columns: Vec<ColumnSchema> =
+            schema_inferring::infer_column_types(path, &context.reader_config(&delimiter))?;
+        println!(
+            "{}",
+            schema_inferring::create_table_sql(&table, &columns, dialect)
+        );
+        return Ok(());
+    }
 
-    event!(Level::INFO, "Writing records to CSV...");
-    data_filtering::write_records_to_csv(&mut reader, &context).unwrap();
-    let mut writers: MutexGuard<HashMap<String, Writer<BufWriter<File>>>> =
-        category_writers.lock().unwrap();
-    for writer in writers.values_mut() {
-        writer.flush().unwrap();
+    // A key expression stands in for the split column, named after its text in the logs
+    let input_columns: Vec<String> = match matches.get_raw("key-expr") {
+        Some(mut key_expr) => vec![key_expr
+            .next()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()],
+        None if !key_columns.is_empty() => vec![key_columns.join(key_separator)],
+        None => match matches.get_one::<String>("join-on") {
+            Some(join_on) => vec![join_on.clone()],
+            None => matches
+                .get_many::<String>("input-column")
+                .unwrap()
+                .cloned()
+                .collect(),
+        },
+    };
+    let input_column: &str = &input_columns[0];
+    if let Some(category) = matches.get_one::<String>("inspect-category") {
+        let path: &Path = Path::new(matches.get_one::<String>("path").unwrap());
+        let delimiter: Delimiter = data_loading::resolve_delimiter(path, delimiter_choice, force)?;
+        category_inspecting::inspect_category(
+            path,
+            input_column,
+            &delimiter,
+            &context,
+            category,
+            &mut io::stdout().lock(),
+        )?;
+        return Ok(());
+    }
+    if matches.get_flag("validate-only") {
+        let path: &Path = Path::new(matches.get_one::<String>("path").unwrap());
+        let delimiter: Delimiter = data_loading::resolve_delimiter(path, delimiter_choice, force)?;
+        let report: ValidationReport =
+            file_validating::validate_file(path, &input_columns, &delimiter, &context)?;
+        println!("{}", report.describe());
+        if report.invalid_records() > 0 {
+            return Err(AppError::ValidationFailed {
+                invalid: report.invalid_records(),
+                records: report.records,
+            });
+        }
+        return Ok(());
+    }
+    if let Some(&sample) = matches.get_one::<CardinalitySample>("measure-cardinality-sampled") {
+        let path: &Path = Path::new(matches.get_one::<String>("path").unwrap());
+        let delimiter: Delimiter = data_loading::resolve_delimiter(path, delimiter_choice, force)?;
+        for input_column in &input_columns {
+            let estimate: CardinalityEstimate = category_inspecting::estimate_cardinality(
+                path,
+                input_column,
+                &delimiter,
+                &context,
+                sample,
+            )?;
+            println!(
+                "{:?}: {} distinct categories in {} sampled records, an estimate from {}",
+                input_column, estimate.categories, estimate.sampled, sample
+            );
+        }
+        return Ok(());
+    }
+    if let Some(watch_dir) = matches.get_one::<String>("watch") {
+        let DelimiterChoice::Fixed(delimiter) = delimiter_choice else {
+            return Err(AppError::InvalidArguments(String::from(
+                "--delimiter auto can't be used with --watch",
+            )));
+        };
+        if input_columns.len() > 1 {
+            return Err(AppError::InvalidArguments(String::from(
+                "Several --column can't be used with --watch",
+            )));
+        }
+        let timed_out: bool = directory_watching::watch_directory(
+            Path::new(watch_dir),
+            input_column,
+            delimiter,
+            &context,
+        )?;
+        if let (true, Some(max_runtime)) = (timed_out, max_runtime) {
+            return Err(AppError::MaxRuntimeExceeded(max_runtime));
+        }
+        return Ok(());
+    }
+
+    event!(Level::INFO, "Run id: {}", run_id);
+    let summary: SplitSummary = if let Some(pattern) = matches.get_one::<String>("input-glob") {
+        if input_columns.len() > 1 {
+            return Err(AppError::InvalidArguments(String::from(
+                "--input-glob splits by a single column",
+            )));
+        }
+        let inputs: Vec<(PathBuf, Delimiter)> = data_loading::expand_input_glob(pattern)?
+            .into_iter()
+            .map(|path| {
+                let delimiter: Delimiter =
+                    data_loading::resolve_delimiter(&path, delimiter_choice, force)?;
+                Ok((path, delimiter))
+            })
+            .collect::<Result<Vec<(PathBuf, Delimiter)>, AppError>>()?;
+        event!(
+            Level::INFO,
+            "Splitting the {} files matching {:?}",
+            inputs.len(),
+            pattern
+        );
+        data_filtering::split_files_by_category(&inputs, input_column, &context)?
+    } else {
+        let path: &Path = Path::new(matches.get_one::<String>("path").unwrap());
+        let delimiter: Delimiter = data_loading::resolve_delimiter(path, delimiter_choice, force)?;
+        if let Some(&threshold) = matches.get_one::<usize>("confirm-categories-above") {
+            let mut count: usize = 0;
+            for input_column in &input_columns {
+                count += category_inspecting::count_categories(
+                    path,
+                    input_column,
+                    &delimiter,
+                    &context,
+                )?;
+            }
+            category_inspecting::confirm_category_count(
+                count,
+                threshold,
+                matches.get_flag("yes"),
+                io::stdin().is_terminal(),
+                &mut io::stdin().lock(),
+                &mut io::stderr(),
+            )?;
+        }
+        event!(Level::INFO, "Reading file: {:?}", path);
+        event!(Level::INFO, "Writing records to CSV...");
+        if matches.get_flag("bytes") {
+            if input_columns.len() > 1 {
+                return Err(AppError::InvalidArguments(String::from(
+                    "--bytes splits by a single column",
+                )));
+            }
+            byte_splitting::split_file_by_bytes(path, input_column, &delimiter, &context)?
+        } else if matches.get_flag("headers-only") {
+            if input_columns.len() > 1 {
+                return Err(AppError::InvalidArguments(String::from(
+                    "--headers-only splits by a single column",
+                )));
+            }
+            data_filtering::split_file_headers_only(path, input_column, &delimiter, &context)?
+        } else if input_columns.len() > 1 || key_regex.is_some() {
+            if context.manifest.is_some() {
+                return Err(AppError::InvalidArguments(String::from(
+                    "--manifest splits by a single column",
+                )));
+            }
+            if context.tee_path.is_some() {
+                return Err(AppError::InvalidArguments(String::from(
+                    "--tee splits by a single column",
+                )));
+            }
+            if sorted_input {
+                return Err(AppError::InvalidArguments(String::from(
+                    "--sorted-input splits by a single column",
+                )));
+            }
+            match key_regex {
+                Some(key_regex) if input_columns.len() == 1 => {
+                    data_filtering::split_file_by_key_groups(
+                        path,
+                        input_column,
+                        key_regex,
+                        &delimiter,
+                        &context,
+                    )?
+                }
+                Some(_) => {
+                    return Err(AppError::InvalidArguments(String::from(
+                        "--key-regex extracts from a single --column",
+                    )));
+                }
+                None => data_filtering::split_file_by_columns(
+                    path,
+                    &input_columns,
+                    &delimiter,
+                    &context,
+                )?,
+            }
+        } else {
+            data_filtering::split_file_by_category(path, input_column, &delimiter, &context)?
+        }
+    };
+    event!(
+        Level::INFO,
+        "Created {} files and appended to {} existing files",
+        summary.created.len(),
+        summary.appended.len()
+    );
+    for (category, file_category) in &summary.renamed {
+        event!(Level::INFO, "Wrote {} to {}", category, file_category);
+    }
+    if dedupe {
+        event!(
+            Level::INFO,
+            "Removed {} duplicate records",
+            summary.duplicates_removed
+        );
+    }
+    if dedupe_global {
+        event!(
+            Level::INFO,
+            "Removed {} duplicate records across categories",
+            summary.global_duplicates_removed
+        );
+    }
+    if max_record_bytes.is_some() {
+        event!(
+            Level::INFO,
+            "Skipped {} oversized records",
+            summary.oversized_records_skipped
+        );
+    }
+    if skip_empty_lines {
+        event!(
+            Level::INFO,
+            "Skipped {} empty lines",
+            summary.empty_lines_skipped
+        );
+    }
+    if skip_null_category {
+        event!(
+            Level::INFO,
+            "Skipped {} records with a null category",
+            summary.null_category_records_skipped
+        );
+    }
+    if invalid_category_policy == InvalidCategoryPolicy::Skip {
+        event!(
+            Level::INFO,
+            "Skipped {} records with an invalid category",
+            summary.invalid_category_records_skipped
+        );
+    }
+    if spill_threshold.is_some() {
+        event!(
+            Level::INFO,
+            "Spilled {} sorted segments to disk",
+            summary.spilled_segments
+        );
+    }
+    if let Some(&summary_format) = matches.get_one::<SummaryFormat>("summary-format") {
+        print!("{}", summary.format(summary_format));
+    }
+    if let Some(stats_path) = matches.get_one::<String>("stats-json") {
+        RunStats::from_summary(&summary, started_at.elapsed(), &run_id)?
+            .write(Path::new(stats_path))?;
+    }
+    if let Some(tar_path) = tar_path {
+        archive_output(&context.output_dir, Path::new(tar_path))?;
+    }
+    if let Some(xlsx_path) = xlsx_path {
+        let sheets: usize = xlsx_writing::write_workbook(
+            &context.output_dir,
+            Path::new(xlsx_path),
+            context.delimiter,
+            context.write_header != WriteHeader::Never,
+        )?;
+        event!(Level::INFO, "Wrote {} sheets to {:?}", sheets, xlsx_path);
+    }
+    if let (true, Some(max_runtime)) = (summary.timed_out, max_runtime) {
+        return Err(AppError::MaxRuntimeExceeded(max_runtime));
     }
     event!(Level::INFO, "Finished writing records to CSV");
+    Ok(())
+}
+
+/// Move the files written to the staging directory into the `--tar` archive
+fn archive_output(staging_dir: &Path, tar_path: &Path) -> Result<(), AppError> {
+    let entries: usize = tar_archiving::archive_directory(staging_dir, tar_path)?;
+    event!(Level::INFO, "Archived {} files to {:?}", entries, tar_path);
+    Ok(())
 }
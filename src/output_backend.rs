@@ -0,0 +1,169 @@
+use crate::app_error::AppError;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// `--dir` writing nothing
+pub(crate) const NULL_OUTPUT_DIR: &str = "-";
+
+/// Stream the records of a category are written to
+pub(crate) type OutputWriter = Box<dyn Write + Send>;
+
+/// Storage the split files are written to, the writers being opened through it
+pub(crate) trait OutputBackend: Send + Sync {
+    /// Size of an existing output file, `None` when there is none yet
+    fn existing_len(&self, path: &Path) -> io::Result<Option<u64>>;
+
+    /// Create a directory and its parents
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Open an output file for appending, creating it when missing
+    fn open_append(&self, path: &Path) -> io::Result<OutputWriter>;
+
+    /// Open an output file for writing, truncating it when it exists
+    fn create(&self, path: &Path) -> io::Result<OutputWriter>;
+
+    /// Whether the files are discarded rather than stored, none being created or appended to
+    fn discards(&self) -> bool {
+        false
+    }
+}
+
+/// Output files on the local disk
+pub(crate) struct LocalBackend;
+
+impl OutputBackend for LocalBackend {
+    fn existing_len(&self, path: &Path) -> io::Result<Option<u64>> {
+        match fs::metadata(path) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn open_append(&self, path: &Path) -> io::Result<OutputWriter> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Box::new(BufWriter::new(file)))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<OutputWriter> {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// Discarding everything written to it, for `--dir -`. The records still go through the
+/// projection and the CSV writers, so timing a run measures all but the disk
+pub(crate) struct NullBackend;
+
+impl OutputBackend for NullBackend {
+    fn existing_len(&self, _path: &Path) -> io::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    fn create_dir_all(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn open_append(&self, _path: &Path) -> io::Result<OutputWriter> {
+        Ok(Box::new(io::sink()))
+    }
+
+    fn create(&self, _path: &Path) -> io::Result<OutputWriter> {
+        Ok(Box::new(io::sink()))
+    }
+
+    fn discards(&self) -> bool {
+        true
+    }
+}
+
+/// Pick the backend of `--dir` from its scheme, returning the directory within it.
+/// Paths without a scheme and `file://` URIs are local, `-` discards the output. A
+/// `file://` URI holds an absolute path, with no host or `localhost`, as in
+/// `file:///data/splits`, a relative one like `file://data/splits` naming the host `data`
+pub(crate) fn backend_for(
+    output_dir: &Path,
+) -> Result<(Arc<dyn OutputBackend>, PathBuf), AppError> {
+    let Some(uri) = output_dir.to_str() else {
+        return Ok((Arc::new(LocalBackend), output_dir.to_path_buf()));
+    };
+    if uri == NULL_OUTPUT_DIR {
+        return Ok((Arc::new(NullBackend), output_dir.to_path_buf()));
+    }
+    if let Some(location) = uri.strip_prefix("file://") {
+        let local_path: &str = location
+            .strip_prefix("localhost")
+            .filter(|path| path.starts_with('/'))
+            .unwrap_or(location);
+        if !local_path.starts_with('/') {
+            return Err(AppError::InvalidArguments(format!(
+                "{} isn't a local directory, a file:// URI takes an absolute path like file:///data/splits",
+                uri
+            )));
+        }
+        return Ok((Arc::new(LocalBackend), PathBuf::from(local_path)));
+    }
+    match uri.split_once("://") {
+        Some((scheme, _)) => Err(AppError::InvalidArguments(format!(
+            "No output backend for {}:// directories, only local paths and file:// are supported",
+            scheme
+        ))),
+        None => Ok((Arc::new(LocalBackend), output_dir.to_path_buf())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_context::TestContext;
+    use std::env;
+
+    #[test]
+    fn test_local_backend() {
+        let mut context = TestContext::new();
+        let local_dir: PathBuf = env::current_dir().unwrap().join("assets/tmp/local_backend");
+        let uri: String = format!("file://{}", local_dir.display());
+        let (backend, output_dir) = backend_for(Path::new(&uri)).unwrap();
+        let output_dir = context.add_dir(output_dir);
+        let file_path = output_dir.join("AK.csv");
+
+        assert_eq!(output_dir, local_dir);
+        let localhost_uri: String = format!("file://localhost{}", local_dir.display());
+        assert_eq!(backend_for(Path::new(&localhost_uri)).unwrap().1, local_dir);
+        assert!(matches!(
+            backend_for(Path::new("file://assets/tmp/local_backend")),
+            Err(AppError::InvalidArguments(_))
+        ));
+        backend.create_dir_all(&output_dir).unwrap();
+        assert_eq!(backend.existing_len(&file_path).unwrap(), None);
+        for line in ["City\n", "Kenai\n"] {
+            let mut writer = backend.open_append(&file_path).unwrap();
+            writer.write_all(line.as_bytes()).unwrap();
+            writer.flush().unwrap();
+        }
+        assert_eq!(backend.existing_len(&file_path).unwrap(), Some(11));
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "City\nKenai\n");
+
+        assert!(backend_for(Path::new("s3://bucket/splits")).is_err());
+    }
+
+    #[test]
+    fn test_null_backend() {
+        let (backend, output_dir) = backend_for(Path::new("-")).unwrap();
+        let file_path = output_dir.join("AK.csv");
+
+        backend.create_dir_all(&output_dir).unwrap();
+        backend
+            .open_append(&file_path)
+            .unwrap()
+            .write_all(b"City\n")
+            .unwrap();
+        assert_eq!(backend.existing_len(&file_path).unwrap(), None);
+        assert!(!file_path.exists());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::output_backend::OutputWriter;
+use encoding_rs::{Encoder, EncoderResult, Encoding, WINDOWS_1252};
+use std::io::{self, Error, ErrorKind, Write};
+use std::str::FromStr;
+
+/// Bytes encoded at once by an `EncodingWriter`
+const ENCODE_BUFFER: usize = 8 * 1024;
+
+/// Encoding of the split files from `--output-encoding`, the records being UTF-8 until
+/// they are written
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum OutputEncoding {
+    #[default]
+    Utf8,
+    /// Also written for `latin1`, which names windows-1252 on the web as it is a superset
+    /// of ISO-8859-1 for printable characters
+    Windows1252,
+}
+
+/// What happens to a character the `--output-encoding` can't represent
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum UnmappablePolicy {
+    /// Write `?` instead
+    #[default]
+    Replace,
+    /// Stop the split with an invalid data error
+    Error,
+}
+
+impl FromStr for OutputEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Ok(OutputEncoding::Utf8),
+            "windows-1252" | "cp1252" | "latin1" => Ok(OutputEncoding::Windows1252),
+            _ => Err(format!(
+                "Invalid output encoding {}, expected utf8, windows-1252 or latin1",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for UnmappablePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "replace" => Ok(UnmappablePolicy::Replace),
+            "error" => Ok(UnmappablePolicy::Error),
+            _ => Err(format!("Invalid policy {}, expected replace or error", s)),
+        }
+    }
+}
+
+/// Writer encoding the UTF-8 written to it before passing it on. A character split across
+/// two writes is held back until the second one
+pub(crate) struct EncodingWriter<W: Write> {
+    inner: W,
+    encoder: Encoder,
+    policy: UnmappablePolicy,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    pub(crate) fn new(inner: W, encoding: &'static Encoding, policy: UnmappablePolicy) -> Self {
+        EncodingWriter {
+            inner,
+            encoder: encoding.new_encoder(),
+            policy,
+            pending: Vec::new(),
+        }
+    }
+
+    fn encode(&mut self, mut text: &str) -> io::Result<()> {
+        let mut buffer: [u8; ENCODE_BUFFER] = [0; ENCODE_BUFFER];
+        loop {
+            let (result, read, written) =
+                self.encoder
+                    .encode_from_utf8_without_replacement(text, &mut buffer, false);
+            self.inner.write_all(&buffer[..written])?;
+            text = &text[read..];
+            match result {
+                EncoderResult::InputEmpty => return Ok(()),
+                EncoderResult::OutputFull => {}
+                EncoderResult::Unmappable(unmappable) => match self.policy {
+                    UnmappablePolicy::Replace => self.inner.write_all(b"?")?,
+                    UnmappablePolicy::Error => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "{:?} can't be written in {}",
+                                unmappable,
+                                self.encoder.encoding().name()
+                            ),
+                        ))
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid_up_to: usize = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            // Only an incomplete character at the end is waited for
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(error) => return Err(Error::new(ErrorKind::InvalidData, error)),
+        };
+        let pending: Vec<u8> = self.pending.split_off(valid_up_to);
+        let text: Vec<u8> = std::mem::replace(&mut self.pending, pending);
+        // The bytes were checked above
+        self.encode(std::str::from_utf8(&text).unwrap_or_default())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Wrap the writer of a split file to write the `--output-encoding`, UTF-8 being written
+/// as is
+pub(crate) fn encode_output(
+    output: OutputWriter,
+    encoding: OutputEncoding,
+    policy: UnmappablePolicy,
+) -> OutputWriter {
+    match encoding {
+        OutputEncoding::Utf8 => output,
+        OutputEncoding::Windows1252 => Box::new(EncodingWriter::new(output, WINDOWS_1252, policy)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(text: &[u8], policy: UnmappablePolicy) -> io::Result<Vec<u8>> {
+        let mut writer = EncodingWriter::new(Vec::new(), WINDOWS_1252, policy);
+        // Split the input within a character to have it held back
+        for part in text.chunks(3) {
+            writer.write_all(part)?;
+        }
+        Ok(writer.inner)
+    }
+
+    #[test]
+    fn test_encoding_writer() {
+        assert_eq!(
+            encode("Bogotá,Usaquén €\n".as_bytes(), UnmappablePolicy::Error).unwrap(),
+            b"Bogot\xe1,Usaqu\xe9n \x80\n"
+        );
+        assert_eq!(
+            encode("Łódź\n".as_bytes(), UnmappablePolicy::Replace).unwrap(),
+            b"?\xf3d?\n"
+        );
+        assert!(encode("Łódź\n".as_bytes(), UnmappablePolicy::Error).is_err());
+        assert_eq!(
+            "LATIN1".parse::<OutputEncoding>().unwrap(),
+            OutputEncoding::Windows1252
+        );
+        assert!("utf16".parse::<OutputEncoding>().is_err());
+    }
+}
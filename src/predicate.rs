@@ -0,0 +1,138 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// Condition on a column value, parsed from `COLUMN OP VALUE` with OP one of
+/// `==`, `!=`, `>`, `>=`, `<`, `<=`. Values compare numerically when both sides are numbers
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Predicate {
+    Equal(String, String),
+    NotEqual(String, String),
+    GreaterThan(String, String),
+    GreaterOrEqual(String, String),
+    LessThan(String, String),
+    LessOrEqual(String, String),
+}
+
+/// How the `--filter` predicates combine
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum FilterLogic {
+    #[default]
+    And,
+    Or,
+}
+
+impl Predicate {
+    /// Column the predicate applies to
+    pub(crate) fn column(&self) -> &str {
+        match self {
+            Predicate::Equal(column, _)
+            | Predicate::NotEqual(column, _)
+            | Predicate::GreaterThan(column, _)
+            | Predicate::GreaterOrEqual(column, _)
+            | Predicate::LessThan(column, _)
+            | Predicate::LessOrEqual(column, _) => column,
+        }
+    }
+
+    /// Check a field against the predicate. Ordering a number against text is never true
+    pub(crate) fn matches(&self, field: &str) -> bool {
+        match self {
+            Predicate::Equal(_, value) => compare(field, value) == Some(Ordering::Equal),
+            Predicate::NotEqual(_, value) => compare(field, value) != Some(Ordering::Equal),
+            Predicate::GreaterThan(_, value) => compare(field, value) == Some(Ordering::Greater),
+            Predicate::GreaterOrEqual(_, value) => matches!(
+                compare(field, value),
+                Some(Ordering::Greater | Ordering::Equal)
+            ),
+            Predicate::LessThan(_, value) => compare(field, value) == Some(Ordering::Less),
+            Predicate::LessOrEqual(_, value) => matches!(
+                compare(field, value),
+                Some(Ordering::Less | Ordering::Equal)
+            ),
+        }
+    }
+}
+
+/// Compare numerically when both sides are numbers, as text when neither is, `None`
+/// when only one of them is a number
+pub(crate) fn compare(field: &str, value: &str) -> Option<Ordering> {
+    match (field.trim().parse::<f64>(), value.trim().parse::<f64>()) {
+        (Ok(field), Ok(value)) => Some(field.total_cmp(&value)),
+        (Err(_), Err(_)) => Some(field.cmp(value)),
+        _ => None,
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The leftmost operator splits the predicate, preferring the two characters ones
+        let (position, operator) = ["==", "!=", ">=", "<=", ">", "<"]
+            .iter()
+            .filter_map(|operator| s.find(operator).map(|position| (position, *operator)))
+            .min_by_key(|(position, operator)| (*position, std::cmp::Reverse(operator.len())))
+            .ok_or_else(|| format!("Invalid filter {}, expected COLUMN OP VALUE", s))?;
+        let column: String = s[..position].trim().to_string();
+        let value: String = s[position + operator.len()..].trim().to_string();
+        if column.is_empty() {
+            return Err(format!("Invalid filter {}, the column is empty", s));
+        }
+
+        Ok(match operator {
+            "==" => Predicate::Equal(column, value),
+            "!=" => Predicate::NotEqual(column, value),
+            ">=" => Predicate::GreaterOrEqual(column, value),
+            "<=" => Predicate::LessOrEqual(column, value),
+            ">" => Predicate::GreaterThan(column, value),
+            _ => Predicate::LessThan(column, value),
+        })
+    }
+}
+
+impl FromStr for FilterLogic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "and" => Ok(FilterLogic::And),
+            "or" => Ok(FilterLogic::Or),
+            _ => Err(format!("Invalid filter logic {}, expected and or or", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_predicate() {
+        assert_eq!(
+            Predicate::from_str("Population > 1000").unwrap(),
+            Predicate::GreaterThan(String::from("Population"), String::from("1000"))
+        );
+        assert_eq!(
+            Predicate::from_str("State != AK").unwrap(),
+            Predicate::NotEqual(String::from("State"), String::from("AK"))
+        );
+        assert_eq!(
+            Predicate::from_str("Population (2020)>=10").unwrap(),
+            Predicate::GreaterOrEqual(String::from("Population (2020)"), String::from("10"))
+        );
+        assert!(Predicate::from_str("Population").is_err());
+        assert!(Predicate::from_str("== AK").is_err());
+    }
+
+    #[test]
+    fn test_predicate_matches() {
+        let greater_than = Predicate::from_str("Population > 1000").unwrap();
+        let equal = Predicate::from_str("Population == 7610").unwrap();
+
+        assert!(greater_than.matches("7610"));
+        assert!(!greater_than.matches("999.5"));
+        assert!(!greater_than.matches(""));
+        assert!(equal.matches("7610.0"));
+        assert!(Predicate::from_str("State != AK").unwrap().matches("AL"));
+    }
+}
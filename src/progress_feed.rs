@@ -0,0 +1,91 @@
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// NDJSON progress lines appended to `--progress-to` after every chunk, for a UI to tail
+pub(crate) struct ProgressFeed {
+    file: File,
+    started_at: Instant,
+}
+
+impl ProgressFeed {
+    /// Open the feed for appending, the elapsed times counting from now
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        Ok(ProgressFeed {
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+            started_at: Instant::now(),
+        })
+    }
+
+    /// Append a line with the records split so far, the categories with a file, the
+    /// input bytes read and the elapsed milliseconds, flushed for the tailer to see it.
+    /// The line is written at once, so a tailer never reads part of it
+    pub(crate) fn report(&mut self, rows: usize, categories: usize, bytes: u64) -> io::Result<()> {
+        let progress = json!({
+            "rows": rows,
+            "categories": categories,
+            "bytes": bytes,
+            "elapsed_ms": self.started_at.elapsed().as_millis() as u64,
+        });
+        self.file.write_all(format!("{}\n", progress).as_bytes())?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_filtering::split_file_by_category;
+    use crate::delimiter::Delimiter;
+    use crate::record_context::RecordProcessingContext;
+    use crate::test_context::TestContext;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    /// Read the fields of a flat JSON object of numbers
+    fn parse_line(line: &str) -> Vec<(String, u64)> {
+        let progress: Value = serde_json::from_str(line).unwrap();
+        progress
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.as_u64().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_progress_feed() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/progress_feed"));
+        let feed_path = context.add_file(PathBuf::from("assets/tmp/progress.ndjson"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            chunk_size: 5,
+            progress_feed: Some(Arc::new(Mutex::new(
+                ProgressFeed::create(&feed_path).unwrap(),
+            ))),
+            ..Default::default()
+        };
+
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let feed: String = fs::read_to_string(&feed_path).unwrap();
+        assert!(feed.ends_with('\n'));
+
+        let lines: Vec<Vec<(String, u64)>> = feed.lines().map(parse_line).collect();
+        let keys: Vec<&str> = lines[0].iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["rows", "categories", "bytes", "elapsed_ms"]);
+        let rows: Vec<u64> = lines.iter().map(|line| line[0].1).collect();
+        assert_eq!(rows, [5, 10, 12]);
+        assert_eq!(lines[2][1].1, 4);
+    }
+}
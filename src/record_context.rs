@@ -1,21 +1,672 @@
+use crate::data_loading::{ReaderConfig, DEFAULT_READ_BUFFER};
 use crate::delimiter::Delimiter;
+use crate::file_following::FollowState;
+use crate::fixed_width::FixedWidth;
+use crate::json_expanding::JsonExpansion;
+use crate::key_expr::KeyExpr;
+use crate::output_backend::{LocalBackend, OutputBackend, OutputWriter};
+use crate::output_encoding::{OutputEncoding, UnmappablePolicy};
+use crate::predicate::{FilterLogic, Predicate};
+use crate::progress_feed::ProgressFeed;
+use crate::row_hashing::RowHashAlgorithm;
+use crate::run_manifest::RunManifest;
+use crate::schema_inferring::TypeSampler;
 use csv::StringRecord;
-use std::collections::HashMap;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::BufWriter;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+/// What to do with a record longer than `--max-record-bytes`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum OversizedRecordPolicy {
+    #[default]
+    Skip,
+    Error,
+}
+
+impl FromStr for OversizedRecordPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(OversizedRecordPolicy::Skip),
+            "error" => Ok(OversizedRecordPolicy::Error),
+            _ => Err(format!("Invalid policy {}, expected skip or error", s)),
+        }
+    }
+}
+
+/// What to do with a category that would escape the output directory, e.g. `../etc`, or
+/// doesn't match `--category-validate`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum InvalidCategoryPolicy {
+    Skip,
+    Sanitize,
+    /// Write the records to the `invalid` category instead
+    Invalid,
+    #[default]
+    Error,
+}
+
+impl FromStr for InvalidCategoryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(InvalidCategoryPolicy::Skip),
+            "sanitize" => Ok(InvalidCategoryPolicy::Sanitize),
+            "invalid" => Ok(InvalidCategoryPolicy::Invalid),
+            "error" => Ok(InvalidCategoryPolicy::Error),
+            _ => Err(format!(
+                "Invalid policy {}, expected skip, sanitize, invalid or error",
+                s
+            )),
+        }
+    }
+}
+
+/// Category of a split column value missing from `--category-map`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum UnmappedCategoryPolicy {
+    #[default]
+    Raw,
+    Null,
+}
+
+impl FromStr for UnmappedCategoryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(UnmappedCategoryPolicy::Raw),
+            "null" => Ok(UnmappedCategoryPolicy::Null),
+            _ => Err(format!("Invalid policy {}, expected raw or null", s)),
+        }
+    }
+}
+
+/// How `--column` is matched against the headers when no header is exactly it
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum ColumnMatch {
+    #[default]
+    Exact,
+    /// The header starts with the column
+    Prefix,
+    /// The column is a glob pattern, e.g. `Population*`
+    Glob,
+}
+
+impl FromStr for ColumnMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(ColumnMatch::Exact),
+            "prefix" => Ok(ColumnMatch::Prefix),
+            "glob" => Ok(ColumnMatch::Glob),
+            _ => Err(format!(
+                "Invalid column match {}, expected exact, prefix or glob",
+                s
+            )),
+        }
+    }
+}
+
+/// Rewrite of the category names from `--category-transform`, the written fields being
+/// kept as is
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum CategoryTransform {
+    Upper,
+    Lower,
+    /// Remove the leading and trailing whitespace
+    Trim,
+}
+
+impl CategoryTransform {
+    pub(crate) fn apply(self, category: &str) -> String {
+        match self {
+            CategoryTransform::Upper => category.to_uppercase(),
+            CategoryTransform::Lower => category.to_lowercase(),
+            CategoryTransform::Trim => category.trim().to_string(),
+        }
+    }
+}
+
+impl FromStr for CategoryTransform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upper" => Ok(CategoryTransform::Upper),
+            "lower" => Ok(CategoryTransform::Lower),
+            "trim" => Ok(CategoryTransform::Trim),
+            _ => Err(format!(
+                "Invalid category transform {}, expected upper, lower or trim",
+                s
+            )),
+        }
+    }
+}
+
+/// What happens to a category missing from `--categories-file`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum UnexpectedCategoryPolicy {
+    /// Stop the split, nothing more being written
+    #[default]
+    Error,
+    /// Write the records to the `other` category
+    Other,
+}
+
+impl FromStr for UnexpectedCategoryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(UnexpectedCategoryPolicy::Error),
+            "other" => Ok(UnexpectedCategoryPolicy::Other),
+            _ => Err(format!("Invalid policy {}, expected error or other", s)),
+        }
+    }
+}
+
+/// How the records of a chunk are categorized in parallel, from `--concurrency-strategy`.
+/// Every strategy writes the same files
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum ConcurrencyStrategy {
+    /// Each rayon thread folds into its own map, the maps being merged at the end
+    #[default]
+    FoldReduce,
+    /// The threads insert into a single sharded map
+    Dashmap,
+    /// The threads send the records to a channel drained by a thread of its own
+    Channel,
+}
+
+impl FromStr for ConcurrencyStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fold-reduce" => Ok(ConcurrencyStrategy::FoldReduce),
+            "dashmap" => Ok(ConcurrencyStrategy::Dashmap),
+            "channel" => Ok(ConcurrencyStrategy::Channel),
+            _ => Err(format!(
+                "Invalid concurrency strategy {}, expected fold-reduce, dashmap or channel",
+                s
+            )),
+        }
+    }
+}
+
+/// When the headers are written to a category file, from `--write-header`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum WriteHeader {
+    Always,
+    Never,
+    /// Only to the files the run creates
+    #[default]
+    IfNew,
+}
+
+impl FromStr for WriteHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(WriteHeader::Always),
+            "never" => Ok(WriteHeader::Never),
+            "if-new" => Ok(WriteHeader::IfNew),
+            _ => Err(format!(
+                "Invalid header policy {}, expected always, never or if-new",
+                s
+            )),
+        }
+    }
+}
+
+/// Where `--count-trailer` writes the records written to each category file
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum CountTrailer {
+    /// A final `#ROWS=<count>` line in the category file
+    Comment,
+    /// A `<category>.count` file next to the category file
+    Sidecar,
+}
+
+impl FromStr for CountTrailer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "comment" => Ok(CountTrailer::Comment),
+            "sidecar" => Ok(CountTrailer::Sidecar),
+            _ => Err(format!(
+                "Invalid count trailer {}, expected comment or sidecar",
+                s
+            )),
+        }
+    }
+}
+
+/// Column to sort the records of each category by, parsed from `COLUMN[:asc|desc]`
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct SortKey {
+    pub(crate) column: String,
+    pub(crate) descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, descending) = match s.rsplit_once(':') {
+            Some((column, "asc")) => (column, false),
+            Some((column, "desc")) => (column, true),
+            _ => (s, false),
+        };
+        if column.is_empty() {
+            return Err(String::from("The sort column can't be empty"));
+        }
+        Ok(SortKey {
+            column: column.to_string(),
+            descending,
+        })
+    }
+}
+
+/// 1-based inclusive record range parsed from `START:END`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) struct RowRange {
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+}
+
+impl FromStr for RowRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid row range {}, expected START:END", s);
+        let (start, end) = s.split_once(':').ok_or_else(invalid)?;
+        let start: u64 = start.parse().map_err(|_| invalid())?;
+        let end: u64 = end.parse().map_err(|_| invalid())?;
+        if start == 0 || end < start {
+            return Err(format!(
+                "Invalid row range {}, rows start at 1 and END can't be before START",
+                s
+            ));
+        }
+        Ok(RowRange { start, end })
+    }
+}
+
+/// When each open category writer was last written to, and the files of the writers
+/// closed to free file handles, for `--retry-open`
+#[derive(Debug, Default)]
+pub(crate) struct WriterUsage {
+    clock: u64,
+    last_used: HashMap<String, u64>,
+    closed: HashMap<String, PathBuf>,
+}
+
+impl WriterUsage {
+    /// Mark the writer of a category as just written to
+    pub(crate) fn touch(&mut self, category: &str) {
+        self.clock += 1;
+        match self.last_used.get_mut(category) {
+            Some(last_used) => *last_used = self.clock,
+            None => {
+                self.last_used.insert(category.to_string(), self.clock);
+            }
+        }
+    }
+
+    /// The `count` open categories written to the longest time ago
+    pub(crate) fn least_recently_used<'a>(
+        &self,
+        open: impl Iterator<Item = &'a String>,
+        count: usize,
+    ) -> Vec<String> {
+        let mut open: Vec<(u64, &String)> = open
+            .map(|category| (self.last_used.get(category).copied().unwrap_or(0), category))
+            .collect();
+        open.sort();
+        open.into_iter()
+            .take(count)
+            .map(|(_, category)| category.clone())
+            .collect()
+    }
+
+    /// Record that the writer of a category was closed, to reopen its file later
+    pub(crate) fn close(&mut self, category: &str, file_path: PathBuf) {
+        self.last_used.remove(category);
+        self.closed.insert(category.to_string(), file_path);
+    }
+
+    /// File of a closed writer, which is reopened
+    pub(crate) fn reopen(&mut self, category: &str) -> Option<PathBuf> {
+        self.closed.remove(category)
+    }
+
+    /// Whether the writer of a category was closed
+    pub(crate) fn is_closed(&self, category: &str) -> bool {
+        self.closed.contains_key(category)
+    }
+}
+
+/// Records categorized and categories seen until `--freeze-categories-after`, a category
+/// seen for the first time afterwards being new
+#[derive(Debug, Default)]
+pub(crate) struct CategoryFreeze {
+    rows: usize,
+    seen: HashSet<String>,
+}
+
+impl CategoryFreeze {
+    /// Records categorized so far, counted until the freeze
+    pub(crate) fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Whether the categories are locked after `freeze_after` records
+    pub(crate) fn is_frozen(&self, freeze_after: usize) -> bool {
+        self.rows >= freeze_after
+    }
+
+    /// Whether a category only appears once the categories are locked
+    pub(crate) fn is_new(&self, category: &str, freeze_after: usize) -> bool {
+        self.is_frozen(freeze_after) && !self.seen.contains(category)
+    }
+
+    /// Record the categories of a chunk of `rows` records read before the freeze
+    pub(crate) fn add_chunk<'a>(
+        &mut self,
+        rows: usize,
+        categories: impl Iterator<Item = &'a String>,
+    ) {
+        self.rows += rows;
+        self.seen.extend(categories.cloned());
+    }
+
+    /// Categories seen before the freeze
+    pub(crate) fn categories(&self) -> usize {
+        self.seen.len()
+    }
+}
+
+/// Normalization of the output headers from `--header-normalize`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum HeaderCase {
+    /// Lowercase words joined by `_`, e.g. `First Name` becomes `first_name`
+    Snake,
+    /// Lowercase words joined by `-`
+    Kebab,
+    Lower,
+    Upper,
+}
+
+impl HeaderCase {
+    pub(crate) fn normalize(self, header: &str) -> String {
+        match self {
+            HeaderCase::Snake => header_words(header).join("_"),
+            HeaderCase::Kebab => header_words(header).join("-"),
+            HeaderCase::Lower => header.to_lowercase(),
+            HeaderCase::Upper => header.to_uppercase(),
+        }
+    }
+}
+
+/// Lowercase words of a header, split on anything but letters and digits and where a
+/// lowercase letter is followed by an uppercase one, as in `firstName`
+fn header_words(header: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word: String = String::new();
+    let mut previous_lowercase: bool = false;
+    for c in header.chars() {
+        if !c.is_alphanumeric() {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+            previous_lowercase = false;
+            continue;
+        }
+        if c.is_uppercase() && previous_lowercase {
+            words.push(std::mem::take(&mut word));
+        }
+        previous_lowercase = c.is_lowercase() || c.is_ascii_digit();
+        word.extend(c.to_lowercase());
+    }
+    words.extend((!word.is_empty()).then_some(word));
+    words
+}
+
+impl FromStr for HeaderCase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "snake" => Ok(HeaderCase::Snake),
+            "kebab" => Ok(HeaderCase::Kebab),
+            "lower" => Ok(HeaderCase::Lower),
+            "upper" => Ok(HeaderCase::Upper),
+            _ => Err(format!(
+                "Invalid header normalization {}, expected snake, kebab, lower or upper",
+                s
+            )),
+        }
+    }
+}
+
+/// Callback rewriting each output header
+pub(crate) type HeaderTransform = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Indexes of the input columns written for a category and their output headers
+pub(crate) type CategoryProjection = (Vec<usize>, StringRecord);
 
 #[derive(Clone)]
 pub(crate) struct RecordProcessingContext {
     pub(crate) headers: StringRecord,
     pub(crate) output_dir: PathBuf,
+    /// Storage of `output_dir`, picked from the scheme of `--dir`
+    pub(crate) output_backend: Arc<dyn OutputBackend>,
     pub(crate) create_directory: bool,
+    /// Write each flat category file under a directory named by its initial, from
+    /// `--shard-by-initial`
+    pub(crate) shard_by_initial: bool,
+    /// Create `output_dir` and its parents before the split, from `--force-create-dir`
+    pub(crate) force_create_dir: bool,
+    /// Directory of one of the independent splits of a single read, `by_<column>` or
+    /// `by_<group>`, naming its categories in the summary
+    pub(crate) split_dir: Option<String>,
     pub(crate) file_name: String,
     pub(crate) delimiter: u8,
-    pub(crate) split_column_idx: usize,
-    pub(crate) writers: Arc<Mutex<HashMap<String, csv::Writer<BufWriter<File>>>>>,
+    /// Index of the split column, none with `--key-expr`
+    pub(crate) split_column_idx: Option<usize>,
+    /// Matching of the split column name, from `--column-match`
+    pub(crate) column_match: ColumnMatch,
+    /// Expression computing the category instead of the split column, from `--key-expr`
+    pub(crate) key_expr: Option<KeyExpr>,
+    /// Pattern matched on the split column value and the named group of it giving the
+    /// category, one split per group of `--key-regex`. A value it doesn't match is the
+    /// null category
+    pub(crate) key_group: Option<(Regex, String)>,
+    pub(crate) writers: Arc<Mutex<HashMap<String, csv::Writer<OutputWriter>>>>,
     pub(crate) header_indexes: Vec<usize>,
+    pub(crate) chunk_size: usize,
+    pub(crate) dedupe: bool,
+    /// Columns identifying a duplicate, all the written fields when empty
+    pub(crate) dedupe_key: Vec<String>,
+    pub(crate) dedupe_key_indexes: Option<Vec<usize>>,
+    /// Hashes of the records already written to each category
+    pub(crate) seen_records: Arc<Mutex<HashMap<String, HashSet<u64>>>>,
+    /// Drop the duplicates across all the categories instead, from `--dedupe-global`
+    pub(crate) dedupe_global: bool,
+    /// Hashes of the records already kept in any category with `--dedupe-global`
+    pub(crate) global_seen_records: Arc<Mutex<HashSet<u64>>>,
+    pub(crate) header_transform: Option<HeaderTransform>,
+    /// Character splitting a category into nested directories when creating directories
+    pub(crate) category_path_separator: Option<char>,
+    /// Characters of the category file names beyond which they are truncated, from
+    /// `--max-name-length`
+    pub(crate) max_name_length: Option<usize>,
+    pub(crate) max_record_bytes: Option<usize>,
+    pub(crate) oversized_record_policy: OversizedRecordPolicy,
+    pub(crate) sort_by: Option<SortKey>,
+    pub(crate) sort_column_idx: Option<usize>,
+    /// Records held back until the end of the file when sorting within categories
+    pub(crate) category_buffers: Arc<Mutex<HashMap<String, Vec<StringRecord>>>>,
+    /// Number of buffered records of a category above which they are spilled to disk
+    pub(crate) spill_threshold: Option<usize>,
+    /// Sorted segment files spilled for each category, merged at the end of the file
+    pub(crate) spilled_segments: Arc<Mutex<HashMap<String, Vec<PathBuf>>>>,
+    pub(crate) ascii_file_names: bool,
+    /// Category owning each transliterated file name, to detect collisions
+    pub(crate) file_categories: Arc<Mutex<HashMap<String, String>>>,
+    /// Predicates a record must satisfy to be written
+    pub(crate) filters: Vec<Predicate>,
+    pub(crate) filter_logic: FilterLogic,
+    /// Index of the column of each filter within the input records
+    pub(crate) filter_column_indexes: Vec<usize>,
+    /// Category of the records with an empty, missing or sentinel split value
+    pub(crate) null_category: String,
+    /// Drop the records of the null category instead, from `--null-category skip`
+    pub(crate) skip_null_category: bool,
+    /// Split values treated as empty, e.g. `NA` or `NULL`
+    pub(crate) null_values: Vec<String>,
+    /// Write an empty `_SUCCESS` file to the output directory once the split is flushed
+    pub(crate) success_marker: bool,
+    /// Remove one layer of matching quotes surrounding each written field
+    pub(crate) strip_quotes: bool,
+    /// Path and headers of the first input split with these writers, which the
+    /// following inputs must match
+    pub(crate) first_headers: Arc<Mutex<Option<(String, StringRecord)>>>,
+    /// Combined file every written record is also written to, from `--tee`
+    pub(crate) tee_path: Option<PathBuf>,
+    /// Writer of `tee_path`, opened with the headers of the first input
+    pub(crate) tee_writer: Arc<Mutex<Option<csv::Writer<File>>>>,
+    /// Times an open failing for lack of file handles is retried, from `--retry-open`
+    pub(crate) retry_open: usize,
+    /// Last use of the open writers and files of the closed ones, for `--retry-open`
+    pub(crate) writer_usage: Arc<Mutex<WriterUsage>>,
+    /// Records after which the categories are locked, from `--freeze-categories-after`
+    pub(crate) freeze_categories_after: Option<usize>,
+    /// What happens to a category new after the freeze, from `--frozen-category`
+    pub(crate) frozen_category_policy: UnexpectedCategoryPolicy,
+    /// Categories seen until `--freeze-categories-after`, telling the new ones after it
+    pub(crate) category_freeze: Arc<RwLock<CategoryFreeze>>,
+    /// Only warn about inputs whose headers differ from the first one
+    pub(crate) allow_schema_drift: bool,
+    /// Send the categorized records to a single thread owning the writers instead of
+    /// locking them from the processing threads
+    pub(crate) writer_thread: bool,
+    /// Chunks of categorized records queued for the writer thread before the reading
+    /// thread blocks
+    pub(crate) channel_capacity: usize,
+    /// Instant after which no more chunks are read, from `--max-runtime`
+    pub(crate) deadline: Option<Instant>,
+    /// Only records within this range are split, from `--rows`
+    pub(crate) row_range: Option<RowRange>,
+    /// What happens to a category whose file would escape the output directory or that
+    /// doesn't match `category_pattern`, from `--on-invalid-category`
+    pub(crate) invalid_category_policy: InvalidCategoryPolicy,
+    /// Pattern a derived category must match to be valid, from `--category-validate`
+    pub(crate) category_pattern: Option<Regex>,
+    /// Categories the split named itself, the null category, `other` and `invalid`,
+    /// which `--category-validate` doesn't apply to
+    pub(crate) assigned_categories: Arc<RwLock<HashSet<String>>>,
+    /// Skipped records after which the run stops, from `--max-errors`
+    pub(crate) max_errors: Option<usize>,
+    /// Write the single output column without quoting, from `--delimiter-out none`
+    pub(crate) raw_output: bool,
+    /// Columns left out of the split files, from `--drop`
+    pub(crate) dropped_columns: Vec<String>,
+    /// Categories whose files quote every field, from `--always-quote-for`
+    pub(crate) always_quoted_categories: Vec<String>,
+    /// Write the empty fields as `""` to tell them from missing values, from `--quote-empty`
+    pub(crate) quote_empty: bool,
+    /// Trim the whitespace around the input headers and fields, from `--trim`
+    pub(crate) trim: bool,
+    /// Read the quotes of the input as any other character, from `--no-quoting`
+    pub(crate) no_quoting: bool,
+    /// Bytes buffered from the input, from `--read-buffer`
+    pub(crate) read_buffer_capacity: usize,
+    /// Columns of a fixed-width input, from `--fixed-width`
+    pub(crate) fixed_width: Option<FixedWidth>,
+    /// Reading position of a followed input, from `--follow`
+    pub(crate) follow: Option<Arc<FollowState>>,
+    /// Strip the carriage returns ending the last field, from `--normalize-line-endings`
+    pub(crate) normalize_line_endings: bool,
+    /// Drop blank lines instead of failing on them, from `--skip-empty-lines`
+    pub(crate) skip_empty_lines: bool,
+    /// Footer records dropped from the end of the input, from `--trailer-rows`
+    pub(crate) trailer_rows: usize,
+    /// When the headers are written to a category file, from `--write-header`
+    pub(crate) write_header: WriteHeader,
+    /// Start the created files with a `sep=<delimiter>` line for Excel, from `--excel-sep-hint`
+    pub(crate) excel_sep_hint: bool,
+    /// Encoding of the split files, from `--output-encoding`
+    pub(crate) output_encoding: OutputEncoding,
+    /// What happens to a character the `output_encoding` can't represent, from
+    /// `--on-unmappable-char`
+    pub(crate) unmappable_policy: UnmappablePolicy,
+    /// Name of the column holding a hash of the other written fields of each record, from
+    /// `--row-hash-column`
+    pub(crate) row_hash_column: Option<String>,
+    /// Hash of the `row_hash_column`, from `--row-hash-algorithm`
+    pub(crate) row_hash_algorithm: RowHashAlgorithm,
+    /// Id of the run appended to every written record as a `run_id` column, from
+    /// `--append-run-id-column`
+    pub(crate) run_id_column: Option<String>,
+    /// Write the records count of each category file once split, from `--count-trailer`
+    pub(crate) count_trailer: Option<CountTrailer>,
+    /// Character the input lines left out start with and the `--count-trailer` comment
+    /// line with, from `--comment-char`
+    pub(crate) comment_char: Option<u8>,
+    /// Write a `.schema.json` file next to each category file, from `--emit-schema`
+    pub(crate) emit_schema: bool,
+    /// Column types of the first records written to each category, for `emit_schema`
+    pub(crate) category_samplers: Arc<Mutex<HashMap<String, TypeSampler>>>,
+    /// Output file and records written of each category, for the count trailers
+    pub(crate) category_rows: Arc<Mutex<HashMap<String, (PathBuf, usize)>>>,
+    /// Categorize and write the records one after the other on the reading thread
+    pub(crate) single_threaded: bool,
+    /// The input is sorted by the split column, each category being written as one run
+    /// and its writer closed when the key changes, from `--sorted-input`
+    pub(crate) sorted_input: bool,
+    /// Warn when a category of `sorted_input` comes back after its writer was closed
+    pub(crate) check_sorted: bool,
+    /// How a chunk is categorized across the rayon threads, from `--concurrency-strategy`
+    pub(crate) concurrency_strategy: ConcurrencyStrategy,
+    /// Feed the progress is appended to after every chunk, from `--progress-to`
+    pub(crate) progress_feed: Option<Arc<Mutex<ProgressFeed>>>,
+    /// Manifest of the inputs split so far and their category files, from `--manifest`
+    pub(crate) manifest: Option<Arc<Mutex<RunManifest>>>,
+    /// JSON object column expanded into a column per key, from `--expand-json`
+    pub(crate) expand_json: Option<String>,
+    /// Keys of `expand_json` found in the input
+    pub(crate) json_expansion: Option<Arc<JsonExpansion>>,
+    /// Categories of some split column values, from `--alias`
+    pub(crate) category_aliases: Arc<HashMap<String, String>>,
+    /// Names of the categories by split column value, from `--category-map`
+    pub(crate) category_map: Arc<HashMap<String, String>>,
+    /// Category of the values missing from the `category_map`, from `--unmapped-category`
+    pub(crate) unmapped_category_policy: UnmappedCategoryPolicy,
+    /// Rewrites of the mapped categories, applied in order
+    pub(crate) category_transforms: Vec<CategoryTransform>,
+    /// Categories whose files are created up front, from `--categories-file`
+    pub(crate) expected_categories: Arc<HashSet<String>>,
+    /// What happens to a category missing from them, from `--unexpected-category`
+    pub(crate) unexpected_category_policy: UnexpectedCategoryPolicy,
+    /// Headers every input must have, from `--expect-headers`
+    pub(crate) expected_headers: Vec<String>,
+    /// Only require the expected headers to be present, in any order
+    pub(crate) expect_headers_subset: bool,
+    /// Columns written for some categories instead of the default ones, from `--header-map`
+    pub(crate) header_map: Arc<HashMap<String, Vec<String>>>,
+    /// `header_map` resolved against the headers of the input
+    pub(crate) category_projections: Arc<HashMap<String, CategoryProjection>>,
 }
 
 impl Default for RecordProcessingContext {
@@ -23,12 +674,161 @@ impl Default for RecordProcessingContext {
         RecordProcessingContext {
             headers: StringRecord::new(),
             output_dir: PathBuf::new(),
+            output_backend: Arc::new(LocalBackend),
             create_directory: false,
+            shard_by_initial: false,
+            force_create_dir: false,
+            split_dir: None,
             file_name: String::new(),
             delimiter: Delimiter::PIPE,
-            split_column_idx: 0,
+            split_column_idx: None,
+            column_match: ColumnMatch::Exact,
+            key_expr: None,
+            key_group: None,
             writers: Arc::new(Mutex::new(HashMap::new())),
             header_indexes: Vec::new(),
+            chunk_size: 100_000,
+            dedupe: false,
+            dedupe_key: Vec::new(),
+            dedupe_key_indexes: None,
+            seen_records: Arc::new(Mutex::new(HashMap::new())),
+            dedupe_global: false,
+            global_seen_records: Arc::new(Mutex::new(HashSet::new())),
+            header_transform: None,
+            category_path_separator: None,
+            max_name_length: None,
+            max_record_bytes: None,
+            oversized_record_policy: OversizedRecordPolicy::Skip,
+            sort_by: None,
+            sort_column_idx: None,
+            category_buffers: Arc::new(Mutex::new(HashMap::new())),
+            spill_threshold: None,
+            spilled_segments: Arc::new(Mutex::new(HashMap::new())),
+            ascii_file_names: false,
+            file_categories: Arc::new(Mutex::new(HashMap::new())),
+            filters: Vec::new(),
+            filter_logic: FilterLogic::And,
+            filter_column_indexes: Vec::new(),
+            null_category: String::from("unknown"),
+            skip_null_category: false,
+            null_values: Vec::new(),
+            success_marker: false,
+            strip_quotes: false,
+            first_headers: Arc::new(Mutex::new(None)),
+            tee_path: None,
+            tee_writer: Arc::new(Mutex::new(None)),
+            retry_open: 0,
+            writer_usage: Arc::default(),
+            freeze_categories_after: None,
+            frozen_category_policy: UnexpectedCategoryPolicy::Other,
+            category_freeze: Arc::default(),
+            allow_schema_drift: false,
+            writer_thread: false,
+            channel_capacity: 4,
+            deadline: None,
+            row_range: None,
+            invalid_category_policy: InvalidCategoryPolicy::Error,
+            category_pattern: None,
+            assigned_categories: Arc::default(),
+            max_errors: None,
+            raw_output: false,
+            dropped_columns: Vec::new(),
+            always_quoted_categories: Vec::new(),
+            quote_empty: false,
+            trim: false,
+            no_quoting: false,
+            read_buffer_capacity: DEFAULT_READ_BUFFER,
+            fixed_width: None,
+            follow: None,
+            normalize_line_endings: false,
+            skip_empty_lines: false,
+            trailer_rows: 0,
+            write_header: WriteHeader::IfNew,
+            excel_sep_hint: false,
+            output_encoding: OutputEncoding::Utf8,
+            unmappable_policy: UnmappablePolicy::Replace,
+            row_hash_column: None,
+            row_hash_algorithm: RowHashAlgorithm::Xxh3,
+            run_id_column: None,
+            count_trailer: None,
+            comment_char: None,
+            category_rows: Arc::new(Mutex::new(HashMap::new())),
+            emit_schema: false,
+            category_samplers: Arc::new(Mutex::new(HashMap::new())),
+            single_threaded: false,
+            sorted_input: false,
+            check_sorted: false,
+            concurrency_strategy: ConcurrencyStrategy::FoldReduce,
+            progress_feed: None,
+            manifest: None,
+            expand_json: None,
+            json_expansion: None,
+            category_aliases: Arc::default(),
+            category_map: Arc::default(),
+            unmapped_category_policy: UnmappedCategoryPolicy::Raw,
+            category_transforms: Vec::new(),
+            expected_categories: Arc::default(),
+            unexpected_category_policy: UnexpectedCategoryPolicy::Error,
+            expected_headers: Vec::new(),
+            expect_headers_subset: false,
+            header_map: Arc::default(),
+            category_projections: Arc::default(),
         }
     }
 }
+
+impl RecordProcessingContext {
+    /// Read records of any length, as blank lines and footers have other lengths than
+    /// the headers. The data records are checked while splitting
+    pub(crate) fn reads_flexibly(&self) -> bool {
+        self.skip_empty_lines || self.trailer_rows > 0
+    }
+
+    /// Settings of the reader of an input split with this context
+    pub(crate) fn reader_config(&self, delimiter: &Delimiter) -> ReaderConfig {
+        ReaderConfig {
+            flexible: self.reads_flexibly(),
+            trim: self.trim,
+            quoting: !self.no_quoting,
+            buffer_capacity: self.read_buffer_capacity,
+            fixed_width: self.fixed_width.clone(),
+            follow: self.follow.clone(),
+            comment: self.comment_char,
+            ..ReaderConfig::new(delimiter)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_row_range() {
+        assert_eq!(
+            RowRange::from_str("1000000:1000100").unwrap(),
+            RowRange {
+                start: 1_000_000,
+                end: 1_000_100
+            }
+        );
+        assert!(RowRange::from_str("0:10").is_err());
+        assert!(RowRange::from_str("10:9").is_err());
+        assert!(RowRange::from_str("10").is_err());
+    }
+
+    #[test]
+    fn test_header_case() {
+        assert_eq!(HeaderCase::Snake.normalize("First Name"), "first_name");
+        assert_eq!(
+            HeaderCase::Snake.normalize("  Population (2020) "),
+            "population_2020"
+        );
+        assert_eq!(HeaderCase::Snake.normalize("zipCode"), "zip_code");
+        assert_eq!(
+            HeaderCase::Kebab.normalize("E-mail Address"),
+            "e-mail-address"
+        );
+        assert_eq!(HeaderCase::Upper.normalize("First Name"), "FIRST NAME");
+    }
+}
@@ -0,0 +1,81 @@
+use csv::StringRecord;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use xxhash_rust::xxh3::Xxh3;
+
+/// Hash of the `--row-hash-column` values
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum RowHashAlgorithm {
+    /// 64 bit XXH3, fast but not meant to resist tampering
+    #[default]
+    Xxh3,
+    Sha256,
+}
+
+impl FromStr for RowHashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xxh3" => Ok(RowHashAlgorithm::Xxh3),
+            "sha256" => Ok(RowHashAlgorithm::Sha256),
+            _ => Err(format!(
+                "Invalid row hash algorithm {}, expected xxh3 or sha256",
+                s
+            )),
+        }
+    }
+}
+
+/// Hash the fields of a record as lowercase hex. Each field is hashed after its length
+/// as 8 little endian bytes, so the hash is the same on every platform and moving a
+/// character from a field to the next changes it
+pub(crate) fn hash_row(record: &StringRecord, algorithm: RowHashAlgorithm) -> String {
+    match algorithm {
+        RowHashAlgorithm::Xxh3 => {
+            let mut hasher: Xxh3 = Xxh3::new();
+            for field in record.iter() {
+                hasher.update(&(field.len() as u64).to_le_bytes());
+                hasher.update(field.as_bytes());
+            }
+            format!("{:016x}", hasher.digest())
+        }
+        RowHashAlgorithm::Sha256 => {
+            let mut hasher: Sha256 = Sha256::new();
+            for field in record.iter() {
+                hasher.update((field.len() as u64).to_le_bytes());
+                hasher.update(field.as_bytes());
+            }
+            hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_row() {
+        let kenai = StringRecord::from(vec!["Kenai", "7610"]);
+        let selma = StringRecord::from(vec!["Selma", "18980"]);
+        let shifted = StringRecord::from(vec!["Kenai7", "610"]);
+
+        for algorithm in [RowHashAlgorithm::Xxh3, RowHashAlgorithm::Sha256] {
+            let hash: String = hash_row(&kenai, algorithm);
+            assert_eq!(hash, hash_row(&kenai.clone(), algorithm));
+            assert_ne!(hash, hash_row(&selma, algorithm));
+            assert_ne!(hash, hash_row(&shifted, algorithm));
+        }
+        assert_eq!(hash_row(&kenai, RowHashAlgorithm::Xxh3).len(), 16);
+        // Pinned so a change of the hashed bytes shows
+        assert_eq!(
+            hash_row(&kenai, RowHashAlgorithm::Sha256),
+            "51ff66687b5115c88bd828c55eb164134509f885f86fa21b1a136ab90a68a812"
+        );
+    }
+}
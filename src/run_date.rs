@@ -0,0 +1,132 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Date of a run, used to resolve the `%Y`, `%m` and `%d` placeholders of `--dir`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) struct RunDate {
+    year: i64,
+    month: u32,
+    day: u32,
+}
+
+impl RunDate {
+    /// Current date in UTC
+    pub(crate) fn today() -> Self {
+        let seconds: u64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        RunDate::from_days(seconds as i64 / 86_400)
+    }
+
+    /// Convert days since 1970-01-01 to a civil date
+    fn from_days(days: i64) -> Self {
+        let days: i64 = days + 719_468;
+        let era: i64 = days.div_euclid(146_097);
+        let day_of_era: i64 = days.rem_euclid(146_097);
+        let year_of_era: i64 =
+            (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year: i64 =
+            day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month: i64 = (5 * day_of_year + 2) / 153;
+        let day: u32 = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month: u32 = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year: i64 = year_of_era + era * 400 + i64::from(month <= 2);
+        RunDate { year, month, day }
+    }
+
+    /// Replace `%Y`, `%m` and `%d` with the date and `%%` with `%`, leaving any other
+    /// text untouched
+    pub(crate) fn expand(&self, template: &str) -> String {
+        let mut expanded: String = String::with_capacity(template.len());
+        let mut chars = template.chars();
+        while let Some(current) = chars.next() {
+            if current != '%' {
+                expanded.push(current);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => expanded.push_str(&format!("{:04}", self.year)),
+                Some('m') => expanded.push_str(&format!("{:02}", self.month)),
+                Some('d') => expanded.push_str(&format!("{:02}", self.day)),
+                Some('%') => expanded.push('%'),
+                Some(other) => {
+                    expanded.push('%');
+                    expanded.push(other);
+                }
+                None => expanded.push('%'),
+            }
+        }
+        expanded
+    }
+}
+
+/// Number of days of a month, February depending on leap years
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl fmt::Display for RunDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for RunDate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid date {}, expected YYYY-MM-DD", s);
+        let mut parts = s.splitn(3, '-');
+        let mut next_part = || -> Result<&str, String> { parts.next().ok_or_else(invalid) };
+        let year: i64 = next_part()?.parse().map_err(|_| invalid())?;
+        let month: u32 = next_part()?.parse().map_err(|_| invalid())?;
+        let day: u32 = next_part()?.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err(invalid());
+        }
+        Ok(RunDate { year, month, day })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let run_date = RunDate::from_str("2024-06-01").unwrap();
+
+        assert_eq!(run_date.expand("out/%Y-%m-%d"), "out/2024-06-01");
+        assert_eq!(run_date.expand("out/%Y/%m/%d/"), "out/2024/06/01/");
+        assert_eq!(run_date.expand("out/100%%/%H"), "out/100%/%H");
+        assert_eq!(run_date.expand("out"), "out");
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            RunDate::from_str("2024-02-29").unwrap().to_string(),
+            "2024-02-29"
+        );
+        assert!(RunDate::from_str("2023-02-29").is_err());
+        assert!(RunDate::from_str("2024-13-01").is_err());
+        assert!(RunDate::from_str("2024-06").is_err());
+    }
+
+    #[test]
+    fn test_from_days() {
+        assert_eq!(RunDate::from_days(0).to_string(), "1970-01-01");
+        assert_eq!(RunDate::from_days(19_875).to_string(), "2024-06-01");
+        assert_eq!(RunDate::from_days(-1).to_string(), "1969-12-31");
+    }
+}
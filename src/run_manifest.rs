@@ -0,0 +1,130 @@
+use crate::split_summary::SplitSummary;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Inputs split so far in a run and the records written to each category file, for
+/// `--manifest`. The file is rewritten after every input, so a run stopping halfway
+/// leaves the manifest of the inputs it finished
+#[derive(Debug)]
+pub(crate) struct RunManifest {
+    path: PathBuf,
+    run_id: String,
+    /// Path and records of each split input, in the order they finished
+    inputs: Vec<(String, usize)>,
+    /// File and records written across the inputs of each category
+    files: BTreeMap<String, (String, usize)>,
+}
+
+impl RunManifest {
+    pub(crate) fn new(path: &Path, run_id: &str) -> Self {
+        RunManifest {
+            path: path.to_path_buf(),
+            run_id: run_id.to_string(),
+            inputs: Vec::new(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Add the records an input wrote to each category, the files being those of
+    /// `category_files`, and rewrite the manifest
+    pub(crate) fn record(
+        &mut self,
+        input: &Path,
+        summary: &SplitSummary,
+        category_files: &HashMap<String, (PathBuf, usize)>,
+    ) -> io::Result<()> {
+        self.inputs
+            .push((input.display().to_string(), summary.rows_processed));
+        for (category, &rows) in &summary.category_rows {
+            let file: &mut (String, usize) = self.files.entry(category.clone()).or_default();
+            if let Some((file_path, _)) = category_files.get(category) {
+                file.0 = file_path.display().to_string();
+            }
+            file.1 += rows;
+        }
+        self.write()
+    }
+
+    /// Format the manifest as a JSON object with the `run_id`, the `inputs` and their
+    /// `rows`, the `rows` of all of them and the `files` by category
+    pub(crate) fn to_json(&self) -> String {
+        let inputs: Vec<Value> = self
+            .inputs
+            .iter()
+            .map(|(path, rows)| json!({"path": path, "rows": rows}))
+            .collect();
+        let files: Map<String, Value> = self
+            .files
+            .iter()
+            .map(|(category, (path, rows))| (category.clone(), json!({"path": path, "rows": rows})))
+            .collect();
+        let manifest: Value = json!({
+            "run_id": self.run_id,
+            "inputs": inputs,
+            "rows": self.inputs.iter().map(|(_, rows)| rows).sum::<usize>(),
+            "files": files,
+        });
+        format!("{:#}\n", manifest)
+    }
+
+    /// Replace the manifest through a temporary file renamed over it, so a reader never
+    /// sees it half written
+    fn write(&self) -> io::Result<()> {
+        let temp_path: PathBuf = self.path.with_extension("tmp");
+        fs::write(&temp_path, self.to_json())?;
+        fs::rename(&temp_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_filtering::split_file_by_category;
+    use crate::delimiter::Delimiter;
+    use crate::record_context::RecordProcessingContext;
+    use crate::test_context::TestContext;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_run_manifest() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/run_manifest"));
+        let manifest_path = context.add_file(PathBuf::from("assets/tmp/run_manifest.json"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            manifest: Some(Arc::new(Mutex::new(RunManifest::new(
+                &manifest_path,
+                "nightly-42",
+            )))),
+            ..Default::default()
+        };
+
+        for input in ["assets/glob_city/part_1.csv", "assets/glob_city/part_2.csv"] {
+            split_file_by_category(Path::new(input), "State", &Delimiter::Comma, &split_context)
+                .unwrap();
+        }
+        let manifest: Value =
+            serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+
+        assert_eq!(manifest["run_id"], "nightly-42");
+        assert_eq!(
+            manifest["inputs"],
+            json!([
+                {"path": "assets/glob_city/part_1.csv", "rows": 3},
+                {"path": "assets/glob_city/part_2.csv", "rows": 2}
+            ])
+        );
+        assert_eq!(manifest["rows"], 5);
+        assert_eq!(
+            manifest["files"],
+            json!({
+                "AK": {"path": "assets/tmp/run_manifest/AK.csv", "rows": 2},
+                "AL": {"path": "assets/tmp/run_manifest/AL.csv", "rows": 2},
+                "CA": {"path": "assets/tmp/run_manifest/CA.csv", "rows": 1}
+            })
+        );
+    }
+}
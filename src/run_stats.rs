@@ -0,0 +1,124 @@
+use crate::app_error::AppError;
+use crate::split_summary::SplitSummary;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Metrics of a run written to `--stats-json`, describing how the split went rather
+/// than the files it wrote
+#[derive(Debug, PartialEq)]
+pub(crate) struct RunStats {
+    run_id: String,
+    total_seconds: f64,
+    read_seconds: f64,
+    filter_seconds: f64,
+    write_seconds: f64,
+    rows_processed: usize,
+    rows_per_second: f64,
+    categories: usize,
+    bytes_written: u64,
+}
+
+impl RunStats {
+    /// Collect the metrics of the split of run `run_id` that took `total` overall. The
+    /// written bytes are the size of the output files less what the appended ones held
+    /// before the run
+    pub(crate) fn from_summary(
+        summary: &SplitSummary,
+        total: Duration,
+        run_id: &str,
+    ) -> Result<Self, AppError> {
+        let mut output_bytes: u64 = 0;
+        for path in summary.created.iter().chain(&summary.appended) {
+            output_bytes += fs::metadata(path)?.len();
+        }
+        let total_seconds: f64 = total.as_secs_f64();
+        Ok(RunStats {
+            run_id: run_id.to_string(),
+            total_seconds,
+            read_seconds: summary.read_time.as_secs_f64(),
+            filter_seconds: summary.filter_time.as_secs_f64(),
+            write_seconds: summary.write_time.as_secs_f64(),
+            rows_processed: summary.rows_processed,
+            rows_per_second: if total_seconds > 0.0 {
+                summary.rows_processed as f64 / total_seconds
+            } else {
+                0.0
+            },
+            categories: summary.created.len() + summary.appended.len(),
+            bytes_written: output_bytes.saturating_sub(summary.existing_bytes),
+        })
+    }
+
+    /// Format the metrics as a JSON object, one key per line
+    pub(crate) fn to_json(&self) -> String {
+        format!(
+            "{{\n  \"run_id\": {},\n  \"total_seconds\": {:.6},\n  \"read_seconds\": {:.6},\n  \"filter_seconds\": {:.6},\n  \"write_seconds\": {:.6},\n  \"rows_processed\": {},\n  \"rows_per_second\": {:.1},\n  \"categories\": {},\n  \"bytes_written\": {}\n}}\n",
+            Value::from(self.run_id.as_str()),
+            self.total_seconds,
+            self.read_seconds,
+            self.filter_seconds,
+            self.write_seconds,
+            self.rows_processed,
+            self.rows_per_second,
+            self.categories,
+            self.bytes_written
+        )
+    }
+
+    /// Write the metrics to `path`, replacing the file of a previous run
+    pub(crate) fn write(&self, path: &Path) -> Result<(), AppError> {
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_filtering::split_file_by_category;
+    use crate::delimiter::Delimiter;
+    use crate::record_context::RecordProcessingContext;
+    use crate::test_context::TestContext;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_run_stats() {
+        let mut context = TestContext::new();
+        let split_context = RecordProcessingContext {
+            output_dir: context.add_dir(PathBuf::from("assets/tmp/run_stats")),
+            ..Default::default()
+        };
+
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let stats =
+            RunStats::from_summary(&summary, Duration::from_millis(10), "nightly-42").unwrap();
+        let json: String = stats.to_json();
+
+        for key in [
+            "run_id",
+            "total_seconds",
+            "read_seconds",
+            "filter_seconds",
+            "write_seconds",
+            "rows_processed",
+            "rows_per_second",
+            "categories",
+            "bytes_written",
+        ] {
+            assert!(json.contains(&format!("\"{}\": ", key)), "missing {}", key);
+        }
+        assert!(json.contains("\"run_id\": \"nightly-42\","));
+        assert!(json.contains("\"rows_processed\": 12,"));
+        assert!(json.contains("\"rows_per_second\": 1200.0,"));
+        assert!(json.contains("\"categories\": 4,"));
+        assert!(stats.bytes_written > 0);
+    }
+}
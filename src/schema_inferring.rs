@@ -0,0 +1,273 @@
+use crate::app_error::AppError;
+use crate::data_loading::{read_file, ReaderConfig};
+use crate::run_date::RunDate;
+use csv::{Reader, StringRecord};
+use serde_json::{json, Value};
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Records sampled to guess the column types
+const SAMPLE_RECORDS: usize = 1_000;
+
+/// Type guessed for a column from its sampled values
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum ColumnType {
+    Bool,
+    Int,
+    Float,
+    Date,
+    Text,
+}
+
+/// Column of a schema, nullable when an empty value was sampled
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct ColumnSchema {
+    pub(crate) name: String,
+    pub(crate) column_type: ColumnType,
+    pub(crate) nullable: bool,
+}
+
+/// Types of the columns of the first sampled records
+#[derive(Debug, Default, Clone)]
+pub(crate) struct TypeSampler {
+    types: Vec<Option<ColumnType>>,
+    nullable: Vec<bool>,
+    sampled: usize,
+}
+
+/// SQL flavor of the `--print-schema-sql` type names and identifier quotes
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum SqlDialect {
+    #[default]
+    Postgres,
+    Mysql,
+    Sqlite,
+}
+
+impl ColumnType {
+    /// Narrowest type of a single non empty value
+    fn of(value: &str) -> Self {
+        if value.parse::<i64>().is_ok() {
+            ColumnType::Int
+        } else if value.parse::<f64>().is_ok() {
+            ColumnType::Float
+        } else if matches!(value.to_ascii_lowercase().as_str(), "true" | "false") {
+            ColumnType::Bool
+        } else if RunDate::from_str(value).is_ok() {
+            ColumnType::Date
+        } else {
+            ColumnType::Text
+        }
+    }
+
+    /// Type holding the values of both types, integers widening to floats and anything
+    /// else mixed falling back to text
+    fn widen(self, other: ColumnType) -> Self {
+        match (self, other) {
+            (left, right) if left == right => left,
+            (ColumnType::Int, ColumnType::Float) | (ColumnType::Float, ColumnType::Int) => {
+                ColumnType::Float
+            }
+            _ => ColumnType::Text,
+        }
+    }
+
+    /// Name of the type in a `.schema.json` file
+    fn json_name(self) -> &'static str {
+        match self {
+            ColumnType::Bool => "bool",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::Text => "text",
+        }
+    }
+
+    /// Name of the type in a SQL dialect
+    fn sql_name(self, dialect: SqlDialect) -> &'static str {
+        match (self, dialect) {
+            (ColumnType::Bool, SqlDialect::Sqlite) => "INTEGER",
+            (ColumnType::Bool, _) => "BOOLEAN",
+            (ColumnType::Int, SqlDialect::Sqlite) => "INTEGER",
+            (ColumnType::Int, _) => "BIGINT",
+            (ColumnType::Float, SqlDialect::Postgres) => "DOUBLE PRECISION",
+            (ColumnType::Float, SqlDialect::Mysql) => "DOUBLE",
+            (ColumnType::Float, SqlDialect::Sqlite) => "REAL",
+            (ColumnType::Date, SqlDialect::Sqlite) => "TEXT",
+            (ColumnType::Date, _) => "DATE",
+            (ColumnType::Text, _) => "TEXT",
+        }
+    }
+}
+
+impl TypeSampler {
+    /// Widen the column types with the values of a record, ignored once enough records
+    /// were sampled. Empty values don't take part in the type
+    pub(crate) fn sample(&mut self, record: &StringRecord) {
+        if self.sampled == SAMPLE_RECORDS {
+            return;
+        }
+        self.sampled += 1;
+        if self.types.len() < record.len() {
+            self.types.resize(record.len(), None);
+            self.nullable.resize(record.len(), false);
+        }
+        for (idx, value) in record.iter().enumerate() {
+            let value: &str = value.trim();
+            if value.is_empty() {
+                self.nullable[idx] = true;
+                continue;
+            }
+            let value_type: ColumnType = ColumnType::of(value);
+            self.types[idx] =
+                Some(self.types[idx].map_or(value_type, |current| current.widen(value_type)));
+        }
+    }
+
+    /// Schema of the headers, a column without any value being text
+    pub(crate) fn columns(&self, headers: &StringRecord) -> Vec<ColumnSchema> {
+        headers
+            .iter()
+            .enumerate()
+            .map(|(idx, header)| ColumnSchema {
+                name: header.to_string(),
+                column_type: self
+                    .types
+                    .get(idx)
+                    .copied()
+                    .flatten()
+                    .unwrap_or(ColumnType::Text),
+                nullable: self.nullable.get(idx).copied().unwrap_or(true),
+            })
+            .collect()
+    }
+}
+
+impl SqlDialect {
+    /// Quote an identifier, doubling the quotes it holds
+    fn quote(self, identifier: &str) -> String {
+        match self {
+            SqlDialect::Mysql => format!("`{}`", identifier.replace('`', "``")),
+            SqlDialect::Postgres | SqlDialect::Sqlite => {
+                format!("\"{}\"", identifier.replace('"', "\"\""))
+            }
+        }
+    }
+}
+
+impl FromStr for SqlDialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" => Ok(SqlDialect::Postgres),
+            "mysql" => Ok(SqlDialect::Mysql),
+            "sqlite" => Ok(SqlDialect::Sqlite),
+            _ => Err(format!(
+                "Invalid SQL dialect {}, expected postgres, mysql or sqlite",
+                s
+            )),
+        }
+    }
+}
+
+/// Guess the type of each column from the first records of the input
+pub(crate) fn infer_column_types(
+    path: &Path,
+    reader_config: &ReaderConfig,
+) -> Result<Vec<ColumnSchema>, AppError> {
+    let mut reader: Reader<Box<dyn Read>> = read_file(path, reader_config)?;
+    let headers: StringRecord = reader.headers()?.clone();
+    let mut sampler: TypeSampler = TypeSampler::default();
+    for result in reader.records().take(SAMPLE_RECORDS) {
+        sampler.sample(&result?);
+    }
+    Ok(sampler.columns(&headers))
+}
+
+/// Format the columns as the `--emit-schema` JSON, a list of `name`, `type` and
+/// `nullable` objects
+pub(crate) fn schema_json(columns: &[ColumnSchema]) -> String {
+    let columns: Vec<Value> = columns
+        .iter()
+        .map(|column| {
+            json!({
+                "name": column.name,
+                "type": column.column_type.json_name(),
+                "nullable": column.nullable,
+            })
+        })
+        .collect();
+    Value::Array(columns).to_string()
+}
+
+/// Build the `CREATE TABLE` statement of the columns
+pub(crate) fn create_table_sql(
+    table: &str,
+    columns: &[ColumnSchema],
+    dialect: SqlDialect,
+) -> String {
+    let definitions: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            format!(
+                "  {} {}",
+                dialect.quote(&column.name),
+                column.column_type.sql_name(dialect)
+            )
+        })
+        .collect();
+    format!(
+        "CREATE TABLE {} (\n{}\n);",
+        dialect.quote(table),
+        definitions.join(",\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delimiter::Delimiter;
+
+    #[test]
+    fn test_create_table_sql() {
+        let columns = infer_column_types(
+            Path::new("assets/city.csv"),
+            &ReaderConfig::new(&Delimiter::Comma),
+        )
+        .unwrap();
+
+        assert_eq!(
+            create_table_sql("city", &columns, SqlDialect::Postgres),
+            "CREATE TABLE \"city\" (\n  \"City\" TEXT,\n  \"State\" TEXT,\n  \"Population\" BIGINT,\n  \"Latitude\" DOUBLE PRECISION,\n  \"Longitude\" DOUBLE PRECISION\n);"
+        );
+        assert!(
+            create_table_sql("city", &columns, SqlDialect::Mysql).contains("`Population` BIGINT")
+        );
+        assert!(create_table_sql("city", &columns, SqlDialect::Sqlite)
+            .contains("\"Population\" INTEGER"));
+    }
+
+    #[test]
+    fn test_schema_json() {
+        let mut sampler = TypeSampler::default();
+        sampler.sample(&StringRecord::from(vec!["Kenai", "7610"]));
+        sampler.sample(&StringRecord::from(vec!["Oakman", ""]));
+        let columns = sampler.columns(&StringRecord::from(vec!["City", "Population"]));
+
+        assert_eq!(
+            schema_json(&columns),
+            r#"[{"name":"City","type":"text","nullable":false},{"name":"Population","type":"int","nullable":true}]"#
+        );
+    }
+
+    #[test]
+    fn test_column_type() {
+        assert_eq!(ColumnType::of("2024-06-01"), ColumnType::Date);
+        assert_eq!(ColumnType::of("TRUE"), ColumnType::Bool);
+        assert_eq!(ColumnType::of("1.5"), ColumnType::Float);
+        assert_eq!(ColumnType::Int.widen(ColumnType::Float), ColumnType::Float);
+        assert_eq!(ColumnType::Int.widen(ColumnType::Date), ColumnType::Text);
+    }
+}
@@ -0,0 +1,257 @@
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Counters reported once a file has been split
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct SplitSummary {
+    pub(crate) duplicates_removed: usize,
+    /// Records dropped by `--dedupe-global` as a repeat of one kept in any category
+    pub(crate) global_duplicates_removed: usize,
+    pub(crate) oversized_records_skipped: usize,
+    /// Sorted segments written to disk because a category buffer hit `--spill-threshold`
+    pub(crate) spilled_segments: usize,
+    /// Blank lines dropped by `--skip-empty-lines`
+    pub(crate) empty_lines_skipped: usize,
+    /// Records dropped by `--on-invalid-category skip`
+    pub(crate) invalid_category_records_skipped: usize,
+    /// Records of the null category dropped by `--null-category skip`
+    pub(crate) null_category_records_skipped: usize,
+    /// The split stopped early because `--max-runtime` was reached
+    pub(crate) timed_out: bool,
+    /// Output files that didn't exist before the run
+    pub(crate) created: Vec<String>,
+    /// Output files that already existed and got records appended
+    pub(crate) appended: Vec<String>,
+    /// Categories written under another file name, transliterated to ASCII or truncated,
+    /// as (original, file name)
+    pub(crate) renamed: Vec<(String, String)>,
+    /// Records written to each category, after the duplicates
+    pub(crate) category_rows: HashMap<String, usize>,
+    /// Records read and handed to the split, after `--rows` and the skipped records
+    pub(crate) rows_processed: usize,
+    /// Size of the appended files before the run
+    pub(crate) existing_bytes: u64,
+    /// Time spent reading and parsing the input
+    pub(crate) read_time: Duration,
+    /// Time spent filtering and categorizing the records
+    pub(crate) filter_time: Duration,
+    /// Time spent buffering and writing the records, overlapping the reading with
+    /// `--writer-thread`
+    pub(crate) write_time: Duration,
+}
+
+/// How the summary is printed to stdout with `--summary-format`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) enum SummaryFormat {
+    /// Aligned table of the counters, then of the records per category
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for SummaryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(format!(
+                "Invalid summary format {}, expected text or json",
+                s
+            )),
+        }
+    }
+}
+
+impl SplitSummary {
+    /// Records skipped because they couldn't be split as is, counted by `--max-errors`
+    pub(crate) fn skipped_records(&self) -> usize {
+        self.oversized_records_skipped + self.invalid_category_records_skipped
+    }
+
+    /// Add the counters of another summary, e.g. of a single chunk
+    pub(crate) fn merge(&mut self, other: SplitSummary) {
+        self.duplicates_removed += other.duplicates_removed;
+        self.global_duplicates_removed += other.global_duplicates_removed;
+        self.oversized_records_skipped += other.oversized_records_skipped;
+        self.spilled_segments += other.spilled_segments;
+        self.invalid_category_records_skipped += other.invalid_category_records_skipped;
+        self.null_category_records_skipped += other.null_category_records_skipped;
+        self.empty_lines_skipped += other.empty_lines_skipped;
+        self.timed_out |= other.timed_out;
+        self.created.extend(other.created);
+        self.appended.extend(other.appended);
+        self.renamed.extend(other.renamed);
+        for (category, rows) in other.category_rows {
+            *self.category_rows.entry(category).or_default() += rows;
+        }
+        self.rows_processed += other.rows_processed;
+        self.existing_bytes += other.existing_bytes;
+        self.read_time += other.read_time;
+        self.filter_time += other.filter_time;
+        self.write_time += other.write_time;
+    }
+
+    /// Format the summary for `--summary-format`, the categories sorted by name
+    pub(crate) fn format(&self, format: SummaryFormat) -> String {
+        match format {
+            SummaryFormat::Text => self.to_table(),
+            SummaryFormat::Json => self.to_json(),
+        }
+    }
+
+    fn category_rows(&self) -> BTreeMap<&str, usize> {
+        self.category_rows
+            .iter()
+            .map(|(category, &rows)| (category.as_str(), rows))
+            .collect()
+    }
+
+    fn to_table(&self) -> String {
+        let mut rows: Vec<(String, String)> = vec![
+            (
+                String::from("Files created"),
+                self.created.len().to_string(),
+            ),
+            (
+                String::from("Files appended"),
+                self.appended.len().to_string(),
+            ),
+            (
+                String::from("Records processed"),
+                self.rows_processed.to_string(),
+            ),
+            (
+                String::from("Duplicates removed"),
+                self.duplicates_removed.to_string(),
+            ),
+            (
+                String::from("Global duplicates removed"),
+                self.global_duplicates_removed.to_string(),
+            ),
+            (
+                String::from("Oversized records skipped"),
+                self.oversized_records_skipped.to_string(),
+            ),
+            (
+                String::from("Empty lines skipped"),
+                self.empty_lines_skipped.to_string(),
+            ),
+            (
+                String::from("Invalid categories skipped"),
+                self.invalid_category_records_skipped.to_string(),
+            ),
+            (
+                String::from("Null categories skipped"),
+                self.null_category_records_skipped.to_string(),
+            ),
+            (
+                String::from("Spilled segments"),
+                self.spilled_segments.to_string(),
+            ),
+            (String::from("Timed out"), self.timed_out.to_string()),
+        ];
+        for (category, records) in self.category_rows() {
+            rows.push((format!("Records in {}", category), records.to_string()));
+        }
+        let label_width: usize = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let value_width: usize = rows.iter().map(|(_, value)| value.len()).max().unwrap_or(0);
+        rows.iter()
+            .map(|(label, value)| {
+                format!(
+                    "{:<label_width$}  {:>value_width$}\n",
+                    label,
+                    value,
+                    label_width = label_width,
+                    value_width = value_width
+                )
+            })
+            .collect()
+    }
+
+    fn to_json(&self) -> String {
+        let renamed: Vec<Value> = self
+            .renamed
+            .iter()
+            .map(|(category, file_category)| json!({"category": category, "file": file_category}))
+            .collect();
+        let summary: Value = json!({
+            "created": self.created,
+            "appended": self.appended,
+            "renamed": renamed,
+            "rows_processed": self.rows_processed,
+            "duplicates_removed": self.duplicates_removed,
+            "global_duplicates_removed": self.global_duplicates_removed,
+            "oversized_records_skipped": self.oversized_records_skipped,
+            "empty_lines_skipped": self.empty_lines_skipped,
+            "invalid_category_records_skipped": self.invalid_category_records_skipped,
+            "null_category_records_skipped": self.null_category_records_skipped,
+            "spilled_segments": self.spilled_segments,
+            "timed_out": self.timed_out,
+            "category_rows": self.category_rows(),
+        });
+        format!("{}\n", summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_filtering::split_file_by_category;
+    use crate::delimiter::Delimiter;
+    use crate::record_context::RecordProcessingContext;
+    use crate::test_context::TestContext;
+    use std::path::{Path, PathBuf};
+
+    fn split_city(output_dir: &Path) -> SplitSummary {
+        let mut context = TestContext::new();
+        let split_context = RecordProcessingContext {
+            output_dir: context.add_dir(output_dir.to_path_buf()),
+            ..Default::default()
+        };
+        let summary = split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        summary
+    }
+
+    #[test]
+    fn test_summary_json() {
+        let summary = split_city(&PathBuf::from("assets/tmp/summary_json"));
+        let json: Value = serde_json::from_str(&summary.format(SummaryFormat::Json)).unwrap();
+
+        assert_eq!(json["created"].as_array().unwrap().len(), 4);
+        assert_eq!(json["appended"], json!([]));
+        assert_eq!(json["rows_processed"], 12);
+        assert_eq!(json["timed_out"], false);
+        let category_rows: u64 = json["category_rows"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|rows| rows.as_u64().unwrap())
+            .sum();
+        assert_eq!(category_rows, 12);
+    }
+
+    #[test]
+    fn test_summary_table() {
+        let summary = SplitSummary {
+            rows_processed: 120,
+            category_rows: HashMap::from([(String::from("NY"), 100), (String::from("AK"), 20)]),
+            ..Default::default()
+        };
+        let table: String = summary.format(SummaryFormat::Text);
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines[2], "Records processed             120");
+        assert_eq!(lines[11], "Records in AK                  20");
+        assert_eq!(lines[12], "Records in NY                 100");
+    }
+}
@@ -0,0 +1,60 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Temporary directory the categories of a `--tar` or `--xlsx` run are written to before
+/// being packed, removed once dropped, also when the run fails
+pub(crate) struct StagingDir {
+    path: PathBuf,
+}
+
+impl StagingDir {
+    /// Create an empty staging directory for the `kind` of output. A directory left by an
+    /// earlier run that got the same process id is removed first, so its files aren't
+    /// packed with the new ones
+    pub(crate) fn create(kind: &str) -> io::Result<Self> {
+        let path: PathBuf =
+            env::temp_dir().join(format!("csv-splitter-{}-{}", process::id(), kind));
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::create_dir_all(&path)?;
+        Ok(StagingDir { path })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        // Packing the files already removes the directory
+        if self.path.exists() {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_staging_dir() {
+        let stale_dir: StagingDir = StagingDir::create("staging-test").unwrap();
+        let stale_path: PathBuf = stale_dir.path().to_path_buf();
+        fs::write(stale_path.join("stale.csv"), "City\n").unwrap();
+        // A killed run doesn't remove its directory
+        std::mem::forget(stale_dir);
+
+        let staging_dir: StagingDir = StagingDir::create("staging-test").unwrap();
+
+        assert_eq!(staging_dir.path(), stale_path);
+        assert_eq!(fs::read_dir(staging_dir.path()).unwrap().count(), 0);
+        drop(staging_dir);
+        assert!(!stale_path.exists());
+    }
+}
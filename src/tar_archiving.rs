@@ -0,0 +1,86 @@
+use crate::app_error::AppError;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tar::Builder;
+
+/// Write every file under `dir` to the tar archive at `tar_path`, named after its path
+/// relative to `dir`, then remove `dir`. Returns the number of entries
+pub(crate) fn archive_directory(dir: &Path, tar_path: &Path) -> Result<usize, AppError> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    collect_files(dir, &mut files)?;
+    // Sorted entries keep the archive the same from one run to the next
+    files.sort();
+
+    let mut builder: Builder<BufWriter<File>> =
+        Builder::new(BufWriter::new(File::create(tar_path)?));
+    for file in &files {
+        let entry_name: &Path = file.strip_prefix(dir).unwrap_or(file);
+        builder.append_path_with_name(file, entry_name)?;
+    }
+    builder.into_inner()?.flush()?;
+    fs::remove_dir_all(dir)?;
+    Ok(files.len())
+}
+
+/// Collect the files of a directory and its subdirectories
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), AppError> {
+    for entry in fs::read_dir(dir)? {
+        let path: PathBuf = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_filtering::split_file_by_category;
+    use crate::delimiter::Delimiter;
+    use crate::record_context::RecordProcessingContext;
+    use crate::test_context::TestContext;
+    use std::io::Read;
+    use tar::Archive;
+
+    #[test]
+    fn test_archive_directory() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/tar"));
+        let tar_path = context.add_file(PathBuf::from("assets/tmp/city.tar"));
+
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            ..Default::default()
+        };
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+        let expected_ak: String = fs::read_to_string(output_dir.join("AK.csv")).unwrap();
+
+        let entries: usize = archive_directory(&output_dir, &tar_path).unwrap();
+
+        assert_eq!(entries, 4);
+        assert!(!output_dir.exists());
+        let mut archive = Archive::new(File::open(&tar_path).unwrap());
+        let mut names: Vec<String> = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name: String = entry.path().unwrap().display().to_string();
+            if name == "AK.csv" {
+                let mut contents: String = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                assert_eq!(contents, expected_ak);
+            }
+            names.push(name);
+        }
+        assert_eq!(names, vec!["AK.csv", "AL.csv", "CA.csv", "NY.csv"]);
+    }
+}
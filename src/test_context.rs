@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::PathBuf;
+
+/// Files and directories a test writes, removed once it ends, also when it panics
+pub(crate) struct TestContext {
+    files: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+}
+
+impl TestContext {
+    pub(crate) fn new() -> Self {
+        TestContext {
+            files: Vec::new(),
+            dirs: Vec::new(),
+        }
+    }
+
+    /// Remove a file the test writes, its directory having to exist
+    pub(crate) fn add_file(&mut self, file_path: PathBuf) -> PathBuf {
+        self.files.push(file_path.clone());
+        file_path
+    }
+
+    /// Create a directory for the test, removed with its contents
+    pub(crate) fn add_dir(&mut self, dir: PathBuf) -> PathBuf {
+        fs::create_dir_all(&dir).unwrap();
+        self.dirs.push(dir.clone());
+        dir
+    }
+}
+
+impl Drop for TestContext {
+    fn drop(&mut self) {
+        for file in &self.files {
+            if file.exists() {
+                fs::remove_file(file).unwrap();
+            }
+        }
+        for dir in &self.dirs {
+            if dir.exists() {
+                fs::remove_dir_all(dir).unwrap();
+            }
+        }
+    }
+}
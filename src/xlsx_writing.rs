@@ -0,0 +1,190 @@
+use crate::app_error::AppError;
+use csv::{ByteRecord, Reader, ReaderBuilder};
+use rust_xlsxwriter::{ColNum, RowNum, Workbook, Worksheet, XlsxError};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Longest sheet name Excel accepts, in characters
+const MAX_SHEET_NAME_CHARS: usize = 31;
+/// Most sheets of a `--xlsx` workbook, the most Excel creates a new workbook with
+const MAX_SHEETS: usize = 255;
+/// Characters Excel doesn't accept in a sheet name
+const RESERVED_SHEET_CHARS: [char; 7] = ['*', '?', ':', '[', ']', '\\', '/'];
+/// Sheet name Excel keeps for the change history of a shared workbook
+const RESERVED_SHEET_NAME: &str = "History";
+
+/// Name of the sheet of a category as Excel accepts it: the reserved characters replaced
+/// with `_`, the apostrophes around it dropped and cut to 31 characters
+pub(crate) fn sheet_name(category: &str) -> String {
+    let name: String = category
+        .chars()
+        .map(|c| {
+            if RESERVED_SHEET_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name: String = name
+        .trim_start_matches('\'')
+        .chars()
+        .take(MAX_SHEET_NAME_CHARS)
+        .collect();
+    match name.trim_end_matches('\'') {
+        "" => String::from("_"),
+        name if name.eq_ignore_ascii_case(RESERVED_SHEET_NAME) => format!("{}_", name),
+        name => name.to_string(),
+    }
+}
+
+/// Write the category files of the staging directory as the sheets of the workbook at
+/// `xlsx_path`, in the order of the categories and with the header row frozen when the
+/// files have one, then remove the directory. Returns the number of sheets
+pub(crate) fn write_workbook(
+    dir: &Path,
+    xlsx_path: &Path,
+    delimiter: u8,
+    has_headers: bool,
+) -> Result<usize, AppError> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    files.retain(|path| path.extension().is_some_and(|extension| extension == "csv"));
+    files.sort();
+    if files.len() > MAX_SHEETS {
+        return Err(AppError::TooManySheets {
+            sheets: files.len(),
+            max_sheets: MAX_SHEETS,
+        });
+    }
+
+    let mut workbook: Workbook = Workbook::new();
+    // Excel compares the sheet names ignoring the case
+    let mut sheet_categories: HashMap<String, String> = HashMap::new();
+    for file in &files {
+        let category: String = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name: String = sheet_name(&category);
+        match sheet_categories.entry(name.to_lowercase()) {
+            Entry::Occupied(first) => {
+                return Err(AppError::SheetNameCollision {
+                    first: format!("{:?}", first.get()),
+                    second: format!("{:?}", category),
+                    sheet: name,
+                });
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(category);
+            }
+        }
+        let worksheet: &mut Worksheet = workbook.add_worksheet_with_constant_memory();
+        worksheet.set_name(name).map_err(xlsx_error)?;
+        if has_headers {
+            worksheet.set_freeze_panes(1, 0).map_err(xlsx_error)?;
+        }
+        write_sheet(worksheet, file, delimiter)?;
+    }
+    workbook.save(xlsx_path).map_err(xlsx_error)?;
+    fs::remove_dir_all(dir)?;
+    Ok(files.len())
+}
+
+/// Write the records of a category file to its sheet as text cells, row by row as a
+/// sheet of constant memory needs them
+fn write_sheet(worksheet: &mut Worksheet, file: &Path, delimiter: u8) -> Result<(), AppError> {
+    let mut reader: Reader<File> = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_path(file)?;
+    for (row, result) in reader.byte_records().enumerate() {
+        let record: ByteRecord = result?;
+        let row: RowNum = RowNum::try_from(row).map_err(io::Error::other)?;
+        for (col, field) in record.iter().enumerate() {
+            let col: ColNum = ColNum::try_from(col).map_err(io::Error::other)?;
+            worksheet
+                .write_string(row, col, String::from_utf8_lossy(field))
+                .map_err(xlsx_error)?;
+        }
+    }
+    Ok(())
+}
+
+fn xlsx_error(error: XlsxError) -> AppError {
+    AppError::Io(io::Error::other(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_filtering::split_file_by_category;
+    use crate::delimiter::Delimiter;
+    use crate::record_context::RecordProcessingContext;
+    use crate::test_context::TestContext;
+    use calamine::{open_workbook, Reader as _, Xlsx};
+    use std::io::BufReader;
+
+    #[test]
+    fn test_write_workbook() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/xlsx"));
+        let xlsx_path = context.add_file(PathBuf::from("assets/tmp/city.xlsx"));
+        let split_context = RecordProcessingContext {
+            output_dir: output_dir.clone(),
+            ..Default::default()
+        };
+        split_file_by_category(
+            Path::new("assets/city.csv"),
+            "State",
+            &Delimiter::Comma,
+            &split_context,
+        )
+        .unwrap();
+
+        let sheets: usize = write_workbook(&output_dir, &xlsx_path, Delimiter::PIPE, true).unwrap();
+
+        assert_eq!(sheets, 4);
+        assert!(!output_dir.exists());
+        let mut workbook: Xlsx<BufReader<File>> = open_workbook(&xlsx_path).unwrap();
+        assert_eq!(workbook.sheet_names(), vec!["AK", "AL", "CA", "NY"]);
+        let ny_range = workbook.worksheet_range("NY").unwrap();
+        assert_eq!(ny_range.height(), 4);
+        assert_eq!(ny_range.get((0, 0)).unwrap().to_string(), "City");
+    }
+
+    #[test]
+    fn test_sheet_name_collision() {
+        let mut context = TestContext::new();
+        let output_dir = context.add_dir(PathBuf::from("assets/tmp/xlsx_collision"));
+        fs::write(output_dir.join("A:B.csv"), "City\nKenai\n").unwrap();
+        fs::write(output_dir.join("a_b.csv"), "City\nOakman\n").unwrap();
+
+        let result = write_workbook(
+            &output_dir,
+            Path::new("assets/tmp/collision.xlsx"),
+            b'|',
+            true,
+        );
+
+        assert!(matches!(
+            result,
+            Err(AppError::SheetNameCollision { sheet, .. }) if sheet == "a_b"
+        ));
+    }
+
+    #[test]
+    fn test_sheet_name() {
+        assert_eq!(sheet_name("NY"), "NY");
+        assert_eq!(sheet_name("US/NY: [north]?"), "US_NY_ _north__");
+        assert_eq!(sheet_name("'quoted'"), "quoted");
+        assert_eq!(sheet_name(&"x".repeat(40)).len(), 31);
+        assert_eq!(sheet_name("history"), "history_");
+        assert_eq!(sheet_name("''"), "_");
+    }
+}